@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS calls (
+	call_sid TEXT NOT NULL PRIMARY KEY,
+	data JSONB NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+	updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE sponsors DROP COLUMN judge_abandoned;
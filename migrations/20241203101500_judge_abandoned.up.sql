@@ -0,0 +1 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS judge_abandoned BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,6 @@
+DROP TABLE call_status_events;
+ALTER TABLE attempts DROP COLUMN ended_at;
+ALTER TABLE attempts DROP COLUMN end_reason;
+ALTER TABLE attempts DROP COLUMN call_duration;
+ALTER TABLE attempts DROP COLUMN call_status;
+ALTER TABLE attempts DROP COLUMN sponsor_id;
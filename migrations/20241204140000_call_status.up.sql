@@ -0,0 +1,16 @@
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS sponsor_id INT REFERENCES sponsors(id);
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS call_status TEXT;
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS call_duration INT;
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS end_reason TEXT;
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS ended_at TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE IF NOT EXISTS call_status_events (
+	id SERIAL PRIMARY KEY,
+	call_sid TEXT NOT NULL,
+	status TEXT NOT NULL,
+	call_duration INT,
+	sip_response_code INT,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS call_status_events_call_sid_idx ON call_status_events (call_sid);
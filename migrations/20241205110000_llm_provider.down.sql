@@ -0,0 +1 @@
+ALTER TABLE sponsors DROP COLUMN llm_provider;
@@ -0,0 +1 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS llm_provider TEXT NOT NULL DEFAULT 'openai';
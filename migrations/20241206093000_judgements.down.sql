@@ -0,0 +1,4 @@
+DROP TABLE judgements;
+ALTER TABLE sponsors DROP COLUMN judge_quorum;
+ALTER TABLE sponsors DROP COLUMN judge_count;
+ALTER TABLE sponsors DROP COLUMN judging_mode;
@@ -0,0 +1,16 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS judging_mode TEXT NOT NULL DEFAULT 'single';
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS judge_count INT NOT NULL DEFAULT 1;
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS judge_quorum INT NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS judgements (
+	id SERIAL PRIMARY KEY,
+	call_sid TEXT NOT NULL,
+	judge_index INT NOT NULL,
+	temperature REAL,
+	won_prize BOOLEAN NOT NULL,
+	rating INT NOT NULL,
+	explanation TEXT NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS judgements_call_sid_idx ON judgements (call_sid);
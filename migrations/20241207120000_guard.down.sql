@@ -0,0 +1,3 @@
+ALTER TABLE attempts DROP COLUMN guard_verdict;
+ALTER TABLE attempts DROP COLUMN guard_flagged;
+ALTER TABLE sponsors DROP COLUMN guard_action;
@@ -0,0 +1,4 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS guard_action TEXT NOT NULL DEFAULT 'force_loss';
+
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS guard_flagged BOOLEAN;
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS guard_verdict TEXT;
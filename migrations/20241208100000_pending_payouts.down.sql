@@ -0,0 +1,2 @@
+DROP TABLE pending_payouts;
+ALTER TABLE sponsors DROP COLUMN payout_approval_threshold;
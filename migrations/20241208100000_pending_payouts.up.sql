@@ -0,0 +1,20 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS payout_approval_threshold BIGINT;
+
+CREATE TABLE IF NOT EXISTS pending_payouts (
+	id SERIAL PRIMARY KEY,
+	call_sid TEXT NOT NULL UNIQUE,
+	sponsor_id INT NOT NULL,
+	phone_number TEXT NOT NULL,
+	caller_name TEXT NOT NULL,
+	reward_tokens BIGINT NOT NULL,
+	video_url TEXT NOT NULL,
+	transcript TEXT NOT NULL,
+	explanation TEXT NOT NULL,
+	reason TEXT NOT NULL,
+	status TEXT NOT NULL DEFAULT 'pending',
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+	deadline TIMESTAMP WITH TIME ZONE NOT NULL,
+	decided_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS pending_payouts_status_idx ON pending_payouts (status);
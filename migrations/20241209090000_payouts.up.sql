@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS payouts (
+	id SERIAL PRIMARY KEY,
+	attempt_id INT,
+	call_sid TEXT NOT NULL UNIQUE,
+	sponsor_id INT NOT NULL,
+	receiver_pubkey TEXT NOT NULL,
+	amount BIGINT NOT NULL,
+	status TEXT NOT NULL DEFAULT 'pending',
+	signature TEXT,
+	last_valid_block_height BIGINT,
+	retries INT NOT NULL DEFAULT 0,
+	last_error TEXT,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+	updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS payouts_status_idx ON payouts (status);
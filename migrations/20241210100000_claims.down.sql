@@ -0,0 +1,6 @@
+ALTER TABLE payouts ALTER COLUMN receiver_pubkey SET NOT NULL;
+
+ALTER TABLE winners DROP COLUMN wallet;
+ALTER TABLE winners DROP COLUMN claimed_at;
+ALTER TABLE winners DROP COLUMN expires_at;
+ALTER TABLE winners DROP COLUMN call_sid;
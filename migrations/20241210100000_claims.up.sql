@@ -0,0 +1,6 @@
+ALTER TABLE winners ADD COLUMN IF NOT EXISTS call_sid TEXT UNIQUE;
+ALTER TABLE winners ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE winners ADD COLUMN IF NOT EXISTS claimed_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE winners ADD COLUMN IF NOT EXISTS wallet TEXT;
+
+ALTER TABLE payouts ALTER COLUMN receiver_pubkey DROP NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE attempts DROP COLUMN prize_sweep_signature;
+ALTER TABLE attempts DROP COLUMN prize_swept_tokens;
+ALTER TABLE attempts DROP COLUMN prize_swept_at;
@@ -0,0 +1,3 @@
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS prize_swept_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS prize_swept_tokens BIGINT;
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS prize_sweep_signature TEXT;
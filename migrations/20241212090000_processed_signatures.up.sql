@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS processed_signatures (
+	signature TEXT PRIMARY KEY,
+	kind TEXT NOT NULL,
+	sponsor_id INT,
+	amount BIGINT NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS balance_reconciliations (
+	sponsor_id INT PRIMARY KEY,
+	onchain_tokens BIGINT NOT NULL,
+	available_tokens BIGINT NOT NULL,
+	owed_tokens BIGINT NOT NULL,
+	in_flight_tokens BIGINT NOT NULL,
+	difference BIGINT NOT NULL,
+	mismatch BOOLEAN NOT NULL,
+	deactivated BOOLEAN NOT NULL DEFAULT false,
+	checked_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
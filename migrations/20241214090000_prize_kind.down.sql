@@ -0,0 +1,2 @@
+ALTER TABLE sponsors DROP COLUMN token_decimals;
+ALTER TABLE sponsors DROP COLUMN prize_kind;
@@ -0,0 +1,2 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS prize_kind TEXT NOT NULL DEFAULT 'spl';
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS token_decimals INT;
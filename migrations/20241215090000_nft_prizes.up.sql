@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS nft_prizes (
+	call_sid TEXT PRIMARY KEY,
+	sponsor_id INT NOT NULL,
+	name TEXT NOT NULL,
+	symbol TEXT NOT NULL,
+	uri TEXT NOT NULL,
+	caller_name TEXT NOT NULL,
+	sponsor_name TEXT NOT NULL,
+	challenge TEXT NOT NULL,
+	rating INT,
+	video_url TEXT NOT NULL,
+	image_url TEXT NOT NULL,
+	mint TEXT,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS reward_tiers (
+	id SERIAL PRIMARY KEY,
+	sponsor_id INT NOT NULL,
+	min_rating INT NOT NULL,
+	reward_tokens BIGINT NOT NULL,
+	name TEXT NOT NULL DEFAULT '',
+	UNIQUE (sponsor_id, min_rating)
+);
@@ -0,0 +1,5 @@
+DROP TABLE jackpot_contributions;
+
+ALTER TABLE sponsors DROP COLUMN jackpot_tokens;
+ALTER TABLE sponsors DROP COLUMN jackpot_contribution;
+ALTER TABLE sponsors DROP COLUMN jackpot;
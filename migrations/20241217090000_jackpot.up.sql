@@ -0,0 +1,10 @@
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS jackpot BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS jackpot_contribution BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sponsors ADD COLUMN IF NOT EXISTS jackpot_tokens BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS jackpot_contributions (
+	call_sid TEXT PRIMARY KEY,
+	sponsor_id INT NOT NULL,
+	amount BIGINT NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
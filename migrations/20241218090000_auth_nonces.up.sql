@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS auth_nonces (
+	nonce TEXT PRIMARY KEY,
+	public_key TEXT NOT NULL,
+	domain TEXT NOT NULL,
+	issued_at TIMESTAMP WITH TIME ZONE NOT NULL,
+	expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+	used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS auth_nonces_expires_at_idx ON auth_nonces (expires_at);
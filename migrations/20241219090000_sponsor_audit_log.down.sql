@@ -0,0 +1 @@
+DROP TABLE sponsor_audit_log;
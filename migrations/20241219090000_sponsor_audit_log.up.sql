@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS sponsor_audit_log (
+	id SERIAL PRIMARY KEY,
+	sponsor_id INT NOT NULL,
+	actor TEXT NOT NULL,
+	changes JSONB NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS sponsor_audit_log_sponsor_id_idx ON sponsor_audit_log (sponsor_id);
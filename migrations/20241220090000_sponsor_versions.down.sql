@@ -0,0 +1,3 @@
+ALTER TABLE attempts DROP COLUMN sponsor_version;
+
+DROP TABLE sponsor_versions;
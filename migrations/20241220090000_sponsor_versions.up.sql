@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS sponsor_versions (
+	id SERIAL PRIMARY KEY,
+	sponsor_id INT NOT NULL,
+	version INT NOT NULL,
+	settings JSONB NOT NULL,
+	actor TEXT NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+	UNIQUE (sponsor_id, version)
+);
+
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS sponsor_version INT;
+
+-- The current settings of the existing sponsors are their first version
+INSERT INTO sponsor_versions (sponsor_id, version, settings, actor)
+SELECT
+	sponsors.id,
+	1,
+	(to_jsonb(sponsors) - 'id' - 'private_key' - 'public_key' - 'original_tokens' - 'available_tokens' - 'jackpot_tokens' - 'initial_funded')
+		|| jsonb_build_object('reward_tiers', COALESCE((
+			SELECT jsonb_agg(jsonb_build_object('min_rating', min_rating, 'reward_tokens', reward_tokens, 'name', name) ORDER BY min_rating)
+			FROM reward_tiers
+			WHERE reward_tiers.sponsor_id = sponsors.id
+		), '[]'::jsonb)),
+	'migration'
+FROM sponsors
+ON CONFLICT (sponsor_id, version) DO NOTHING;
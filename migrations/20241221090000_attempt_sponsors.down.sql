@@ -0,0 +1 @@
+-- The matched sponsors are kept, the attempts were made for them
@@ -0,0 +1,13 @@
+-- Attempts from before the sponsor id was recorded only know the token mint and name of their
+-- sponsor, they are only matched if no other sponsor has the same token mint and name
+UPDATE attempts
+SET sponsor_id = sponsors.id
+FROM sponsors
+WHERE attempts.sponsor_id IS NULL
+AND sponsors.token_mint = attempts.sponsor_token_mint
+AND sponsors.name = attempts.sponsor_name
+AND (
+	SELECT COUNT(*) FROM sponsors AS others
+	WHERE others.token_mint = attempts.sponsor_token_mint
+	AND others.name = attempts.sponsor_name
+) = 1;
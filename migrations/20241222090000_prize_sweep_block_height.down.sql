@@ -0,0 +1 @@
+ALTER TABLE attempts DROP COLUMN prize_sweep_last_valid_block_height;
@@ -0,0 +1 @@
+ALTER TABLE attempts ADD COLUMN IF NOT EXISTS prize_sweep_last_valid_block_height BIGINT;
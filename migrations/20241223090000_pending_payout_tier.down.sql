@@ -0,0 +1 @@
+ALTER TABLE pending_payouts DROP COLUMN reward_tier;
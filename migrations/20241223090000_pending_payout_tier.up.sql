@@ -0,0 +1 @@
+ALTER TABLE pending_payouts ADD COLUMN IF NOT EXISTS reward_tier TEXT NOT NULL DEFAULT '';
@@ -4,11 +4,11 @@ use axum::Extension;
 use crate::Database;
 use crate::StatusCode;
 use serde::{Serialize, Deserialize};
-use crate::secrets::Secrets;
+use crate::solana::service::SolanaService;
 use base64::{engine::general_purpose, Engine as _};
 use bincode;
 use solana_sdk::transaction::Transaction;
-use crate::solana::verify_deposit::verify_deposit;
+use crate::solana::verify::{verify_deposit, DEPOSIT_KIND};
 use crate::api::ResponseData;
 use crate::api::launchpad::ReturnSponsor;
 
@@ -20,63 +20,84 @@ pub struct ActivateSponsorArgs {
 }
 
 pub async fn activate_sponsor(
-    secrets: Extension<Secrets>,
+    Extension(solana): Extension<SolanaService>,
     Extension(database): Extension<Database>,
     Json(sponsor_args): Json<ActivateSponsorArgs>,
 ) -> impl IntoResponse {
 
     // Decode the base64-encoded transaction
-    let decoded_transaction = general_purpose::STANDARD
-        .decode(&sponsor_args.transaction)
-        .expect("Failed to decode transaction");
+    let Ok(decoded_transaction) = general_purpose::STANDARD.decode(&sponsor_args.transaction) else {
+        return (StatusCode::BAD_REQUEST, "Invalid transaction encoding").into_response();
+    };
 
     // Deserialize the transaction
-    let transaction: Transaction = bincode::deserialize(&decoded_transaction)
-        .expect("Failed to deserialize transaction");
-
-
-    let signature = verify_deposit(
-        &secrets,
-        sponsor_args.sponsor_public_key.clone(),
-        transaction
-    ).await
-    .unwrap();  
+    let Ok(transaction) = bincode::deserialize::<Transaction>(&decoded_transaction) else {
+        return (StatusCode::BAD_REQUEST, "Invalid transaction").into_response();
+    };
 
 
-    let sponsor = database.get_sponsor_by_public_key(sponsor_args.sponsor_public_key.clone()).await.unwrap();
+    let Ok(sponsor) = database.get_sponsor_by_public_key(sponsor_args.sponsor_public_key.clone()).await else {
+        return (StatusCode::NOT_FOUND, "Sponsor not found").into_response();
+    };
 
     if sponsor.initial_funded == true {
         return (StatusCode::BAD_REQUEST, "Initial was already funded").into_response();
     }
 
-    database.update_sponsor_to_active(sponsor_args.sponsor_public_key.clone()).await.unwrap();
-
-
-    let return_sponsor = ReturnSponsor {
-        id: sponsor.id,
-        name: sponsor.name,
-        user_id: sponsor.user_id,
-        active: sponsor.active,
-        background_url: sponsor.background_url,
-        public_key: sponsor.public_key,
-        token_mint: sponsor.token_mint,
-        original_tokens: sponsor.original_tokens,
-        available_tokens: sponsor.available_tokens,
-        reward_tokens: sponsor.reward_tokens,
-        challenge_text: sponsor.challenge_text,
-        challenge_time: sponsor.challenge_time,
-        system_instruction: sponsor.system_instruction,
-        start_text: sponsor.start_text,
-        won_text: sponsor.won_text,
-        lost_text: sponsor.lost_text,
-        greeting_text: sponsor.greeting_text,
-        end_text: sponsor.end_text,
-        rating_threshold: sponsor.rating_threshold,
-        initial_funded: true,
+    let Ok(amount) = u64::try_from(sponsor.original_tokens) else {
+        return (StatusCode::BAD_REQUEST, "Invalid deposit amount").into_response();
     };
 
+    // The sponsor is only activated once the deposit landed
+    let signature = match verify_deposit(
+        &solana,
+        &database,
+        &sponsor,
+        DEPOSIT_KIND,
+        amount,
+        transaction
+    ).await {
+        Ok(signature) => signature,
+        Err(e) if e.is_rejection() => {
+            log::warn!("Rejected deposit of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::BAD_REQUEST, "The deposit could not be verified").into_response();
+        }
+        Err(e) => {
+            log::error!("Failed to verify deposit of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to send the deposit").into_response();
+        }
+    };
+
+
+    let sponsor = match database.update_sponsor_to_active(sponsor_args.sponsor_public_key.clone()).await {
+        Ok(Some(sponsor)) => sponsor,
+        Ok(None) => {
+            // Another deposit funded the sponsor while this one was sent, the tokens of this
+            // one landed in the wallet as well, so they are added like a top-up
+            log::warn!("Sponsor {} was funded twice, adding deposit {signature} to the pool", sponsor.id);
+            match database.add_sponsor_tokens(sponsor.id, sponsor.original_tokens).await {
+                Ok(sponsor) => sponsor,
+                Err(e) => {
+                    // The deposit landed, so the pool has to be corrected by hand
+                    log::error!("Failed to add deposit {signature} of {} tokens to sponsor {}: {e:?}", sponsor.original_tokens, sponsor.id);
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add the tokens").into_response();
+                }
+            }
+        }
+        Err(e) => {
+            // The deposit landed, releasing it lets the same transaction be sent again,
+            // which is accepted without depositing twice
+            log::error!("Failed to activate sponsor {} funded with {signature}: {e:?}", sponsor.id);
+            if let Err(e) = database.release_signature(&signature.to_string()).await {
+                log::error!("Failed to release deposit {signature}: {e:?}");
+            }
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to activate the sponsor").into_response();
+        }
+    };
+
+
     let response_data = ResponseData {
-        sponsor: return_sponsor,
+        sponsor: ReturnSponsor::from(sponsor),
         signature: signature.to_string(),
     };
 
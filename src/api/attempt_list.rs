@@ -3,6 +3,7 @@ use axum::Json;
 use crate::api::{Attempt, AttemptReturn};
 use axum::Extension;
 use crate::Database;
+use std::collections::HashMap;
 
 // Implement the From trait for AttemptReturn
 impl From<Attempt> for AttemptReturn {
@@ -21,6 +22,10 @@ impl From<Attempt> for AttemptReturn {
             sponsor_challenge_time: attempt.sponsor_challenge_time,
             challenge_transcript: attempt.challenge_transcript,
             challenge_status: attempt.challenge_status,
+            guard_flagged: attempt.guard_flagged,
+            guard_verdict: attempt.guard_verdict,
+            sponsor_jackpot: None,
+            sponsor_version: attempt.sponsor_version,
         }
     }
 }
@@ -29,12 +34,27 @@ pub async fn attempt_list(
     Extension(database): Extension<Database>,
 ) -> impl IntoResponse {
 
+    // Every attempt shows the current jackpot of its sponsor
+    let jackpots: HashMap<i32, i64> = database
+        .get_jackpots()
+        .await
+        .unwrap_or(vec![])
+        .into_iter()
+        .map(|jackpot| (jackpot.sponsor_id, jackpot.jackpot_tokens))
+        .collect();
+
     let attempt_list: Vec<AttemptReturn> = database
         .get_all_attempts_last_14_days()
         .await
         .unwrap_or(vec![])
         .into_iter()
-        .map(AttemptReturn::from)
+        .map(|attempt| {
+            let sponsor_jackpot = attempt.sponsor_id.and_then(|id| jackpots.get(&id).copied());
+            AttemptReturn {
+                sponsor_jackpot,
+                ..AttemptReturn::from(attempt)
+            }
+        })
         .filter(|attempt| attempt.video_url.as_ref().map_or(false, |url| !url.is_empty()))
         .collect();
 
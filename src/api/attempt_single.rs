@@ -11,6 +11,14 @@ pub async fn attempt_single(
 ) -> impl IntoResponse {
 
     if let Some(attempt) = database.get_attempt_by_pubkey(public_key).await.unwrap_or(None) {
+        let sponsor_jackpot = database
+            .get_jackpots()
+            .await
+            .unwrap_or(vec![])
+            .into_iter()
+            .find(|jackpot| Some(jackpot.sponsor_id) == attempt.sponsor_id)
+            .map(|jackpot| jackpot.jackpot_tokens);
+
         let attempt_return = AttemptReturn {
             id: attempt.id,
             created_at: attempt.created_at,
@@ -25,6 +33,10 @@ pub async fn attempt_single(
             sponsor_challenge_time: attempt.sponsor_challenge_time,
             challenge_transcript: attempt.challenge_transcript,
             challenge_status: attempt.challenge_status,
+            guard_flagged: attempt.guard_flagged,
+            guard_verdict: attempt.guard_verdict,
+            sponsor_jackpot,
+            sponsor_version: attempt.sponsor_version,
         };
 
         Json(attempt_return).into_response()
@@ -0,0 +1,361 @@
+use crate::database::{AuthNonce, Database, Sponsor};
+use crate::secrets::Secrets;
+use crate::StatusCode;
+use axum::extract::FromRequestParts;
+use axum::http::{header::AUTHORIZATION, request::Parts};
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Extension, Json};
+use chrono::{DateTime, Duration, SecondsFormat, SubsecRound, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use std::str::FromStr;
+
+/// How long a sign-in challenge can be signed.
+const CHALLENGE_LIFETIME: Duration = Duration::minutes(5);
+
+/// How long a session lasts before the owner has to sign in again.
+const SESSION_LIFETIME: Duration = Duration::hours(1);
+
+/// What the owner agrees to by signing the message.
+const SIGN_IN_STATEMENT: &str = "Sign in to manage your sponsors on why.fun.";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ChallengeArgs {
+    pub public_key: String,
+}
+
+#[derive(Serialize)]
+pub struct ChallengeResponse {
+    message: String,
+    nonce: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct VerifyArgs {
+    pub public_key: String,
+    pub nonce: String,
+    pub signature: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionClaims {
+    sub: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// The wallet of a sponsor owner who signed in with Solana, taken from the session token in
+/// the `Authorization: Bearer` header. Handlers that take it can only be called by a signed in owner.
+#[derive(Debug, Clone)]
+pub struct SponsorSession {
+    pub public_key: Pubkey,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for SponsorSession {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(secrets) = parts.extensions.get::<Secrets>() else {
+            log::error!("Secrets are missing from the request extensions");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check the session",
+            ));
+        };
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or((StatusCode::UNAUTHORIZED, "Sign in to continue"))?;
+
+        let public_key = check_session(token, secrets).ok_or((
+            StatusCode::UNAUTHORIZED,
+            "The session is invalid or expired",
+        ))?;
+
+        Ok(SponsorSession { public_key })
+    }
+}
+
+impl SponsorSession {
+    /// The sponsor with the public key, if the signed in owner owns it. Otherwise the response
+    /// to answer with.
+    pub async fn owned_sponsor(
+        &self,
+        database: &Database,
+        public_key: &str,
+    ) -> Result<Sponsor, Response> {
+        let Ok(sponsor) = database
+            .get_sponsor_by_public_key(public_key.to_owned())
+            .await
+        else {
+            return Err((StatusCode::NOT_FOUND, Json("Sponsor not found")).into_response());
+        };
+
+        if sponsor.user_id != self.public_key.to_string() {
+            log::warn!(
+                "Rejected access to sponsor {} by {}, who does not own it",
+                sponsor.id,
+                self.public_key
+            );
+            return Err(
+                (StatusCode::FORBIDDEN, Json("You do not own this sponsor")).into_response()
+            );
+        }
+
+        Ok(sponsor)
+    }
+}
+
+/// Issues a nonce for the wallet and returns the Sign-In-With-Solana message it has to sign.
+pub async fn challenge(
+    secrets: Extension<Secrets>,
+    Extension(database): Extension<Database>,
+    Json(request): Json<ChallengeArgs>,
+) -> impl IntoResponse {
+    let Ok(public_key) = Pubkey::from_str(&request.public_key) else {
+        return (StatusCode::BAD_REQUEST, Json("Invalid public key format")).into_response();
+    };
+
+    // The message carries whole seconds, so it can be rebuilt from the stored nonce
+    let issued_at = Utc::now().trunc_subsecs(0);
+
+    let nonce = match database
+        .create_auth_nonce(
+            &public_key.to_string(),
+            &secrets.auth_domain,
+            issued_at,
+            issued_at + CHALLENGE_LIFETIME,
+        )
+        .await
+    {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            log::error!("Failed to create sign-in nonce: {e:?}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json("Failed to create the challenge"),
+            )
+                .into_response();
+        }
+    };
+
+    let response = ChallengeResponse {
+        message: sign_in_message(&nonce, &secrets.global_url),
+        nonce: nonce.nonce,
+        expires_at: nonce.expires_at,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Checks the signed challenge and starts a session for the wallet. Every nonce signs in once.
+pub async fn verify(
+    secrets: Extension<Secrets>,
+    Extension(database): Extension<Database>,
+    Json(request): Json<VerifyArgs>,
+) -> impl IntoResponse {
+    let (Ok(signature), Ok(public_key)) = (
+        Signature::from_str(&request.signature),
+        Pubkey::from_str(&request.public_key),
+    ) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json("Invalid signature or public key format"),
+        )
+            .into_response();
+    };
+
+    // The nonce is used up before the signature is checked, so it can not be guessed at
+    let nonce = match database
+        .use_auth_nonce(&request.nonce, &public_key.to_string())
+        .await
+    {
+        Ok(Some(nonce)) => nonce,
+        Ok(None) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json("The challenge expired or was already used"),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            log::error!("Failed to use sign-in nonce: {e:?}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json("Failed to check the challenge"),
+            )
+                .into_response();
+        }
+    };
+
+    let message = sign_in_message(&nonce, &secrets.global_url);
+
+    if nonce.domain != secrets.auth_domain
+        || !signature.verify(&public_key.to_bytes(), message.as_bytes())
+    {
+        return (StatusCode::UNAUTHORIZED, Json("Invalid signature")).into_response();
+    }
+
+    match issue_session(&public_key, &secrets, Utc::now()) {
+        Ok((token, expires_at)) => {
+            (StatusCode::OK, Json(SessionResponse { token, expires_at })).into_response()
+        }
+        Err(e) => {
+            log::error!("Failed to issue session token: {e:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json("Failed to start the session"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The Sign-In-With-Solana message of the nonce, in the format wallets show to the owner.
+fn sign_in_message(nonce: &AuthNonce, uri: &str) -> String {
+    format!(
+        "{domain} wants you to sign in with your Solana account:\n\
+        {address}\n\
+        \n\
+        {SIGN_IN_STATEMENT}\n\
+        \n\
+        URI: {uri}\n\
+        Version: 1\n\
+        Nonce: {nonce}\n\
+        Issued At: {issued_at}\n\
+        Expiration Time: {expires_at}",
+        domain = nonce.domain,
+        address = nonce.public_key,
+        nonce = nonce.nonce,
+        issued_at = nonce.issued_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        expires_at = nonce.expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
+
+/// Signs a session token for the wallet, valid for the domain the owner signed in to.
+fn issue_session(
+    public_key: &Pubkey,
+    secrets: &Secrets,
+    now: DateTime<Utc>,
+) -> Result<(String, DateTime<Utc>), jsonwebtoken::errors::Error> {
+    let expires_at = now + SESSION_LIFETIME;
+    let claims = SessionClaims {
+        sub: public_key.to_string(),
+        aud: secrets.auth_domain.clone(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+    };
+
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secrets.session_secret.as_bytes()),
+    )?;
+
+    Ok((token, expires_at))
+}
+
+/// The wallet of the session, if the token is valid and did not expire.
+fn check_session(token: &str, secrets: &Secrets) -> Option<Pubkey> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[&secrets.auth_domain]);
+
+    let claims = decode::<SessionClaims>(
+        token,
+        &DecodingKey::from_secret(secrets.session_secret.as_bytes()),
+        &validation,
+    )
+    .ok()?
+    .claims;
+
+    Pubkey::from_str(&claims.sub).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    fn secrets() -> Secrets {
+        Secrets {
+            global_url: "https://api.why.fun".to_owned(),
+            auth_domain: "why.fun".to_owned(),
+            session_secret: "test secret".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn nonce(public_key: &Pubkey) -> AuthNonce {
+        let issued_at = DateTime::from_timestamp(1_734_000_000, 0).unwrap();
+
+        AuthNonce {
+            nonce: "0123456789abcdef".to_owned(),
+            public_key: public_key.to_string(),
+            domain: "why.fun".to_owned(),
+            issued_at,
+            expires_at: issued_at + CHALLENGE_LIFETIME,
+            used_at: None,
+        }
+    }
+
+    #[test]
+    fn message_binds_domain_wallet_and_nonce() {
+        let keypair = Keypair::new();
+        let message = sign_in_message(&nonce(&keypair.pubkey()), "https://api.why.fun");
+
+        assert!(message.starts_with(&format!(
+            "why.fun wants you to sign in with your Solana account:\n{}\n",
+            keypair.pubkey()
+        )));
+        assert!(message.contains("\nNonce: 0123456789abcdef\n"));
+        assert!(message.ends_with("Expiration Time: 2024-12-12T10:45:00Z"));
+
+        let signature = keypair.sign_message(message.as_bytes());
+        assert!(signature.verify(&keypair.pubkey().to_bytes(), message.as_bytes()));
+    }
+
+    #[test]
+    fn session_round_trips() {
+        let public_key = Pubkey::new_unique();
+        let (token, _) = issue_session(&public_key, &secrets(), Utc::now()).unwrap();
+
+        assert_eq!(check_session(&token, &secrets()), Some(public_key));
+    }
+
+    #[test]
+    fn rejects_expired_and_foreign_sessions() {
+        let public_key = Pubkey::new_unique();
+
+        let (expired, _) =
+            issue_session(&public_key, &secrets(), Utc::now() - Duration::hours(2)).unwrap();
+        assert_eq!(check_session(&expired, &secrets()), None);
+
+        let other_domain = Secrets {
+            auth_domain: "evil.fun".to_owned(),
+            ..secrets()
+        };
+        let (foreign, _) = issue_session(&public_key, &other_domain, Utc::now()).unwrap();
+        assert_eq!(check_session(&foreign, &secrets()), None);
+
+        let other_secret = Secrets {
+            session_secret: "other secret".to_owned(),
+            ..secrets()
+        };
+        let (forged, _) = issue_session(&public_key, &other_secret, Utc::now()).unwrap();
+        assert_eq!(check_session(&forged, &secrets()), None);
+    }
+}
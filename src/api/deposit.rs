@@ -3,7 +3,7 @@ use axum::Json;
 use axum::Extension;
 use crate::StatusCode;
 use serde::{Serialize, Deserialize};
-use crate::secrets::Secrets;
+use crate::solana::service::SolanaService;
 use crate::solana::generate_deposit::generate_deposit;
 use base64::{engine::general_purpose, Engine as _};
 use bincode;
@@ -17,7 +17,7 @@ pub struct DepositArgs {
 }
 
 pub async fn deposit(
-    secrets: Extension<Secrets>,
+    Extension(solana): Extension<SolanaService>,
     Extension(database): Extension<Database>,
     Json(payment_args): Json<DepositArgs>
 ) -> impl IntoResponse {
@@ -25,13 +25,23 @@ pub async fn deposit(
     let sender_public_key = payment_args.sender_public_key;
     let sponsor_public_key = payment_args.sponsor_public_key;
 
-    let deposit_transaction = generate_deposit(
-        &secrets,
-        &database,
+    let Ok(sponsor) = database.get_sponsor_by_public_key(sponsor_public_key).await else {
+        return (StatusCode::NOT_FOUND, "Sponsor not found").into_response();
+    };
+
+    let deposit_transaction = match generate_deposit(
+        &solana,
         sender_public_key,
-        sponsor_public_key
-    ).await
-    .unwrap();
+        &sponsor,
+        sponsor.original_tokens as u64
+    ).await {
+        Ok(transaction) => transaction,
+        Err(e) if e.is_rejection() => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => {
+            log::error!("Failed to generate deposit of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate the deposit").into_response();
+        }
+    };
 
     let serialized_transaction = bincode::serialize(&deposit_transaction).expect("Failed to serialize transaction");
     let encoded_transaction = general_purpose::STANDARD.encode(serialized_transaction);
@@ -1,20 +1,25 @@
 use axum::response::IntoResponse;
 use axum::Json;
 use axum::Extension;
-use crate::solana::verify_payment::verify_payment;
-use crate::database::Sponsor;
+use crate::solana::verify::verify_payment;
+use crate::database::{RewardTier, Sponsor};
 use crate::api::SponsorArgs;
 use crate::Database;
-use anyhow::Context;
 use crate::StatusCode;
 use serde::Serialize;
 use crate::solana::keys::generate_private_key;
 use solana_sdk::signer::Signer;
-use crate::secrets::Secrets;
+use crate::solana::service::SolanaService;
 use base64::{engine::general_purpose, Engine as _};
 use bincode;
 use solana_sdk::transaction::Transaction;
-use crate::api::ResponseData;
+use crate::api::{ResponseData, MAX_CHALLENGE_TIME};
+use crate::game::consensus::{JudgingMode, MAX_JUDGE_COUNT};
+use crate::game::guard::GuardAction;
+use crate::game::reward::{validate_jackpot, validate_tiers};
+use crate::game::texts::{preview_texts, validate_text, SponsorText, TextPreviews};
+use crate::llm::LlmProviders;
+use crate::solana::prize::{Prize, PrizeKind, MIN_SOL_PRIZE_LAMPORTS};
 
 
 #[derive(Serialize)]
@@ -39,12 +44,72 @@ pub struct ReturnSponsor {
     pub end_text: String,
     pub rating_threshold: i32,
     pub initial_funded: bool,
+    pub judge_abandoned: bool,
+    pub llm_provider: String,
+    pub judging_mode: String,
+    pub judge_count: i32,
+    pub judge_quorum: i32,
+    pub guard_action: String,
+    pub payout_approval_threshold: Option<i64>,
+    pub prize_kind: String,
+    pub token_decimals: Option<i32>,
+    /// Only set by the responses that load the tiers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reward_tiers: Option<Vec<RewardTier>>,
+    pub jackpot: bool,
+    pub jackpot_contribution: i64,
+    pub jackpot_tokens: i64,
+    /// The texts as callers get them, only set by the responses that change the texts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_previews: Option<TextPreviews>,
+}
+
+impl From<Sponsor> for ReturnSponsor {
+    fn from(sponsor: Sponsor) -> Self {
+        ReturnSponsor {
+            id: sponsor.id,
+            name: sponsor.name,
+            user_id: sponsor.user_id,
+            active: sponsor.active,
+            background_url: sponsor.background_url,
+            public_key: sponsor.public_key,
+            token_mint: sponsor.token_mint,
+            original_tokens: sponsor.original_tokens,
+            available_tokens: sponsor.available_tokens,
+            reward_tokens: sponsor.reward_tokens,
+            challenge_text: sponsor.challenge_text,
+            challenge_time: sponsor.challenge_time,
+            system_instruction: sponsor.system_instruction,
+            start_text: sponsor.start_text,
+            won_text: sponsor.won_text,
+            lost_text: sponsor.lost_text,
+            greeting_text: sponsor.greeting_text,
+            end_text: sponsor.end_text,
+            rating_threshold: sponsor.rating_threshold,
+            initial_funded: sponsor.initial_funded,
+            judge_abandoned: sponsor.judge_abandoned,
+            llm_provider: sponsor.llm_provider,
+            judging_mode: sponsor.judging_mode,
+            judge_count: sponsor.judge_count,
+            judge_quorum: sponsor.judge_quorum,
+            guard_action: sponsor.guard_action,
+            payout_approval_threshold: sponsor.payout_approval_threshold,
+            prize_kind: sponsor.prize_kind,
+            token_decimals: sponsor.token_decimals,
+            reward_tiers: None,
+            jackpot: sponsor.jackpot,
+            jackpot_contribution: sponsor.jackpot_contribution,
+            jackpot_tokens: sponsor.jackpot_tokens,
+            text_previews: None,
+        }
+    }
 }
 
 
 pub async fn launchpad(
-    secrets: Extension<Secrets>,
+    Extension(solana): Extension<SolanaService>,
     Extension(database): Extension<Database>,
+    Extension(llm): Extension<LlmProviders>,
     Json(new_sponsor): Json<SponsorArgs>,
 ) -> impl IntoResponse {
     let challenge: String = String::from("Lets start the game: ");
@@ -52,7 +117,64 @@ pub async fn launchpad(
 
     let private_key = generate_private_key();
     let public_key = private_key.pubkey().to_string();
-    let private_key_base58 = private_key.to_base58_string();
+
+    // The key is sealed before anything is paid, a sponsor is never stored with an open key
+    let sealed_private_key = match solana.seal_key(&private_key) {
+        Ok(sealed_private_key) => sealed_private_key,
+        Err(e) => {
+            log::error!("Failed to seal private key of new sponsor: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create the sponsor wallet").into_response();
+        }
+    };
+
+    // The mint is checked before the launch fee is paid, so a wrong mint does not cost anything
+    let prize_kind = PrizeKind::from(new_sponsor.prize_kind.as_str());
+    let prize = match Prize::resolve(&solana, prize_kind, new_sponsor.token_mint.trim()).await {
+        Ok(prize) => prize,
+        Err(e) if e.is_rejection() => {
+            log::warn!("Rejected prize of new sponsor: {e}");
+            return (StatusCode::BAD_REQUEST, "The token mint does not match the prize kind").into_response();
+        }
+        Err(e) => {
+            log::error!("Failed to look up prize of new sponsor: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to look up the token mint").into_response();
+        }
+    };
+
+    if prize == Prize::Sol && new_sponsor.reward_tokens < MIN_SOL_PRIZE_LAMPORTS as i64 {
+        return (StatusCode::BAD_REQUEST, "The SOL prize is too small to be paid out").into_response();
+    }
+
+    if let Err(e) = validate_tiers(prize_kind, &new_sponsor.reward_tiers) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    if let Err(e) = validate_jackpot(prize_kind, new_sponsor.jackpot, new_sponsor.jackpot_contribution) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    // An unknown provider would silently fall back on the default one
+    let llm_provider = new_sponsor.llm_provider.trim().to_owned();
+    if !llm.contains(&llm_provider) {
+        return (StatusCode::BAD_REQUEST, format!("Unknown LLM provider {llm_provider}")).into_response();
+    }
+
+    // Every text is checked for variables it can not use before the sponsor is created
+    let start_text = format!("{} {}", challenge, new_sponsor.challenge);
+    for (kind, text) in [
+        (SponsorText::Greeting, &new_sponsor.greeting_text),
+        (SponsorText::Start, &start_text),
+        (SponsorText::End, &new_sponsor.end_text),
+        (SponsorText::Won, &new_sponsor.won_text),
+        (SponsorText::Lost, &new_sponsor.lost_text),
+    ] {
+        if let Err(e) = validate_text(kind, text) {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+
+    // Every judge is a completion, so the size of the panel is limited
+    let judge_count = new_sponsor.judge_count.clamp(1, MAX_JUDGE_COUNT);
 
     let sponsor = Sponsor {
         id: 1,
@@ -60,66 +182,98 @@ pub async fn launchpad(
         user_id: new_sponsor.user_id.trim().to_string(),
         active: false,
         background_url: new_sponsor.background_url.trim().to_string(),
-        private_key: private_key_base58,
+        private_key: sealed_private_key,
         public_key: public_key.to_string(),
-        token_mint: new_sponsor.token_mint.trim().to_string(),
+        token_mint: match prize {
+            Prize::Token { .. } => new_sponsor.token_mint.trim().to_string(),
+            Prize::Sol | Prize::Nft => String::new(),
+        },
         original_tokens: new_sponsor.original_tokens,
         available_tokens: new_sponsor.original_tokens,
-        reward_tokens: new_sponsor.reward_tokens,
-        challenge_time: if new_sponsor.challenge_time > 60 {
-            60
+        // Every win is worth a single NFT
+        reward_tokens: if prize == Prize::Nft { 1 } else { new_sponsor.reward_tokens },
+        challenge_time: if new_sponsor.challenge_time > MAX_CHALLENGE_TIME {
+            MAX_CHALLENGE_TIME
         } else {
             new_sponsor.challenge_time
         },
         system_instruction: new_sponsor.system_instruction,
-        greeting_text: "Welcome to Why dot Fun. Please tell me your name to start the game.".to_string(),
+        greeting_text: new_sponsor.greeting_text.trim().to_string(),
         challenge_text: new_sponsor.challenge.clone(),
-        start_text: format!("{} {}", challenge, new_sponsor.challenge),
-        end_text: "Alright, your time is up! Thank you for participating. You will receive a text message with the results of your attempt. If you are calling from the United States, visit claim.why.fun to check your result. Callers from the US will not receive a text message, please check your result on claim.why.fun. Thank you for playing today!".to_string(),
-        won_text: "Congratulations {name}, you won! Claim your prize: {link}. View the video of your attempt here: {video_url} (it will be ready in around 15 minutes)".to_string(),
-        lost_text: "Unfortunately, you did not win this time. Better luck next time! Check out https://x.com/whydotfun for tips and tricks to improve your chances.".to_string(),
+        start_text,
+        end_text: new_sponsor.end_text.trim().to_string(),
+        won_text: new_sponsor.won_text.trim().to_string(),
+        lost_text: new_sponsor.lost_text.trim().to_string(),
         rating_threshold: new_sponsor.rating_threshold,
         initial_funded: false,
+        judge_abandoned: new_sponsor.judge_abandoned,
+        llm_provider,
+        judging_mode: JudgingMode::from(new_sponsor.judging_mode.as_str()).as_str().to_owned(),
+        judge_count,
+        judge_quorum: new_sponsor.judge_quorum.clamp(1, judge_count),
+        guard_action: GuardAction::from(new_sponsor.guard_action.as_str()).as_str().to_owned(),
+        // A negative threshold would hold back every win, which is what a threshold of 0 does
+        payout_approval_threshold: new_sponsor.payout_approval_threshold.map(|threshold| threshold.max(0)),
+        prize_kind: prize_kind.as_str().to_owned(),
+        token_decimals: Some(prize.decimals().into()),
+        jackpot: new_sponsor.jackpot,
+        jackpot_contribution: if new_sponsor.jackpot { new_sponsor.jackpot_contribution } else { 0 },
+        jackpot_tokens: 0,
     };
 
     // Decode the base64-encoded transaction
-    let decoded_transaction = general_purpose::STANDARD
-        .decode(&new_sponsor.transaction)
-        .expect("Failed to decode transaction");
+    let Ok(decoded_transaction) = general_purpose::STANDARD.decode(&new_sponsor.transaction) else {
+        return (StatusCode::BAD_REQUEST, "Invalid transaction encoding").into_response();
+    };
 
     // Deserialize the transaction
-    let transaction: Transaction = bincode::deserialize(&decoded_transaction)
-        .expect("Failed to deserialize transaction");
+    let Ok(transaction) = bincode::deserialize::<Transaction>(&decoded_transaction) else {
+        return (StatusCode::BAD_REQUEST, "Invalid transaction").into_response();
+    };
 
-    let signature = verify_payment(&secrets, transaction).await.expect("Failed to verify payment");
+    // The sponsor is only created once the launch fee landed
+    let signature = match verify_payment(&solana, &database, transaction).await {
+        Ok(signature) => signature,
+        Err(e) if e.is_rejection() => {
+            log::warn!("Rejected launch payment: {e}");
+            return (StatusCode::BAD_REQUEST, "The payment could not be verified").into_response();
+        }
+        Err(e) => {
+            log::error!("Failed to verify launch payment: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to send the payment").into_response();
+        }
+    };
+
+    let sponsor_entry = match database.create_sponsor(sponsor).await {
+        Ok(sponsor_entry) => sponsor_entry,
+        Err(e) => {
+            // The payment landed, releasing it lets the same transaction be sent again,
+            // which is accepted without paying twice
+            log::error!("Failed to create sponsor paid with {signature}: {e:?}");
+            if let Err(e) = database.release_signature(&signature.to_string()).await {
+                log::error!("Failed to release payment {signature}: {e:?}");
+            }
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create the sponsor").into_response();
+        }
+    };
+
+    let reward_tiers = match database.set_reward_tiers(sponsor_entry.id, &new_sponsor.reward_tiers).await {
+        Ok(reward_tiers) => reward_tiers,
+        Err(e) => {
+            log::error!("Failed to set reward tiers of sponsor {}: {e:?}", sponsor_entry.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set the reward tiers").into_response();
+        }
+    };
 
-    let sponsor_entry = database
-        .create_sponsor(sponsor)
-        .await
-        .context("Creating sponsor")
-        .expect("Failed to create sponsor");
+    if let Err(e) = database.create_sponsor_version(&sponsor_entry, &reward_tiers, &sponsor_entry.user_id).await {
+        log::error!("Failed to create the first version of sponsor {}: {e:?}", sponsor_entry.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create the sponsor").into_response();
+    }
 
     let return_sponsor = ReturnSponsor {
-        id: sponsor_entry.id,
-        name: sponsor_entry.name,
-        user_id: sponsor_entry.user_id,
-        active: sponsor_entry.active,
-        background_url: sponsor_entry.background_url,
-        public_key: sponsor_entry.public_key,
-        token_mint: sponsor_entry.token_mint,
-        original_tokens: sponsor_entry.original_tokens,
-        available_tokens: sponsor_entry.available_tokens,
-        reward_tokens: sponsor_entry.reward_tokens,
-        challenge_text: sponsor_entry.challenge_text,
-        challenge_time: sponsor_entry.challenge_time,
-        system_instruction: sponsor_entry.system_instruction,
-        start_text: sponsor_entry.start_text,
-        won_text: sponsor_entry.won_text,
-        lost_text: sponsor_entry.lost_text,
-        greeting_text: sponsor_entry.greeting_text,
-        end_text: sponsor_entry.end_text,
-        rating_threshold: sponsor_entry.rating_threshold,
-        initial_funded: sponsor_entry.initial_funded,
+        text_previews: Some(preview_texts(&sponsor_entry, &reward_tiers)),
+        reward_tiers: Some(reward_tiers),
+        ..ReturnSponsor::from(sponsor_entry)
     };
 
     let response_data = ResponseData {
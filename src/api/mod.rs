@@ -1,4 +1,5 @@
 pub mod attempt_list;
+pub mod auth;
 pub mod attempt_single;
 pub mod launchpad;
 pub mod payment;
@@ -7,13 +8,21 @@ pub mod deposit;
 pub mod activate_sponsor;
 pub mod sponsor_list;
 pub mod update_sponsor;
+pub mod top_up;
+pub mod withdraw;
+pub mod nft_metadata;
+pub mod sponsor_audit;
+pub mod sponsor_diff;
+pub mod sponsor_versions;
 
 use chrono::Utc;
 use serde::{Serialize, Deserialize};
 use crate::api::launchpad::ReturnSponsor;
 
+/// The longest challenge a sponsor can set, in seconds.
+pub const MAX_CHALLENGE_TIME: i32 = 60;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Attempt {
     // id of the attempt
     pub id: i32,
@@ -53,6 +62,30 @@ pub struct Attempt {
     pub winner_url: String,
     // call sid of the call
     pub call_sid: String,
+    // id of the sponsor of the attempt
+    pub sponsor_id: Option<i32>,
+    // last reported twilio status of the call
+    pub call_status: Option<String>,
+    // duration of the call in seconds
+    pub call_duration: Option<i32>,
+    // reason the call ended
+    pub end_reason: Option<String>,
+    // time the call ended
+    pub ended_at: Option<chrono::DateTime<Utc>>,
+    // whether the injection guard flagged the attempt
+    pub guard_flagged: Option<bool>,
+    // why the injection guard did or did not flag the attempt
+    pub guard_verdict: Option<String>,
+    // time the unclaimed prize was returned to the sponsor
+    pub prize_swept_at: Option<chrono::DateTime<Utc>>,
+    // number of tokens returned to the sponsor, set when the sweep is sent
+    pub prize_swept_tokens: Option<i64>,
+    // signature of the transfer that returned the prize
+    pub prize_sweep_signature: Option<String>,
+    // version of the sponsor settings the attempt was played under
+    pub sponsor_version: Option<i32>,
+    // last block height at which the transfer returning the prize can land
+    pub prize_sweep_last_valid_block_height: Option<i64>,
 } 
 
 
@@ -83,6 +116,14 @@ pub struct AttemptReturn {
     pub challenge_transcript: Option<String>,
     // status of the challenge
     pub challenge_status: Option<String>,
+    // whether the injection guard flagged the attempt
+    pub guard_flagged: Option<bool>,
+    // why the injection guard did or did not flag the attempt
+    pub guard_verdict: Option<String>,
+    // current jackpot of the sponsor, if they run one
+    pub sponsor_jackpot: Option<i64>,
+    // version of the sponsor settings the attempt was played under
+    pub sponsor_version: Option<i32>,
 } 
 
 
@@ -99,6 +140,80 @@ pub struct SponsorArgs {
     pub challenge: String,
     pub rating_threshold: i32,
     pub transaction: String,
+    #[serde(default)]
+    pub judge_abandoned: bool,
+    #[serde(default = "default_llm_provider")]
+    pub llm_provider: String,
+    #[serde(default = "default_judging_mode")]
+    pub judging_mode: String,
+    #[serde(default = "default_judge_count")]
+    pub judge_count: i32,
+    #[serde(default = "default_judge_count")]
+    pub judge_quorum: i32,
+    #[serde(default = "default_guard_action")]
+    pub guard_action: String,
+    #[serde(default)]
+    pub payout_approval_threshold: Option<i64>,
+    #[serde(default = "default_prize_kind")]
+    pub prize_kind: String,
+    #[serde(default)]
+    pub reward_tiers: Vec<RewardTierArgs>,
+    #[serde(default)]
+    pub jackpot: bool,
+    #[serde(default)]
+    pub jackpot_contribution: i64,
+    #[serde(default = "default_greeting_text")]
+    pub greeting_text: String,
+    #[serde(default = "default_end_text")]
+    pub end_text: String,
+    #[serde(default = "default_won_text")]
+    pub won_text: String,
+    #[serde(default = "default_lost_text")]
+    pub lost_text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewardTierArgs {
+    pub min_rating: i32,
+    pub reward_tokens: i64,
+    #[serde(default)]
+    pub name: String,
+}
+
+fn default_llm_provider() -> String {
+    crate::llm::DEFAULT_PROVIDER.to_owned()
+}
+
+fn default_prize_kind() -> String {
+    crate::solana::prize::PrizeKind::Spl.as_str().to_owned()
+}
+
+fn default_judging_mode() -> String {
+    crate::game::consensus::JudgingMode::Single.as_str().to_owned()
+}
+
+fn default_guard_action() -> String {
+    crate::game::guard::GuardAction::ForceLoss.as_str().to_owned()
+}
+
+fn default_judge_count() -> i32 {
+    1
+}
+
+fn default_greeting_text() -> String {
+    crate::game::texts::DEFAULT_GREETING_TEXT.to_owned()
+}
+
+fn default_end_text() -> String {
+    crate::game::texts::DEFAULT_END_TEXT.to_owned()
+}
+
+fn default_won_text() -> String {
+    crate::game::texts::DEFAULT_WON_TEXT.to_owned()
+}
+
+fn default_lost_text() -> String {
+    crate::game::texts::DEFAULT_LOST_TEXT.to_owned()
 }
 
 
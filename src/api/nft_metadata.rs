@@ -0,0 +1,22 @@
+use axum::response::IntoResponse;
+use axum::extract::Path;
+use axum::Json;
+use axum::Extension;
+use crate::Database;
+use crate::StatusCode;
+use crate::solana::nft::offchain_metadata;
+
+/// Serves the off-chain metadata of the NFT prize of a call, its on-chain uri points here.
+pub async fn nft_metadata(
+    Extension(database): Extension<Database>,
+    Path(call_sid): Path<String>,
+) -> impl IntoResponse {
+    match database.get_nft_prize(&call_sid).await {
+        Ok(Some(prize)) => Json(offchain_metadata(&prize)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "NFT not found").into_response(),
+        Err(e) => {
+            log::error!("Failed to get NFT prize of call {call_sid}: {e:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get the NFT").into_response()
+        }
+    }
+}
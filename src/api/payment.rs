@@ -3,8 +3,9 @@ use axum::Json;
 use axum::Extension;
 use crate::StatusCode;
 use serde::{Serialize, Deserialize};
-use crate::secrets::Secrets;
+use crate::solana::service::SolanaService;
 use crate::solana::generate_payment::generate_payment;
+use crate::solana::verify::LAUNCH_FEE_LAMPORTS;
 use base64::{engine::general_purpose, Engine as _};
 use bincode;
 
@@ -16,19 +17,21 @@ pub struct PaymentArgs {
 
 #[axum::debug_handler]
 pub async fn payment(
-    secrets: Extension<Secrets>,
+    Extension(solana): Extension<SolanaService>,
     Json(payment_args): Json<PaymentArgs>
 ) -> impl IntoResponse {
 
     let sender = payment_args.sender;
-    let amount = 1000000000;
-
-    let transaction = generate_payment(
-        &secrets,
-        sender, 
-        amount
-    ).await
-    .unwrap();
+    let amount = LAUNCH_FEE_LAMPORTS;
+
+    let transaction = match generate_payment(&solana, sender, amount).await {
+        Ok(transaction) => transaction,
+        Err(e) if e.is_rejection() => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => {
+            log::error!("Failed to generate payment: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate the payment").into_response();
+        }
+    };
 
     let serialized_transaction = bincode::serialize(&transaction).expect("Failed to serialize transaction");
     let encoded_transaction = general_purpose::STANDARD.encode(serialized_transaction);
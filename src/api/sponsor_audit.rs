@@ -0,0 +1,37 @@
+use axum::extract::Query;
+use axum::response::IntoResponse;
+use axum::Json;
+use axum::Extension;
+use crate::Database;
+use crate::StatusCode;
+use crate::api::auth::SponsorSession;
+use serde::Deserialize;
+
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SponsorAuditArgs {
+    pub public_key: String,
+}
+
+/// Lists the changes made to a sponsor of the signed in owner, the latest first.
+pub async fn sponsor_audit(
+    Extension(database): Extension<Database>,
+    session: SponsorSession,
+    Query(request): Query<SponsorAuditArgs>,
+) -> impl IntoResponse {
+
+    let sponsor = match session.owned_sponsor(&database, &request.public_key).await {
+        Ok(sponsor) => sponsor,
+        Err(response) => return response,
+    };
+
+    let audit_log = match database.get_sponsor_audit_log(sponsor.id).await {
+        Ok(audit_log) => audit_log,
+        Err(e) => {
+            log::error!("Failed to get audit log of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the audit log")).into_response();
+        }
+    };
+
+    (StatusCode::OK, Json(audit_log)).into_response()
+}
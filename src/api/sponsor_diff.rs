@@ -0,0 +1,180 @@
+use crate::api::update_sponsor::UpdateSponsorArgs;
+use crate::database::{RewardTier, Sponsor};
+use serde_json::{json, Map, Value};
+
+/// Fields of the sponsor that are not part of its settings: its identity, its keys and the
+/// balances that change with every call.
+const UNTRACKED_FIELDS: [&str; 7] = [
+    "id",
+    "private_key",
+    "public_key",
+    "original_tokens",
+    "available_tokens",
+    "jackpot_tokens",
+    "initial_funded",
+];
+
+/// The settings of the sponsor and its reward tiers, as they are compared between changes.
+pub fn sponsor_snapshot(sponsor: &Sponsor, tiers: &[RewardTier]) -> Value {
+    let mut snapshot = match serde_json::to_value(sponsor) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+
+    for field in UNTRACKED_FIELDS {
+        snapshot.remove(field);
+    }
+
+    let tiers = tiers
+        .iter()
+        .map(|tier| {
+            json!({
+                "min_rating": tier.min_rating,
+                "reward_tokens": tier.reward_tokens,
+                "name": tier.name,
+            })
+        })
+        .collect();
+    snapshot.insert("reward_tiers".to_owned(), Value::Array(tiers));
+
+    Value::Object(snapshot)
+}
+
+/// The fields whose values differ between the snapshots, as `{"field": {"old": .., "new": ..}}`.
+/// Fields missing from one of the snapshots are compared as `null`.
+pub fn diff_snapshots(before: &Value, after: &Value) -> Map<String, Value> {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let old = before.get(field).unwrap_or(&Value::Null);
+            let new = after.get(field).unwrap_or(&Value::Null);
+
+            (old != new).then(|| (field.clone(), json!({ "old": old, "new": new })))
+        })
+        .collect()
+}
+
+/// The update that restores the settings of a version of the sponsor. Whether the sponsor is
+/// active is not restored, it depends on the funds the sponsor has now.
+pub fn rollback_args(public_key: &str, settings: &Value) -> serde_json::Result<UpdateSponsorArgs> {
+    let mut settings = settings.clone();
+    if let Value::Object(fields) = &mut settings {
+        fields.remove("active");
+        fields.insert("public_key".to_owned(), json!(public_key));
+    }
+
+    serde_json::from_value(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::update_sponsor::validate_update;
+
+    fn sponsor() -> Sponsor {
+        Sponsor {
+            id: 1,
+            name: "Test Sponsor".to_owned(),
+            challenge_time: 30,
+            rating_threshold: 7,
+            available_tokens: 1000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unchanged_sponsor_has_no_changes() {
+        let snapshot = sponsor_snapshot(&sponsor(), &[]);
+
+        assert!(diff_snapshots(&snapshot, &snapshot).is_empty());
+    }
+
+    #[test]
+    fn records_old_and_new_values() {
+        let before = sponsor_snapshot(&sponsor(), &[]);
+        let after = sponsor_snapshot(
+            &Sponsor {
+                rating_threshold: 8,
+                available_tokens: 500,
+                ..sponsor()
+            },
+            &[RewardTier {
+                id: 3,
+                sponsor_id: 1,
+                min_rating: 9,
+                reward_tokens: 50,
+                name: "gold".to_owned(),
+            }],
+        );
+
+        let changes = diff_snapshots(&before, &after);
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes["rating_threshold"], json!({ "old": 7, "new": 8 }));
+        assert_eq!(
+            changes["reward_tiers"]["new"],
+            json!([{ "min_rating": 9, "reward_tokens": 50, "name": "gold" }])
+        );
+    }
+
+    #[test]
+    fn rollback_restores_the_settings_but_not_the_activation() {
+        let settings = sponsor_snapshot(
+            &Sponsor {
+                active: true,
+                ..sponsor()
+            },
+            &[RewardTier {
+                id: 3,
+                sponsor_id: 1,
+                min_rating: 9,
+                reward_tokens: 50,
+                name: "gold".to_owned(),
+            }],
+        );
+
+        let update = rollback_args("sponsor key", &settings).unwrap();
+
+        assert_eq!(update.public_key, "sponsor key");
+        assert_eq!(update.name.as_deref(), Some("Test Sponsor"));
+        assert_eq!(update.rating_threshold, Some(7));
+        assert_eq!(update.active, None);
+        assert_eq!(update.reward_tiers.unwrap()[0].reward_tokens, 50);
+    }
+
+    #[test]
+    fn rollback_is_checked_like_an_update() {
+        let valid = Sponsor {
+            greeting_text: "Welcome!".to_owned(),
+            start_text: "Go, {name}!".to_owned(),
+            end_text: "Time is up!".to_owned(),
+            won_text: "You won, {name}!".to_owned(),
+            lost_text: "You lost, {name}.".to_owned(),
+            ..sponsor()
+        };
+        let update = rollback_args("sponsor key", &sponsor_snapshot(&valid, &[])).unwrap();
+        assert!(validate_update(&valid, &[], None, &update).is_ok());
+
+        let invalid = Sponsor {
+            greeting_text: "Hi {name}!".to_owned(),
+            ..valid.clone()
+        };
+        let update = rollback_args("sponsor key", &sponsor_snapshot(&invalid, &[])).unwrap();
+        assert!(validate_update(&valid, &[], None, &update).is_err());
+
+        let invalid = Sponsor {
+            challenge_time: 90,
+            ..valid.clone()
+        };
+        let update = rollback_args("sponsor key", &sponsor_snapshot(&invalid, &[])).unwrap();
+        assert!(validate_update(&valid, &[], None, &update).is_err());
+    }
+}
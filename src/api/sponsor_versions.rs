@@ -0,0 +1,169 @@
+use axum::extract::Query;
+use axum::response::IntoResponse;
+use axum::Json;
+use axum::Extension;
+use crate::Database;
+use crate::StatusCode;
+use crate::api::auth::SponsorSession;
+use crate::api::sponsor_diff::{diff_snapshots, rollback_args};
+use crate::api::update_sponsor::validate_update;
+use crate::api::ReturnSponsor;
+use crate::game::texts::preview_texts;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SponsorVersionsArgs {
+    pub public_key: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SponsorVersionDiffArgs {
+    pub public_key: String,
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Serialize)]
+pub struct SponsorVersionDiff {
+    from: i32,
+    to: i32,
+    changes: Map<String, Value>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RollbackSponsorArgs {
+    pub public_key: String,
+    pub version: i32,
+}
+
+/// Lists the versions of a sponsor of the signed in owner, the latest first.
+pub async fn sponsor_versions(
+    Extension(database): Extension<Database>,
+    session: SponsorSession,
+    Query(request): Query<SponsorVersionsArgs>,
+) -> impl IntoResponse {
+
+    let sponsor = match session.owned_sponsor(&database, &request.public_key).await {
+        Ok(sponsor) => sponsor,
+        Err(response) => return response,
+    };
+
+    let versions = match database.get_sponsor_versions(sponsor.id).await {
+        Ok(versions) => versions,
+        Err(e) => {
+            log::error!("Failed to get versions of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the versions")).into_response();
+        }
+    };
+
+    (StatusCode::OK, Json(versions)).into_response()
+}
+
+/// Shows what changed between two versions of a sponsor of the signed in owner.
+pub async fn sponsor_version_diff(
+    Extension(database): Extension<Database>,
+    session: SponsorSession,
+    Query(request): Query<SponsorVersionDiffArgs>,
+) -> impl IntoResponse {
+
+    let sponsor = match session.owned_sponsor(&database, &request.public_key).await {
+        Ok(sponsor) => sponsor,
+        Err(response) => return response,
+    };
+
+    let versions = (
+        database.get_sponsor_version(sponsor.id, request.from).await,
+        database.get_sponsor_version(sponsor.id, request.to).await,
+    );
+
+    let (from, to) = match versions {
+        (Ok(Some(from)), Ok(Some(to))) => (from, to),
+        (Ok(_), Ok(_)) => return (StatusCode::NOT_FOUND, Json("Version not found")).into_response(),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Failed to get versions {} and {} of sponsor {}: {e:?}", request.from, request.to, sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the versions")).into_response();
+        }
+    };
+
+    let diff = SponsorVersionDiff {
+        from: from.version,
+        to: to.version,
+        changes: diff_snapshots(&from.settings, &to.settings),
+    };
+
+    (StatusCode::OK, Json(diff)).into_response()
+}
+
+/// Restores the settings of a version of a sponsor of the signed in owner. The restored
+/// settings become a new version, so the rollback can be undone as well.
+pub async fn rollback_sponsor(
+    Extension(database): Extension<Database>,
+    session: SponsorSession,
+    Json(request): Json<RollbackSponsorArgs>,
+) -> impl IntoResponse {
+
+    let sponsor = match session.owned_sponsor(&database, &request.public_key).await {
+        Ok(sponsor) => sponsor,
+        Err(response) => return response,
+    };
+
+    log::info!("Rolling back sponsor {} to version {} signed in as {}", sponsor.id, request.version, session.public_key);
+
+    let version = match database.get_sponsor_version(sponsor.id, request.version).await {
+        Ok(Some(version)) => version,
+        Ok(None) => return (StatusCode::NOT_FOUND, Json("Version not found")).into_response(),
+        Err(e) => {
+            log::error!("Failed to get version {} of sponsor {}: {e:?}", request.version, sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the version")).into_response();
+        }
+    };
+
+    let update = match rollback_args(&sponsor.public_key, &version.settings) {
+        Ok(update) => update,
+        Err(e) => {
+            log::error!("Failed to read version {} of sponsor {}: {e:?}", version.version, sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to read the version")).into_response();
+        }
+    };
+
+    let reward_tiers = match database.get_reward_tiers(sponsor.id).await {
+        Ok(reward_tiers) => reward_tiers,
+        Err(e) => {
+            log::error!("Failed to get reward tiers of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the reward tiers")).into_response();
+        }
+    };
+
+    let reconciliation = match database.get_reconciliation(sponsor.id).await {
+        Ok(reconciliation) => reconciliation,
+        Err(e) => {
+            log::error!("Failed to get reconciliation of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the balance check")).into_response();
+        }
+    };
+
+    if let Err(e) = validate_update(&sponsor, &reward_tiers, reconciliation.as_ref(), &update) {
+        return (StatusCode::BAD_REQUEST, Json(format!("Version {} can not be restored: {e}", version.version))).into_response();
+    }
+
+    let (sponsor_entry, reward_tiers) = match database
+        .update_sponsor(sponsor.id, &update, &session.public_key.to_string())
+        .await
+    {
+        Ok(updated) => updated,
+        Err(e) => {
+            log::error!("Failed to roll back sponsor {} to version {}: {e:?}", sponsor.id, version.version);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to roll back the sponsor")).into_response();
+        }
+    };
+
+    let return_sponsor = ReturnSponsor {
+        text_previews: Some(preview_texts(&sponsor_entry, &reward_tiers)),
+        reward_tiers: Some(reward_tiers),
+        ..ReturnSponsor::from(sponsor_entry)
+    };
+
+    (StatusCode::OK, Json(return_sponsor)).into_response()
+}
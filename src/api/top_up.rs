@@ -0,0 +1,134 @@
+use axum::response::IntoResponse;
+use axum::Json;
+use axum::Extension;
+use crate::StatusCode;
+use serde::{Serialize, Deserialize};
+use crate::solana::service::SolanaService;
+use crate::solana::generate_deposit::generate_deposit;
+use crate::solana::verify::{verify_deposit, TOP_UP_KIND};
+use base64::{engine::general_purpose, Engine as _};
+use bincode;
+use solana_sdk::transaction::Transaction;
+use crate::database::Database;
+use crate::api::ResponseData;
+use crate::api::launchpad::ReturnSponsor;
+
+
+#[derive(Serialize, Deserialize)]
+pub struct TopUpArgs {
+    pub sender_public_key: String,
+    pub sponsor_public_key: String,
+    pub amount: u64,
+}
+
+/// Builds a transaction that deposits additional tokens to a funded sponsor.
+pub async fn top_up(
+    Extension(solana): Extension<SolanaService>,
+    Extension(database): Extension<Database>,
+    Json(top_up_args): Json<TopUpArgs>
+) -> impl IntoResponse {
+
+    if top_up_args.amount == 0 {
+        return (StatusCode::BAD_REQUEST, "The amount has to be positive").into_response();
+    }
+
+    let Ok(sponsor) = database.get_sponsor_by_public_key(top_up_args.sponsor_public_key).await else {
+        return (StatusCode::NOT_FOUND, "Sponsor not found").into_response();
+    };
+
+    if !sponsor.initial_funded {
+        return (StatusCode::BAD_REQUEST, "The initial deposit has to be made first").into_response();
+    }
+
+    let top_up_transaction = match generate_deposit(
+        &solana,
+        top_up_args.sender_public_key,
+        &sponsor,
+        top_up_args.amount
+    ).await {
+        Ok(transaction) => transaction,
+        Err(e) if e.is_rejection() => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => {
+            log::error!("Failed to generate top-up of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate the top-up").into_response();
+        }
+    };
+
+    let serialized_transaction = bincode::serialize(&top_up_transaction).expect("Failed to serialize transaction");
+    let encoded_transaction = general_purpose::STANDARD.encode(serialized_transaction);
+
+    (StatusCode::OK, Json(encoded_transaction)).into_response()
+}
+
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyTopUpArgs {
+    pub sponsor_public_key: String,
+    pub amount: u64,
+    pub transaction: String,
+}
+
+/// Sends the signed top-up transaction and adds the tokens to the sponsor's pool once it landed.
+pub async fn verify_top_up(
+    Extension(solana): Extension<SolanaService>,
+    Extension(database): Extension<Database>,
+    Json(top_up_args): Json<VerifyTopUpArgs>,
+) -> impl IntoResponse {
+
+    // Decode the base64-encoded transaction
+    let Ok(decoded_transaction) = general_purpose::STANDARD.decode(&top_up_args.transaction) else {
+        return (StatusCode::BAD_REQUEST, "Invalid transaction encoding").into_response();
+    };
+
+    // Deserialize the transaction
+    let Ok(transaction) = bincode::deserialize::<Transaction>(&decoded_transaction) else {
+        return (StatusCode::BAD_REQUEST, "Invalid transaction").into_response();
+    };
+
+    let Ok(amount) = i64::try_from(top_up_args.amount) else {
+        return (StatusCode::BAD_REQUEST, "Invalid top-up amount").into_response();
+    };
+
+    let Ok(sponsor) = database.get_sponsor_by_public_key(top_up_args.sponsor_public_key).await else {
+        return (StatusCode::NOT_FOUND, "Sponsor not found").into_response();
+    };
+
+    if !sponsor.initial_funded {
+        return (StatusCode::BAD_REQUEST, "The initial deposit has to be made first").into_response();
+    }
+
+    let signature = match verify_deposit(
+        &solana,
+        &database,
+        &sponsor,
+        TOP_UP_KIND,
+        top_up_args.amount,
+        transaction
+    ).await {
+        Ok(signature) => signature,
+        Err(e) if e.is_rejection() => {
+            log::warn!("Rejected top-up of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::BAD_REQUEST, "The top-up could not be verified").into_response();
+        }
+        Err(e) => {
+            log::error!("Failed to verify top-up of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to send the top-up").into_response();
+        }
+    };
+
+    let sponsor = match database.add_sponsor_tokens(sponsor.id, amount).await {
+        Ok(sponsor) => sponsor,
+        Err(e) => {
+            // The deposit landed, so the pool has to be corrected by hand
+            log::error!("Failed to add top-up {signature} of {amount} tokens to sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add the tokens").into_response();
+        }
+    };
+
+    let response_data = ResponseData {
+        sponsor: ReturnSponsor::from(sponsor),
+        signature: signature.to_string(),
+    };
+
+    (StatusCode::OK, Json(response_data)).into_response()
+}
@@ -2,85 +2,167 @@ use axum::response::IntoResponse;
 use axum::Json;
 use axum::Extension;
 use crate::Database;
+use crate::database::{BalanceReconciliation, RewardTier, Sponsor};
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
-use solana_sdk::signature::Signature;
-use solana_sdk::pubkey::Pubkey;
-use std::str::FromStr;
-use crate::api::ReturnSponsor;
+use crate::api::auth::SponsorSession;
+use crate::api::{ReturnSponsor, RewardTierArgs, MAX_CHALLENGE_TIME};
+use crate::game::reward::{highest_reward, validate_tiers, MAX_RATING};
+use crate::game::texts::{preview_texts, validate_text, SponsorText};
+use crate::solana::prize::PrizeKind;
 use crate::StatusCode;
 
 
+/// The changes to a sponsor. Only the fields that are set are changed.
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct UpdateSponsorArgs {
     pub public_key: String,
-    pub name: String,
-    pub active: bool,
-    pub background_url: String,
-    pub challenge_time: i32,
-    pub system_instruction: String,
-    pub start_text: String,
-    pub rating_threshold: i32,
-    pub challenge_text: String,
-    pub user_id: String,
-    pub signature: String
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub active: Option<bool>,
+    #[serde(default)]
+    pub background_url: Option<String>,
+    #[serde(default)]
+    pub challenge_time: Option<i32>,
+    #[serde(default)]
+    pub system_instruction: Option<String>,
+    #[serde(default)]
+    pub start_text: Option<String>,
+    #[serde(default)]
+    pub rating_threshold: Option<i32>,
+    #[serde(default)]
+    pub challenge_text: Option<String>,
+    #[serde(default)]
+    pub greeting_text: Option<String>,
+    #[serde(default)]
+    pub end_text: Option<String>,
+    #[serde(default)]
+    pub won_text: Option<String>,
+    #[serde(default)]
+    pub lost_text: Option<String>,
+    /// Replaces the reward tiers of the sponsor, the tiers are kept if missing
+    #[serde(default)]
+    pub reward_tiers: Option<Vec<RewardTierArgs>>,
 }
 
+/// Updates a sponsor of the signed in owner. Every change is written to the audit log and
+/// becomes a new version of the sponsor.
 pub async fn update_sponsor(
     Extension(database): Extension<Database>,
-    Json(request): Json<UpdateSponsorArgs>,
+    session: SponsorSession,
+    Json(mut request): Json<UpdateSponsorArgs>,
 ) -> impl IntoResponse {
 
-    let signature = &request.signature;
-    let public_key = &request.user_id;
+    log::info!("Updating sponsor {} signed in as {}", request.public_key, session.public_key);
 
-    // Convert the signature and public key from strings to their respective types
-    let signature = Signature::from_str(signature).expect("Invalid signature format");
-    let public_key = Pubkey::from_str(public_key).expect("Invalid public key format");
-
-    let message = chrono::Utc::now().format("%Y-%m-%d %H:00:00").to_string();
+    let sponsor = match session.owned_sponsor(&database, &request.public_key).await {
+        Ok(sponsor) => sponsor,
+        Err(response) => return response,
+    };
 
-    // Verify the signature
-    if !signature.verify(&public_key.to_bytes(), message.as_bytes()) {
-        return (StatusCode::BAD_REQUEST, Json("Invalid signature")).into_response();
-    }
+    let reward_tiers = match database.get_reward_tiers(sponsor.id).await {
+        Ok(reward_tiers) => reward_tiers,
+        Err(e) => {
+            log::error!("Failed to get reward tiers of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the reward tiers")).into_response();
+        }
+    };
 
-    let sponsor = database
-        .get_sponsor_by_public_key(request.public_key.clone())
-        .await
-        .expect("Failed to get sponsor");
+    let reconciliation = match database.get_reconciliation(sponsor.id).await {
+        Ok(reconciliation) => reconciliation,
+        Err(e) => {
+            log::error!("Failed to get reconciliation of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to get the balance check")).into_response();
+        }
+    };
 
-    if request.active == true && (sponsor.available_tokens < sponsor.reward_tokens || sponsor.available_tokens <= 0) {
-        return (StatusCode::BAD_REQUEST, Json("Cannot activate agent, not enough (reward) tokens available")).into_response();
+    if let Err(e) = validate_update(&sponsor, &reward_tiers, reconciliation.as_ref(), &request) {
+        return (StatusCode::BAD_REQUEST, Json(e.to_string())).into_response();
     }
 
+    for text in [
+        &mut request.name,
+        &mut request.background_url,
+        &mut request.greeting_text,
+        &mut request.end_text,
+        &mut request.won_text,
+        &mut request.lost_text,
+    ].into_iter().flatten() {
+        *text = text.trim().to_string();
+    }
 
-    let sponsor_entry = database.update_sponsor(request)
+    let (sponsor_entry, reward_tiers) = match database
+        .update_sponsor(sponsor.id, &request, &session.public_key.to_string())
         .await
-        .expect("Failed to update sponsor");
-
+    {
+        Ok(updated) => updated,
+        Err(e) => {
+            log::error!("Failed to update sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to update the sponsor")).into_response();
+        }
+    };
 
     let return_sponsor = ReturnSponsor {
-        id: sponsor_entry.id,
-        name: sponsor_entry.name,
-        user_id: sponsor_entry.user_id,
-        active: sponsor_entry.active,
-        background_url: sponsor_entry.background_url,
-        public_key: sponsor_entry.public_key,
-        token_mint: sponsor_entry.token_mint,
-        original_tokens: sponsor_entry.original_tokens,
-        available_tokens: sponsor_entry.available_tokens,
-        reward_tokens: sponsor_entry.reward_tokens,
-        challenge_text: sponsor_entry.challenge_text,
-        challenge_time: sponsor_entry.challenge_time,
-        system_instruction: sponsor_entry.system_instruction,
-        start_text: sponsor_entry.start_text,
-        greeting_text: sponsor_entry.greeting_text,
-        end_text: sponsor_entry.end_text,
-        won_text: sponsor_entry.won_text,
-        lost_text: sponsor_entry.lost_text,
-        rating_threshold: sponsor_entry.rating_threshold,
-        initial_funded: sponsor_entry.initial_funded,
+        text_previews: Some(preview_texts(&sponsor_entry, &reward_tiers)),
+        reward_tiers: Some(reward_tiers),
+        ..ReturnSponsor::from(sponsor_entry)
     };
 
     (StatusCode::OK, Json(return_sponsor)).into_response()
-}
\ No newline at end of file
+}
+
+/// Checks the changes to the sponsor before they are written, rollbacks are checked the same
+/// way, the settings of old versions may not be valid anymore. `reward_tiers` are the current
+/// tiers of the sponsor and `reconciliation` the latest check of their wallet.
+pub fn validate_update(
+    sponsor: &Sponsor,
+    reward_tiers: &[RewardTier],
+    reconciliation: Option<&BalanceReconciliation>,
+    request: &UpdateSponsorArgs,
+) -> Result<()> {
+    if request.challenge_time.is_some_and(|challenge_time| !(1..=MAX_CHALLENGE_TIME).contains(&challenge_time)) {
+        bail!("The challenge time has to be between 1 and {MAX_CHALLENGE_TIME} seconds");
+    }
+
+    if request.rating_threshold.is_some_and(|rating_threshold| !(0..=MAX_RATING).contains(&rating_threshold)) {
+        bail!("The rating threshold has to be between 0 and {MAX_RATING}");
+    }
+
+    // The pool has to cover the highest tier the sponsor has after the update
+    let highest_prize = match &request.reward_tiers {
+        Some(tiers) => highest_reward(sponsor, tiers.iter().map(|tier| tier.reward_tokens)),
+        None => highest_reward(sponsor, reward_tiers.iter().map(|tier| tier.reward_tokens)),
+    };
+
+    if request.active == Some(true) && (sponsor.available_tokens < highest_prize || sponsor.available_tokens <= 0) {
+        bail!("Cannot activate agent, not enough (reward) tokens available");
+    }
+
+    // The pool alone is not trusted, the wallet has to have covered the prize at its last check
+    if request.active == Some(true) && reconciliation.is_some_and(|reconciliation| {
+        reconciliation.mismatch
+            || reconciliation.deactivated
+            || reconciliation.onchain_tokens - reconciliation.owed_tokens - reconciliation.in_flight_tokens < highest_prize
+    }) {
+        bail!("Cannot activate agent, its wallet did not cover the prize at the last balance check, try again after the next one");
+    }
+
+    if let Some(reward_tiers) = &request.reward_tiers {
+        validate_tiers(PrizeKind::from(sponsor.prize_kind.as_str()), reward_tiers)?;
+    }
+
+    for (kind, text) in [
+        (SponsorText::Greeting, &request.greeting_text),
+        (SponsorText::Start, &request.start_text),
+        (SponsorText::End, &request.end_text),
+        (SponsorText::Won, &request.won_text),
+        (SponsorText::Lost, &request.lost_text),
+    ] {
+        if let Some(text) = text {
+            validate_text(kind, text)?;
+        }
+    }
+
+    Ok(())
+}
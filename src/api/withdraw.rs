@@ -0,0 +1,91 @@
+use axum::response::IntoResponse;
+use axum::Json;
+use axum::Extension;
+use crate::Database;
+use crate::StatusCode;
+use serde::{Deserialize, Serialize};
+use crate::api::auth::SponsorSession;
+use crate::api::launchpad::ReturnSponsor;
+use crate::solana::service::SolanaService;
+use crate::solana::withdraw::withdraw_sponsor_tokens;
+
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WithdrawArgs {
+    pub sponsor_public_key: String,
+}
+
+#[derive(Serialize)]
+pub struct WithdrawResponse {
+    sponsor: ReturnSponsor,
+    signature: Option<String>,
+    amount: u64,
+}
+
+/// Deactivates the sponsor and returns the tokens that are not owed to winners to the owner.
+pub async fn withdraw(
+    Extension(solana): Extension<SolanaService>,
+    Extension(database): Extension<Database>,
+    session: SponsorSession,
+    Json(request): Json<WithdrawArgs>,
+) -> impl IntoResponse {
+
+    let owner = session.public_key;
+
+    let Ok(sponsor) = database.get_sponsor_by_public_key(request.sponsor_public_key.clone()).await else {
+        return (StatusCode::NOT_FOUND, Json("Sponsor not found")).into_response();
+    };
+
+    if sponsor.user_id != owner.to_string() {
+        return (StatusCode::FORBIDDEN, Json("Only the owner can withdraw the tokens")).into_response();
+    }
+
+    // No new calls are started and calls in progress can not win anything while the tokens
+    // are withdrawn, everything that is not reserved by then is withdrawn
+    let reserved = match database.empty_pool_for_withdrawal(sponsor.id).await {
+        Ok(reserved) => reserved,
+        Err(e) => {
+            log::error!("Failed to empty the pool of sponsor {}: {e:?}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to withdraw the tokens")).into_response();
+        }
+    };
+
+    let withdrawal = match withdraw_sponsor_tokens(&solana, &sponsor, &owner, reserved.max(0) as u64).await {
+        Ok(withdrawal) => withdrawal,
+        Err(e) => {
+            // The pool stays empty until the withdrawal is retried, which sets it from the wallet
+            log::error!("Failed to withdraw tokens of sponsor {}: {e}", sponsor.id);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to withdraw the tokens")).into_response();
+        }
+    };
+
+    // The pool is set from the on-chain balance, so it no longer drifts from the wallet
+    let sponsor = match database.record_withdrawal(sponsor.id, withdrawal.remaining as i64).await {
+        Ok(sponsor) => sponsor,
+        Err(e) => {
+            // The tokens were withdrawn, so the pool has to be corrected by hand
+            log::error!(
+                "Failed to record withdrawal {:?} of {} tokens of sponsor {}: {e:?}",
+                withdrawal.signature,
+                withdrawal.amount,
+                sponsor.id
+            );
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Failed to record the withdrawal")).into_response();
+        }
+    };
+
+    log::info!(
+        "Withdrew {} tokens of sponsor {} to {}",
+        withdrawal.amount,
+        sponsor.id,
+        owner
+    );
+
+    let response = WithdrawResponse {
+        sponsor: ReturnSponsor::from(sponsor),
+        signature: withdrawal.signature.map(|signature| signature.to_string()),
+        amount: withdrawal.amount,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
@@ -1,15 +1,113 @@
-use crate::database::Sponsor;
+use crate::database::{Database, Sponsor};
+use anyhow::Result;
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestMessage,
     ChatCompletionRequestUserMessageContent,
 };
-use std::time::Instant;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Mutex;
 
+/// Stores the in-flight calls, mapping the call sid to the cached call.
+///
+/// The in-memory store only lives as long as the process and can only be used
+/// with a single instance of the app. The postgres store persists every change,
+/// so calls can be resumed and judged after a restart or by another instance.
 #[derive(Debug, Clone)]
+pub enum CallStore {
+    Memory(Arc<Mutex<HashMap<String, CachedCall>>>),
+    Postgres(Database),
+}
+
+impl CallStore {
+    pub fn memory() -> Self {
+        Self::Memory(Arc::new(Mutex::new(HashMap::new())))
+    }
+
+    pub fn postgres(database: Database) -> Self {
+        Self::Postgres(database)
+    }
+
+    /// Gets a copy of the cached call with the given sid.
+    /// Returns `None` if the call is not (or no longer) in the store.
+    pub async fn get(&self, call_sid: &str) -> Result<Option<CachedCall>> {
+        match self {
+            Self::Memory(cache) => Ok(cache.lock().await.get(call_sid).cloned()),
+            Self::Postgres(database) => database.get_cached_call(call_sid).await,
+        }
+    }
+
+    /// Inserts the cached call, replacing any previous call with the same sid.
+    pub async fn insert(&self, call_sid: &str, mut cached_call: CachedCall) -> Result<()> {
+        cached_call.last_activity = Utc::now();
+
+        match self {
+            Self::Memory(cache) => {
+                cache.lock().await.insert(call_sid.to_owned(), cached_call);
+                Ok(())
+            }
+            Self::Postgres(database) => database.upsert_cached_call(call_sid, &cached_call).await,
+        }
+    }
+
+    /// Applies `update` to the cached call with the given sid and stores the result.
+    /// Returns `None` if the call is not in the store, otherwise the value returned by `update`.
+    pub async fn update<T>(
+        &self,
+        call_sid: &str,
+        update: impl FnOnce(&mut CachedCall) -> T,
+    ) -> Result<Option<T>> {
+        let update = |cached_call: &mut CachedCall| {
+            cached_call.last_activity = Utc::now();
+            update(cached_call)
+        };
+
+        match self {
+            Self::Memory(cache) => Ok(cache.lock().await.get_mut(call_sid).map(update)),
+            Self::Postgres(database) => database.update_cached_call(call_sid, update).await,
+        }
+    }
+
+    /// Removes the cached call with the given sid from the store and returns it.
+    pub async fn remove(&self, call_sid: &str) -> Result<Option<CachedCall>> {
+        match self {
+            Self::Memory(cache) => Ok(cache.lock().await.remove(call_sid)),
+            Self::Postgres(database) => database.delete_cached_call(call_sid).await,
+        }
+    }
+
+    /// Lists all cached calls that have not been touched by a webhook since `inactive_since`.
+    pub async fn stale_calls(
+        &self,
+        inactive_since: DateTime<Utc>,
+    ) -> Result<Vec<(String, CachedCall)>> {
+        match self {
+            Self::Memory(cache) => Ok(cache
+                .lock()
+                .await
+                .iter()
+                .filter(|(_, cached_call)| cached_call.last_activity < inactive_since)
+                .map(|(call_sid, cached_call)| (call_sid.clone(), cached_call.clone()))
+                .collect()),
+            Self::Postgres(database) => database.get_stale_cached_calls(inactive_since).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedCall {
     pub name: String,
+    #[serde(default)]
+    pub phone_number: String,
     pub sponsor: Sponsor,
-    pub start: Instant,
+    pub start: DateTime<Utc>,
+    /// The last time the call was written to the store, i.e. the last webhook activity.
+    #[serde(default = "Utc::now")]
+    pub last_activity: DateTime<Utc>,
+    /// The moment the challenge timer runs out, set once the challenge has started.
+    /// Stored alongside the call so the challenge still ends if the timer task is lost.
+    pub challenge_end: Option<DateTime<Utc>>,
     pub messages: Vec<ChatCompletionRequestMessage>,
     pub timestamps: Vec<Timespan>,
 }
@@ -19,18 +117,21 @@ pub struct CachedMessage {
     pub timespan: Timespan,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Timespan {
-    pub start: Instant,
-    pub end: Instant,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
 }
 
 impl CachedCall {
-    pub fn new(sponsor: Sponsor) -> Self {
+    pub fn new(sponsor: Sponsor, phone_number: String) -> Self {
         Self {
             sponsor,
+            phone_number,
             name: String::new(),
-            start: Instant::now(),
+            start: Utc::now(),
+            last_activity: Utc::now(),
+            challenge_end: None,
             messages: Vec::new(),
             timestamps: Vec::new(),
         }
@@ -41,22 +142,28 @@ impl CachedCall {
     pub fn add_system_message(&mut self, message: ChatCompletionRequestMessage) {
         self.messages.push(message);
         self.timestamps.push(Timespan {
-            start: Instant::now(),
-            end: Instant::now(),
+            start: Utc::now(),
+            end: Utc::now(),
         });
     }
 
     /// Adds a user message to the conversation cache with the last message's end time
     /// as the start time of the new message and the current time as the end time.
     pub fn add_user_message(&mut self, message: ChatCompletionRequestMessage) {
+        self.add_user_message_until(message, Utc::now());
+    }
+
+    /// Adds a user message that ended at the given time, for messages that are stored
+    /// some time after the caller said them.
+    pub fn add_user_message_until(
+        &mut self,
+        message: ChatCompletionRequestMessage,
+        end: DateTime<Utc>,
+    ) {
         self.messages.push(message);
         self.timestamps.push(Timespan {
-            start: self
-                .timestamps
-                .last()
-                .map(|t| t.end)
-                .unwrap_or_else(Instant::now),
-            end: Instant::now(),
+            start: self.timestamps.last().map(|t| t.end).unwrap_or(end),
+            end,
         });
     }
 
@@ -65,7 +172,7 @@ impl CachedCall {
     /// instruction was a twilio `Say` verb.
     pub fn end_last_message(&mut self) {
         if let Some(timestamp) = self.timestamps.last_mut() {
-            timestamp.end = Instant::now();
+            timestamp.end = Utc::now();
         }
     }
 
@@ -83,6 +190,24 @@ impl CachedCall {
             .collect()
     }
 
+    /// Formats the audible conversation as a transcript with one line per message.
+    pub fn transcript(&self) -> String {
+        self.messages
+            .iter()
+            .filter_map(|message| {
+                let speaker = match message {
+                    ChatCompletionRequestMessage::User(_) => "Caller",
+                    ChatCompletionRequestMessage::Assistant(_) => "Host",
+                    _ => return None,
+                };
+
+                Self::extract_message_content(message)
+                    .map(|content| format!("{speaker}: {content}"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     /// Extracts the content of a message from the openai chat completion if the
     /// message is either a user or assistant message with text content. System messages
     /// are ignored, as they are not part of the (audible) conversation.
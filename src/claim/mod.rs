@@ -1,3 +1,4 @@
+use crate::secrets::Secrets;
 use axum::{
     middleware::{self},
     routing::get,
@@ -7,11 +8,17 @@ use std::sync::Arc;
 use tower_governor::{governor::GovernorConfig, GovernorLayer};
 
 mod page;
+mod submit;
 mod verify;
 
+/// How long winners have to claim their prize, unclaimed prizes return to the sponsor.
+pub fn claim_window(secrets: &Secrets) -> chrono::Duration {
+    chrono::Duration::days(secrets.claim_window_days)
+}
+
 pub fn router() -> Router {
     Router::new()
-        .route("/", get(page::page_handler))
+        .route("/", get(page::page_handler).post(submit::claim_handler))
         .layer(middleware::from_fn(verify::verify))
         .layer(GovernorLayer {
             config: Arc::new(GovernorConfig::default()),
@@ -1,13 +1,26 @@
 use crate::database::{Sponsor, Winner};
 use askama::Template;
 use axum::Extension;
+use chrono::Utc;
+
+use super::submit::claim_message;
 
 pub async fn page_handler(
     Extension(winner): Extension<Winner>,
     Extension(sponsor): Extension<Sponsor>,
 ) -> ClaimPage {
+    let expired = !winner
+        .expires_at
+        .is_some_and(|expires_at| expires_at > Utc::now());
+    let message = claim_message(&winner.key);
+
     // Render the html page with winner and sponsor info
-    ClaimPage { winner, sponsor }
+    ClaimPage {
+        winner,
+        sponsor,
+        expired,
+        message,
+    }
 }
 
 #[derive(Debug, Template)]
@@ -15,4 +28,8 @@ pub async fn page_handler(
 pub struct ClaimPage {
     winner: Winner,
     sponsor: Sponsor,
+    /// Whether the prize can not be claimed anymore
+    expired: bool,
+    /// The message the winner signs with their wallet
+    message: String,
 }
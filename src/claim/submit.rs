@@ -0,0 +1,144 @@
+use crate::{
+    database::{Database, Winner},
+    game::payout::process_payout,
+    solana::service::SolanaService,
+};
+use anyhow::{bail, Context, Result};
+use axum::{http::StatusCode, Extension, Json};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimRequest {
+    /// The base58 address of the wallet that receives the prize
+    wallet: String,
+    /// The base64 signature of the claim message by the wallet
+    signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClaimResponse {
+    wallet: String,
+}
+
+/// Claims the prize of the winner to their wallet, once they proved that they own it.
+pub async fn claim_handler(
+    Extension(database): Extension<Database>,
+    Extension(solana): Extension<SolanaService>,
+    Extension(winner): Extension<Winner>,
+    Json(request): Json<ClaimRequest>,
+) -> Result<Json<ClaimResponse>, (StatusCode, &'static str)> {
+    let message = claim_message(&winner.key);
+    if let Err(e) = verify_wallet_signature(&request.wallet, &message, &request.signature) {
+        log::debug!("Rejected claim of winner {}: {e:?}", winner.id);
+        return Err((StatusCode::BAD_REQUEST, "The wallet signature is invalid"));
+    }
+
+    let payout = match database.claim_prize(&winner.key, &request.wallet).await {
+        Ok(Some(payout)) => payout,
+        Ok(None) => {
+            return Err((
+                StatusCode::CONFLICT,
+                "The prize was already claimed or has expired",
+            ))
+        }
+        Err(e) => {
+            log::error!("Failed to claim prize of winner {}: {e:?}", winner.id);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to claim the prize",
+            ));
+        }
+    };
+
+    log::debug!(
+        "Winner {} claimed payout {} to {}",
+        winner.id,
+        payout.id,
+        request.wallet
+    );
+
+    // The payout worker retries the transfer if it fails now
+    tokio::spawn(async move {
+        let id = payout.id;
+        if let Err(e) = process_payout(&database, &solana, payout).await {
+            log::error!("Failed to send claimed payout {id}, retrying later: {e:?}");
+        }
+    });
+
+    Ok(Json(ClaimResponse {
+        wallet: request.wallet,
+    }))
+}
+
+/// The message winners sign to prove that they own the wallet, bound to their claim token.
+pub fn claim_message(key: &str) -> String {
+    format!("Claim my why.fun prize with claim code {key}")
+}
+
+/// Checks that the signature of the message was made by the wallet.
+fn verify_wallet_signature(wallet: &str, message: &str, signature: &str) -> Result<Pubkey> {
+    let wallet = Pubkey::from_str(wallet).context("Parsing wallet address")?;
+    let signature = general_purpose::STANDARD
+        .decode(signature)
+        .context("Decoding signature")?;
+    let signature = Signature::try_from(signature.as_slice()).context("Parsing signature")?;
+
+    if !signature.verify(wallet.as_ref(), message.as_bytes()) {
+        bail!("The signature does not match the wallet");
+    }
+
+    Ok(wallet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    fn sign(keypair: &Keypair, message: &str) -> String {
+        let signature = keypair.sign_message(message.as_bytes());
+        general_purpose::STANDARD.encode(signature.as_ref())
+    }
+
+    #[test]
+    fn accepts_signature_of_wallet() {
+        let keypair = Keypair::new();
+        let message = claim_message("key");
+
+        let wallet = verify_wallet_signature(
+            &keypair.pubkey().to_string(),
+            &message,
+            &sign(&keypair, &message),
+        )
+        .unwrap();
+        assert_eq!(wallet, keypair.pubkey());
+    }
+
+    #[test]
+    fn rejects_signature_of_other_wallet() {
+        let keypair = Keypair::new();
+        let message = claim_message("key");
+
+        let result = verify_wallet_signature(
+            &Keypair::new().pubkey().to_string(),
+            &message,
+            &sign(&keypair, &message),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_signature_of_other_claim() {
+        let keypair = Keypair::new();
+
+        let result = verify_wallet_signature(
+            &keypair.pubkey().to_string(),
+            &claim_message("key"),
+            &sign(&keypair, &claim_message("other key")),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{api::update_sponsor::UpdateSponsorArgs, secrets::Secrets};
+use crate::{api::{sponsor_diff::{diff_snapshots, sponsor_snapshot}, update_sponsor::UpdateSponsorArgs, RewardTierArgs}, cache::CachedCall, secrets::Secrets, solana::sponsor_key::SponsorKey};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::{postgres::PgPoolOptions, types::Json, PgConnection, PgPool};
 use serde::{Serialize, Deserialize};
 use crate::api::Attempt;
 
@@ -22,16 +22,31 @@ impl Database {
         Self { pool }
     }
 
+    /// Creates a database that never connects, every query fails after a short timeout.
+    /// Used to test how the webhooks handle database failures.
+    #[cfg(test)]
+    pub fn unreachable() -> Self {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(100))
+            .connect_lazy("postgres://postgres@127.0.0.1:1/gamecall")
+            .expect("Failed to create the database pool");
+
+        Self { pool }
+    }
+
     /// Gets a random sponsor from the database that meets these requirements:
     /// - The sponsor is active
-    /// - The sponsor has enough available tokens to reward the user
+    /// - The sponsor has enough available tokens to pay the highest reward tier
     pub async fn get_random_sponsor(&self) -> Result<Sponsor> {
         Ok(sqlx::query_as!(
             Sponsor,
             r#"
                 SELECT * FROM sponsors
                 WHERE active = true
-                AND available_tokens >= reward_tokens
+                AND available_tokens >= GREATEST(reward_tokens, (
+                    SELECT MAX(reward_tokens) FROM reward_tiers
+                    WHERE reward_tiers.sponsor_id = sponsors.id
+                ))
                 ORDER BY RANDOM()
                 LIMIT 1
             "#
@@ -54,6 +69,28 @@ impl Database {
         .await?)
     }
 
+    /// Gets the sponsor with the given token mint and name, the way attempts from before the
+    /// sponsor id was recorded on them refer to their sponsor.
+    /// Returns `None` if no sponsor or more than one sponsor matches.
+    pub async fn get_sponsor_by_token_mint_and_name(
+        &self,
+        token_mint: &str,
+        name: &str,
+    ) -> Result<Option<Sponsor>> {
+        Ok(sqlx::query_as!(
+            Sponsor,
+            r#"
+                SELECT * FROM sponsors
+                WHERE token_mint = $1 AND name = $2
+                AND (SELECT COUNT(*) FROM sponsors WHERE token_mint = $1 AND name = $2) = 1
+            "#,
+            token_mint,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
 
     /// Gets the sponsor with the given ID from the database.
     pub async fn get_sponsor_by_public_key(&self, public_key: String) -> Result<Sponsor> {
@@ -85,23 +122,6 @@ impl Database {
 
 
 
-    /// Creates a new winner in the database with the given name and sponsor ID.
-    /// Uses a random UUID as the private key that the user can use to claim their reward.
-    pub async fn create_winner(&self, name: String, sponsor_id: i32) -> Result<Winner> {
-        Ok(sqlx::query_as!(
-            Winner,
-            r#"
-                INSERT INTO winners (key, name, sponsor_id)
-                VALUES (gen_random_uuid(), $1, $2)
-                RETURNING *
-            "#,
-            name,
-            sponsor_id
-        )
-        .fetch_one(&self.pool)
-        .await?)
-    }
-
     /// Gets the winner with the given key from the database.
     /// Returns `None` if there is no winner with the given key.
     pub async fn get_winner_by_key(&self, key: &str) -> Result<Option<Winner>> {
@@ -237,203 +257,437 @@ impl Database {
     }
 
 
-    /// Withdraws the reward tokens from the sponsor with the given ID.
-    /// Returns an error if there was a communication error with the database.
+    /// Withdraws the amount of the prize and the whole jackpot from the sponsor with the given ID
+    /// and records the unclaimed payout and the winner of the call in the same transaction, so
+    /// withdrawn tokens are never lost.
+    /// The winner's random key is the claim token the caller uses to claim the payout.
+    /// Returns an error if there was a communication error with the database or the call
+    /// already has a payout, in which case nothing is withdrawn.
     /// Returns `None` if the sponsor does not have enough available tokens to withdraw.
-    /// Returns the amount of withdrawn tokens if the sponsor has enough available tokens.
-    pub async fn withdraw_tokens(&self, sponsor_id: i32) -> Result<Option<WithdrawnTokens>> {
-        Ok(sqlx::query_as!(
+    pub async fn reserve_prize(
+        &self,
+        call_sid: &str,
+        sponsor_id: i32,
+        name: &str,
+        amount: i64,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<ReservedPrize>> {
+        let mut transaction = self.pool.begin().await?;
+
+        // The pot is locked before it is read, so no contribution is lost while it is emptied
+        let withdrawn = sqlx::query_as!(
             WithdrawnTokens,
             r#"
                 UPDATE sponsors
-                SET available_tokens = available_tokens - reward_tokens
+                SET available_tokens = sponsors.available_tokens - $2, jackpot_tokens = 0
+                FROM (SELECT id, jackpot_tokens FROM sponsors WHERE id = $1 FOR UPDATE) AS pot
+                WHERE sponsors.id = pot.id
+                AND sponsors.available_tokens >= $2
+                RETURNING $2 + pot.jackpot_tokens AS "amount!"
+            "#,
+            sponsor_id,
+            amount
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(withdrawn) = withdrawn else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+                INSERT INTO payouts (attempt_id, call_sid, sponsor_id, amount, status)
+                VALUES ((SELECT id FROM attempts WHERE call_sid = $1), $1, $2, $3, 'unclaimed')
+            "#,
+            call_sid,
+            sponsor_id,
+            withdrawn.amount
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let winner = sqlx::query_as!(
+            Winner,
+            r#"
+                INSERT INTO winners (key, name, sponsor_id, call_sid, expires_at)
+                VALUES (gen_random_uuid(), $1, $2, $3, $4)
+                RETURNING *
+            "#,
+            name,
+            sponsor_id,
+            call_sid,
+            expires_at
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Some(ReservedPrize {
+            winner,
+            amount: withdrawn.amount,
+        }))
+    }
+
+    /// Moves the jackpot contribution of the sponsor from their pool into their jackpot for
+    /// the lost call. A call contributes at most once, and only if the pool can still pay
+    /// out the highest prize afterwards.
+    /// Returns the new jackpot, or `None` if nothing was contributed.
+    pub async fn contribute_to_jackpot(&self, call_sid: &str, sponsor_id: i32) -> Result<Option<i64>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let Some(contribution) = sqlx::query!(
+            r#"
+                UPDATE sponsors
+                SET available_tokens = available_tokens - jackpot_contribution,
+                    jackpot_tokens = jackpot_tokens + jackpot_contribution
                 WHERE id = $1
-                AND available_tokens >= reward_tokens
-                RETURNING reward_tokens AS amount
+                AND jackpot
+                AND jackpot_contribution > 0
+                AND available_tokens >= jackpot_contribution + GREATEST(reward_tokens, (
+                    SELECT MAX(reward_tokens) FROM reward_tiers
+                    WHERE reward_tiers.sponsor_id = sponsors.id
+                ))
+                RETURNING jackpot_contribution, jackpot_tokens
             "#,
             sponsor_id
         )
-        .fetch_optional(&self.pool)
-        .await?)
+        .fetch_optional(&mut *transaction)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let recorded = sqlx::query!(
+            r#"
+                INSERT INTO jackpot_contributions (call_sid, sponsor_id, amount)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (call_sid) DO NOTHING
+            "#,
+            call_sid,
+            sponsor_id,
+            contribution.jackpot_contribution
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        // The call already contributed, the transaction is rolled back when dropped
+        if recorded.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        transaction.commit().await?;
+
+        Ok(Some(contribution.jackpot_tokens))
     }
 
-    /// Gets the user with the given phone number from the database. Creates a new user if the
-    /// user does not yet exist in the database.
-    pub async fn get_or_insert_user_by_phone_number(&self, phone_number: &str) -> Result<User> {
+    /// Gets the current jackpot of every sponsor in jackpot mode.
+    pub async fn get_jackpots(&self) -> Result<Vec<Jackpot>> {
         Ok(sqlx::query_as!(
-            User,
+            Jackpot,
             r#"
-                INSERT INTO users (phone_number, attempts_today, last_attempt, banned)
-                VALUES ($1, 1, now(), false)
-                ON CONFLICT (phone_number) DO UPDATE
-                SET attempts_today = users.attempts_today + 1
-                RETURNING *
-                
-            "#,
-            phone_number
+                SELECT id AS sponsor_id, jackpot_tokens FROM sponsors
+                WHERE jackpot
+            "#
         )
-        .fetch_one(&self.pool)
+        .fetch_all(&self.pool)
         .await?)
     }
 
-    pub async fn update_user(&self, user: &User) -> Result<()> {
-        sqlx::query!(
+    /// Claims the prize of the winner with the given key to the wallet, the payout is sent
+    /// by the payout worker from then on.
+    /// Returns `None` if the prize was already claimed or expired.
+    pub async fn claim_prize(&self, key: &str, wallet: &str) -> Result<Option<Payout>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let winner = sqlx::query_as!(
+            Winner,
             r#"
-                UPDATE users
-                SET attempts_today = $1, last_attempt = now(), banned = $2
-                WHERE phone_number = $3
+                UPDATE winners
+                SET claimed_at = now(), wallet = $1
+                WHERE key = $2
+                AND claimed_at IS NULL
+                AND expires_at > now()
+                RETURNING *
             "#,
-            user.attempts_today,
-            user.banned,
-            user.phone_number
+            wallet,
+            key
         )
-        .execute(&self.pool)
+        .fetch_optional(&mut *transaction)
         .await?;
 
-        Ok(())
+        let Some(winner) = winner else {
+            return Ok(None);
+        };
+
+        // The prize may have expired between the two updates
+        let payout = sqlx::query_as!(
+            Payout,
+            r#"
+                UPDATE payouts
+                SET status = 'pending', receiver_pubkey = $1, updated_at = now()
+                WHERE call_sid = $2 AND status = 'unclaimed'
+                RETURNING *
+            "#,
+            wallet,
+            winner.call_sid
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        if payout.is_some() {
+            transaction.commit().await?;
+        }
+
+        Ok(payout)
     }
 
-     /// Creates a new sponsor in the database.
-    pub async fn create_sponsor(&self, sponsor: Sponsor) -> Result<Sponsor> {
+    /// Expires the unclaimed prizes whose claim window passed and returns their tokens
+    /// to the sponsors' pools. Returns the refunded tokens of every sponsor.
+    pub async fn expire_unclaimed_prizes(&self) -> Result<Vec<PrizeRefund>> {
         Ok(sqlx::query_as!(
-            Sponsor,
+            PrizeRefund,
             r#"
-                INSERT INTO sponsors (
-                name,
-                user_id,
-                active, 
-                background_url, 
-                private_key,
-                public_key,
-                token_mint, 
-                original_tokens, 
-                available_tokens, 
-                reward_tokens, 
-                challenge_time,
-                system_instruction,
-                greeting_text, 
-                challenge_text,
-                start_text, 
-                end_text,
-                won_text,
-                lost_text,
-                rating_threshold
-            )
-                VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19
+                WITH expired AS (
+                    UPDATE payouts
+                    SET status = 'expired', updated_at = now()
+                    WHERE status = 'unclaimed'
+                    AND call_sid IN (
+                        SELECT call_sid FROM winners
+                        WHERE claimed_at IS NULL AND expires_at < now()
+                    )
+                    RETURNING call_sid, sponsor_id, amount
+                ), swept AS (
+                    UPDATE attempts
+                    SET prize_swept_at = now(), prize_swept_tokens = expired.amount
+                    FROM expired
+                    WHERE attempts.call_sid = expired.call_sid
+                ), refunds AS (
+                    SELECT sponsor_id, SUM(amount)::BIGINT AS amount, COUNT(*) AS prizes
+                    FROM expired
+                    GROUP BY sponsor_id
                 )
-                RETURNING *
+                UPDATE sponsors
+                SET available_tokens = available_tokens + refunds.amount
+                FROM refunds
+                WHERE sponsors.id = refunds.sponsor_id
+                RETURNING sponsors.id AS sponsor_id, refunds.amount AS "amount!", refunds.prizes AS "prizes!"
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Gets the won attempts from before the claim tokens whose prize is still in the generated
+    /// keypair from the winner url and were not claimed since the given time.
+    pub async fn get_unswept_legacy_prizes(&self, won_before: DateTime<Utc>) -> Result<Vec<Attempt>> {
+        Ok(sqlx::query_as!(
+            Attempt,
+            r#"
+                SELECT * FROM attempts
+                WHERE is_winner = true
+                AND winner_url LIKE '%key=%'
+                AND prize_swept_at IS NULL
+                AND created_at < $1
+                AND NOT EXISTS (SELECT 1 FROM winners WHERE winners.call_sid = attempts.call_sid)
+                ORDER BY created_at
             "#,
-            sponsor.name,
-            sponsor.user_id,
-            sponsor.active,
-            sponsor.background_url,
-            sponsor.private_key,
-            sponsor.public_key,
-            sponsor.token_mint,
-            sponsor.original_tokens,
-            sponsor.available_tokens,
-            sponsor.reward_tokens,
-            sponsor.challenge_time,
-            sponsor.system_instruction,
-            sponsor.greeting_text,
-            sponsor.challenge_text,
-            sponsor.start_text,
-            sponsor.end_text,
-            sponsor.won_text,
-            sponsor.lost_text,
-            sponsor.rating_threshold
+            won_before
         )
-        .fetch_one(&self.pool)
+        .fetch_all(&self.pool)
         .await?)
     }
 
-    pub async fn update_sponsor_to_active(&self, sponsor_public_key: String) -> Result<()> {
+    /// Stores the signature of the transfer that sweeps the prize of the attempt and the
+    /// tokens it moves before it is sent. `previous_signature` is the signature the attempt
+    /// had when the transfer was signed.
+    /// Returns `false` if another sweep was recorded since, the transfer must not be sent then.
+    pub async fn record_prize_sweep_signature(
+        &self,
+        call_sid: &str,
+        previous_signature: Option<&str>,
+        signature: &str,
+        tokens: i64,
+        last_valid_block_height: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET prize_sweep_signature = $1,
+                    prize_swept_tokens = $2,
+                    prize_sweep_last_valid_block_height = $3
+                WHERE call_sid = $4
+                AND prize_swept_at IS NULL
+                AND prize_sweep_signature IS NOT DISTINCT FROM $5
+            "#,
+            signature,
+            tokens,
+            last_valid_block_height,
+            call_sid,
+            previous_signature
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records the sweep of an unclaimed prize on the attempt once it landed and returns the
+    /// swept tokens to the sponsor's pool. A prize is only returned once.
+    pub async fn record_prize_sweep(&self, call_sid: &str, sponsor_id: i32, tokens: i64) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let swept = sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET prize_swept_at = now(), prize_swept_tokens = $1
+                WHERE call_sid = $2 AND prize_swept_at IS NULL
+            "#,
+            tokens,
+            call_sid
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if swept.rows_affected() == 0 {
+            return Ok(());
+        }
+
         sqlx::query!(
             r#"
                 UPDATE sponsors
-                SET active = true, initial_funded = true
-                WHERE public_key = $1
+                SET available_tokens = available_tokens + $1
+                WHERE id = $2
             "#,
-            sponsor_public_key
+            tokens,
+            sponsor_id
         )
-        .execute(&self.pool)
+        .execute(&mut *transaction)
         .await?;
 
+        transaction.commit().await?;
+
         Ok(())
     }
 
+    /// Gets the payout of the call with the given sid.
+    /// Returns `None` if the call has no payout.
+    pub async fn get_payout_by_call_sid(&self, call_sid: &str) -> Result<Option<Payout>> {
+        Ok(sqlx::query_as!(
+            Payout,
+            r#"
+                SELECT * FROM payouts
+                WHERE call_sid = $1
+            "#,
+            call_sid
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
 
-    pub async fn update_sponsor(&self, update_sponsor: UpdateSponsorArgs) -> Result<Sponsor> {
+    /// Gets the payouts that are not confirmed or abandoned yet, the oldest first.
+    pub async fn get_unsettled_payouts(&self) -> Result<Vec<Payout>> {
         Ok(sqlx::query_as!(
-            Sponsor,
+            Payout,
             r#"
-                UPDATE sponsors
-                SET name = $1, active = $2, background_url = $3, challenge_time = $4, system_instruction = $5, start_text = $6, rating_threshold = $7, challenge_text = $8
-                WHERE public_key = $9
+                SELECT * FROM payouts
+                WHERE status IN ('pending', 'signing', 'sent')
+                ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Claims a pending payout for signing, so only one instance signs and sends it. A claim
+    /// that was not followed by a signature since `stale_before` is taken over, nothing was
+    /// sent for it. The `updated_at` of the returned payout identifies the claim.
+    /// Returns `None` if the payout is not pending or claimed by another instance.
+    pub async fn claim_payout(&self, id: i32, stale_before: DateTime<Utc>) -> Result<Option<Payout>> {
+        Ok(sqlx::query_as!(
+            Payout,
+            r#"
+                UPDATE payouts
+                SET status = 'signing', updated_at = now()
+                WHERE id = $1
+                AND (status = 'pending' OR (status = 'signing' AND updated_at < $2))
                 RETURNING *
             "#,
-            update_sponsor.name,
-            update_sponsor.active,
-            update_sponsor.background_url,
-            update_sponsor.challenge_time,
-            update_sponsor.system_instruction,
-            update_sponsor.start_text,
-            update_sponsor.rating_threshold,
-            update_sponsor.challenge_text,
-            update_sponsor.public_key
+            id,
+            stale_before
         )
-        .fetch_one(&self.pool)
+        .fetch_optional(&self.pool)
         .await?)
     }
 
-
-    /// Creates a new attempt in the database.
-    pub async fn create_attempt_with_sponsor(&self, user: &User, sponsor: &Sponsor, call_sid: String) -> Result<()> {
-        sqlx::query!(
+    /// Stores the signature of a transfer before it is sent, so a transfer that
+    /// may have landed is never sent a second time. `claimed_at` is the `updated_at` of
+    /// the claimed payout.
+    /// Returns `false` if the claim was taken over, the transfer must not be sent then.
+    pub async fn record_payout_signature(
+        &self,
+        id: i32,
+        claimed_at: DateTime<Utc>,
+        signature: &str,
+        last_valid_block_height: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
             r#"
-                INSERT INTO attempts (
-                phone_number,
-                sponsor_question,
-                sponsor_name,
-                sponsor_token_mint,
-                sponsor_total_reward,
-                sponsor_attempt_reward,
-                sponsor_background_url,
-                sponsor_challenge_time,
-                call_sid
-            )
-                VALUES (
-                    $1, $2, $3, $4, $5, $6, $7, $8, $9
-                )
+                UPDATE payouts
+                SET status = 'sent', signature = $1, last_valid_block_height = $2, updated_at = now()
+                WHERE id = $3 AND status = 'signing' AND updated_at = $4
             "#,
-            user.phone_number,
-            sponsor.challenge_text,
-            sponsor.name,
-            sponsor.token_mint,
-            sponsor.original_tokens,
-            sponsor.reward_tokens,
-            sponsor.background_url,
-            sponsor.challenge_time,
-            call_sid
+            signature,
+            last_valid_block_height,
+            id,
+            claimed_at
         )
         .execute(&self.pool)
         .await?;
 
-        Ok(())  
+        Ok(result.rows_affected() > 0)
     }
 
+    /// Records a failed transfer, the payout is sent again with a new signature.
+    /// `updated_at` is the one of the payout the failure was seen on.
+    /// Returns the number of failed transfers of the payout, or `None` if the payout
+    /// changed since, in which case another instance handled it.
+    pub async fn record_payout_failure(
+        &self,
+        id: i32,
+        updated_at: DateTime<Utc>,
+        error: &str,
+    ) -> Result<Option<i32>> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                UPDATE payouts
+                SET status = 'pending',
+                    signature = NULL,
+                    last_valid_block_height = NULL,
+                    retries = retries + 1,
+                    last_error = $1,
+                    updated_at = now()
+                WHERE id = $2 AND status IN ('signing', 'sent') AND updated_at = $3
+                RETURNING retries
+            "#,
+            error,
+            id,
+            updated_at
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
 
-    pub async fn update_attempt_video(&self, caller_phone_number: String, video_url: String, call_sid: String) -> Result<()> {
+    /// Marks the payout as confirmed once its transfer landed.
+    pub async fn confirm_payout(&self, id: i32) -> Result<()> {
         sqlx::query!(
             r#"
-                UPDATE attempts
-                SET video_url = $1
-                WHERE phone_number = $2 AND call_sid = $3
+                UPDATE payouts
+                SET status = 'confirmed', last_error = NULL, updated_at = now()
+                WHERE id = $1
             "#,
-            video_url,
-            caller_phone_number,
-            call_sid
+            id
         )
         .execute(&self.pool)
         .await?;
@@ -441,14 +695,792 @@ impl Database {
         Ok(())
     }
 
-    pub async fn update_attempt_winner(&self, caller_phone_number: String, is_winner: bool, call_sid: String) -> Result<()> {
+    /// Stores the metadata of the NFT that is minted for the winner of the call once they
+    /// claim it. A win that is handled again keeps its first metadata.
+    pub async fn create_nft_prize(&self, prize: &NftPrize) -> Result<()> {
         sqlx::query!(
             r#"
-                UPDATE attempts
-                SET is_winner = $1
-                WHERE phone_number = $2 AND call_sid = $3
+                INSERT INTO nft_prizes (
+                    call_sid, sponsor_id, name, symbol, uri, caller_name, sponsor_name,
+                    challenge, rating, video_url, image_url
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT (call_sid) DO NOTHING
             "#,
-            is_winner,
+            prize.call_sid,
+            prize.sponsor_id,
+            prize.name,
+            prize.symbol,
+            prize.uri,
+            prize.caller_name,
+            prize.sponsor_name,
+            prize.challenge,
+            prize.rating,
+            prize.video_url,
+            prize.image_url
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the NFT prize of the call.
+    pub async fn get_nft_prize(&self, call_sid: &str) -> Result<Option<NftPrize>> {
+        Ok(sqlx::query_as!(
+            NftPrize,
+            r#"
+                SELECT * FROM nft_prizes
+                WHERE call_sid = $1
+            "#,
+            call_sid
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Records the mint of the NFT prize of the call before the transaction minting it is sent.
+    pub async fn record_nft_mint(&self, call_sid: &str, mint: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE nft_prizes
+                SET mint = $1
+                WHERE call_sid = $2
+            "#,
+            mint,
+            call_sid
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the rating of the call, the rounded average of its judgements.
+    pub async fn get_call_rating(&self, call_sid: &str) -> Result<Option<i32>> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                SELECT ROUND(AVG(rating))::INT FROM judgements
+                WHERE call_sid = $1
+            "#,
+            call_sid
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Gets the reward tiers of the sponsor, the lowest rating first.
+    pub async fn get_reward_tiers(&self, sponsor_id: i32) -> Result<Vec<RewardTier>> {
+        Ok(sqlx::query_as!(
+            RewardTier,
+            r#"
+                SELECT * FROM reward_tiers
+                WHERE sponsor_id = $1
+                ORDER BY min_rating
+            "#,
+            sponsor_id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Gets the reward tiers of all the sponsors at once, ordered by sponsor and rating.
+    pub async fn get_reward_tiers_of_sponsors(&self, sponsor_ids: &[i32]) -> Result<Vec<RewardTier>> {
+        Ok(sqlx::query_as!(
+            RewardTier,
+            r#"
+                SELECT * FROM reward_tiers
+                WHERE sponsor_id = ANY($1)
+                ORDER BY sponsor_id, min_rating
+            "#,
+            sponsor_ids
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Replaces the reward tiers of the sponsor in a single transaction, so wins never see
+    /// a partial set of tiers.
+    pub async fn set_reward_tiers(
+        &self,
+        sponsor_id: i32,
+        tiers: &[RewardTierArgs],
+    ) -> Result<Vec<RewardTier>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let created = Self::replace_reward_tiers(&mut transaction, sponsor_id, tiers).await?;
+
+        transaction.commit().await?;
+
+        Ok(created)
+    }
+
+    async fn replace_reward_tiers(
+        connection: &mut PgConnection,
+        sponsor_id: i32,
+        tiers: &[RewardTierArgs],
+    ) -> Result<Vec<RewardTier>> {
+        sqlx::query!(
+            r#"
+                DELETE FROM reward_tiers
+                WHERE sponsor_id = $1
+            "#,
+            sponsor_id
+        )
+        .execute(&mut *connection)
+        .await?;
+
+        let mut created = Vec::with_capacity(tiers.len());
+        for tier in tiers {
+            created.push(
+                sqlx::query_as!(
+                    RewardTier,
+                    r#"
+                        INSERT INTO reward_tiers (sponsor_id, min_rating, reward_tokens, name)
+                        VALUES ($1, $2, $3, $4)
+                        RETURNING *
+                    "#,
+                    sponsor_id,
+                    tier.min_rating,
+                    tier.reward_tokens,
+                    tier.name.trim()
+                )
+                .fetch_one(&mut *connection)
+                .await?,
+            );
+        }
+
+        created.sort_by_key(|tier| tier.min_rating);
+        Ok(created)
+    }
+
+    async fn get_reward_tiers_for_update(
+        connection: &mut PgConnection,
+        sponsor_id: i32,
+    ) -> Result<Vec<RewardTier>> {
+        Ok(sqlx::query_as!(
+            RewardTier,
+            r#"
+                SELECT * FROM reward_tiers
+                WHERE sponsor_id = $1
+                ORDER BY min_rating
+            "#,
+            sponsor_id
+        )
+        .fetch_all(&mut *connection)
+        .await?)
+    }
+
+    /// Gives up on an unsettled payout and refunds its tokens to the sponsor.
+    /// Returns `false` if the payout was already settled.
+    pub async fn abandon_payout(&self, id: i32) -> Result<bool> {
+        let mut transaction = self.pool.begin().await?;
+
+        let abandoned = sqlx::query_as!(
+            WithdrawnTokens,
+            r#"
+                UPDATE payouts
+                SET status = 'abandoned', updated_at = now()
+                WHERE id = $1 AND status IN ('pending', 'signing', 'sent')
+                RETURNING amount
+            "#,
+            id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(abandoned) = abandoned else {
+            return Ok(false);
+        };
+
+        sqlx::query!(
+            r#"
+                UPDATE sponsors
+                SET available_tokens = available_tokens + $1
+                WHERE id = (SELECT sponsor_id FROM payouts WHERE id = $2)
+            "#,
+            abandoned.amount,
+            id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Gets the user with the given phone number from the database. Creates a new user if the
+    /// user does not yet exist in the database.
+    pub async fn get_or_insert_user_by_phone_number(&self, phone_number: &str) -> Result<User> {
+        Ok(sqlx::query_as!(
+            User,
+            r#"
+                INSERT INTO users (phone_number, attempts_today, last_attempt, banned)
+                VALUES ($1, 1, now(), false)
+                ON CONFLICT (phone_number) DO UPDATE
+                SET attempts_today = users.attempts_today + 1
+                RETURNING *
+                
+            "#,
+            phone_number
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    pub async fn update_user(&self, user: &User) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE users
+                SET attempts_today = $1, last_attempt = now(), banned = $2
+                WHERE phone_number = $3
+            "#,
+            user.attempts_today,
+            user.banned,
+            user.phone_number
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+     /// Creates a new sponsor in the database.
+    pub async fn create_sponsor(&self, sponsor: Sponsor) -> Result<Sponsor> {
+        Ok(sqlx::query_as!(
+            Sponsor,
+            r#"
+                INSERT INTO sponsors (
+                name,
+                user_id,
+                active, 
+                background_url, 
+                private_key,
+                public_key,
+                token_mint, 
+                original_tokens, 
+                available_tokens, 
+                reward_tokens, 
+                challenge_time,
+                system_instruction,
+                greeting_text, 
+                challenge_text,
+                start_text, 
+                end_text,
+                won_text,
+                lost_text,
+                rating_threshold,
+                judge_abandoned,
+                llm_provider,
+                judging_mode,
+                judge_count,
+                judge_quorum,
+                guard_action,
+                payout_approval_threshold,
+                prize_kind,
+                token_decimals,
+                jackpot,
+                jackpot_contribution
+            )
+                VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30
+                )
+                RETURNING *
+            "#,
+            sponsor.name,
+            sponsor.user_id,
+            sponsor.active,
+            sponsor.background_url,
+            sponsor.private_key.as_sealed(),
+            sponsor.public_key,
+            sponsor.token_mint,
+            sponsor.original_tokens,
+            sponsor.available_tokens,
+            sponsor.reward_tokens,
+            sponsor.challenge_time,
+            sponsor.system_instruction,
+            sponsor.greeting_text,
+            sponsor.challenge_text,
+            sponsor.start_text,
+            sponsor.end_text,
+            sponsor.won_text,
+            sponsor.lost_text,
+            sponsor.rating_threshold,
+            sponsor.judge_abandoned,
+            sponsor.llm_provider,
+            sponsor.judging_mode,
+            sponsor.judge_count,
+            sponsor.judge_quorum,
+            sponsor.guard_action,
+            sponsor.payout_approval_threshold,
+            sponsor.prize_kind,
+            sponsor.token_decimals,
+            sponsor.jackpot,
+            sponsor.jackpot_contribution
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Gets the wallet keys of all sponsors, to seal or rotate them.
+    pub async fn get_sponsor_keys(&self) -> Result<Vec<(i32, SponsorKey)>> {
+        Ok(sqlx::query!(
+            r#"
+                SELECT id, private_key FROM sponsors
+                ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.id, SponsorKey::from(row.private_key)))
+        .collect())
+    }
+
+    /// Replaces the wallet key of the sponsor with a newly sealed one.
+    pub async fn update_sponsor_key(&self, id: i32, private_key: &SponsorKey) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE sponsors
+                SET private_key = $1
+                WHERE id = $2
+            "#,
+            private_key.as_sealed(),
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Activates the sponsor after their initial deposit landed.
+    /// Returns `None` if another deposit funded the sponsor first.
+    pub async fn update_sponsor_to_active(&self, sponsor_public_key: String) -> Result<Option<Sponsor>> {
+        Ok(sqlx::query_as!(
+            Sponsor,
+            r#"
+                UPDATE sponsors
+                SET active = true, initial_funded = true
+                WHERE public_key = $1
+                AND initial_funded = false
+                RETURNING *
+            "#,
+            sponsor_public_key
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Reserves the signature of a payment or deposit, so the same transaction can not be
+    /// used to create or fund a sponsor twice.
+    /// Returns `false` if the signature was already reserved.
+    pub async fn reserve_signature(
+        &self,
+        signature: &str,
+        kind: &str,
+        sponsor_id: Option<i32>,
+        amount: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO processed_signatures (signature, kind, sponsor_id, amount)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (signature) DO NOTHING
+            "#,
+            signature,
+            kind,
+            sponsor_id,
+            amount
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Releases a reserved signature whose transaction did not land, so it can be retried.
+    pub async fn release_signature(&self, signature: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+                DELETE FROM processed_signatures
+                WHERE signature = $1
+            "#,
+            signature
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Issues a sign-in nonce for the wallet, nonces that expired are removed on the way.
+    pub async fn create_auth_nonce(
+        &self,
+        public_key: &str,
+        domain: &str,
+        issued_at: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthNonce> {
+        sqlx::query!(
+            r#"
+                DELETE FROM auth_nonces
+                WHERE expires_at < now()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(sqlx::query_as!(
+            AuthNonce,
+            r#"
+                INSERT INTO auth_nonces (nonce, public_key, domain, issued_at, expires_at)
+                VALUES (replace(gen_random_uuid()::TEXT, '-', ''), $1, $2, $3, $4)
+                RETURNING *
+            "#,
+            public_key,
+            domain,
+            issued_at,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Uses up the sign-in nonce of the wallet, so a signed message can not be replayed.
+    /// Returns `None` if the nonce is unknown, expired, already used or was issued to another wallet.
+    pub async fn use_auth_nonce(&self, nonce: &str, public_key: &str) -> Result<Option<AuthNonce>> {
+        Ok(sqlx::query_as!(
+            AuthNonce,
+            r#"
+                UPDATE auth_nonces
+                SET used_at = now()
+                WHERE nonce = $1
+                AND public_key = $2
+                AND used_at IS NULL
+                AND expires_at > now()
+                RETURNING *
+            "#,
+            nonce,
+            public_key
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Adds the tokens of a verified top-up to the pool of the sponsor.
+    pub async fn add_sponsor_tokens(&self, sponsor_id: i32, amount: i64) -> Result<Sponsor> {
+        Ok(sqlx::query_as!(
+            Sponsor,
+            r#"
+                UPDATE sponsors
+                SET available_tokens = available_tokens + $2
+                WHERE id = $1
+                RETURNING *
+            "#,
+            sponsor_id,
+            amount
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    pub async fn deactivate_sponsor(&self, sponsor_id: i32) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE sponsors
+                SET active = false
+                WHERE id = $1
+            "#,
+            sponsor_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deactivates the sponsor and empties their pool and jackpot before a withdrawal, so no
+    /// call can win the tokens that are withdrawn. Prizes that are reserved at the same time
+    /// hold the lock of the sponsor and are waited for.
+    /// Returns the number of tokens of the sponsor that are owed to winners: prizes that are
+    /// not paid out yet and wins that are waiting for approval.
+    pub async fn empty_pool_for_withdrawal(&self, sponsor_id: i32) -> Result<i64> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+                UPDATE sponsors
+                SET active = false, available_tokens = 0, jackpot_tokens = 0
+                WHERE id = $1
+            "#,
+            sponsor_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let reserved = sqlx::query_scalar!(
+            r#"
+                SELECT (
+                    COALESCE((
+                        SELECT SUM(amount) FROM payouts
+                        WHERE sponsor_id = $1
+                        AND status IN ('unclaimed', 'pending', 'signing', 'sent')
+                    ), 0)
+                    + COALESCE((
+                        SELECT SUM(reward_tokens) FROM pending_payouts
+                        WHERE sponsor_id = $1
+                        AND status IN ('pending', 'approving')
+                    ), 0)
+                )::BIGINT AS "reserved!"
+            "#,
+            sponsor_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(reserved)
+    }
+
+    /// Sets the pool of the sponsor to what is left in their wallet after a withdrawal,
+    /// minus the prizes that still have to be paid out from it. The jackpot was withdrawn
+    /// with the pool, see [`Database::empty_pool_for_withdrawal`].
+    pub async fn record_withdrawal(&self, sponsor_id: i32, remaining_tokens: i64) -> Result<Sponsor> {
+        Ok(sqlx::query_as!(
+            Sponsor,
+            r#"
+                UPDATE sponsors
+                SET available_tokens = $2::BIGINT - COALESCE((
+                    SELECT SUM(amount) FROM payouts
+                    WHERE sponsor_id = $1
+                    AND status IN ('unclaimed', 'pending', 'signing', 'sent')
+                ), 0), jackpot_tokens = 0
+                WHERE id = $1
+                RETURNING *
+            "#,
+            sponsor_id,
+            remaining_tokens
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+
+    /// Changes the fields of the sponsor that are set in the update and replaces its reward tiers
+    /// if they are set. What changed is written to the audit log with the wallet that changed it
+    /// and the new settings become the next version of the sponsor, in the same transaction.
+    pub async fn update_sponsor(
+        &self,
+        sponsor_id: i32,
+        update_sponsor: &UpdateSponsorArgs,
+        actor: &str,
+    ) -> Result<(Sponsor, Vec<RewardTier>)> {
+        let mut transaction = self.pool.begin().await?;
+
+        let before = sqlx::query_as!(
+            Sponsor,
+            r#"
+                SELECT * FROM sponsors
+                WHERE id = $1
+                FOR UPDATE
+            "#,
+            sponsor_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        let tiers_before = Self::get_reward_tiers_for_update(&mut transaction, sponsor_id).await?;
+
+        let after = sqlx::query_as!(
+            Sponsor,
+            r#"
+                UPDATE sponsors
+                SET name = COALESCE($2, name),
+                    active = COALESCE($3, active),
+                    background_url = COALESCE($4, background_url),
+                    challenge_time = COALESCE($5, challenge_time),
+                    system_instruction = COALESCE($6, system_instruction),
+                    start_text = COALESCE($7, start_text),
+                    rating_threshold = COALESCE($8, rating_threshold),
+                    challenge_text = COALESCE($9, challenge_text),
+                    greeting_text = COALESCE($10, greeting_text),
+                    end_text = COALESCE($11, end_text),
+                    won_text = COALESCE($12, won_text),
+                    lost_text = COALESCE($13, lost_text)
+                WHERE id = $1
+                RETURNING *
+            "#,
+            sponsor_id,
+            update_sponsor.name,
+            update_sponsor.active,
+            update_sponsor.background_url,
+            update_sponsor.challenge_time,
+            update_sponsor.system_instruction,
+            update_sponsor.start_text,
+            update_sponsor.rating_threshold,
+            update_sponsor.challenge_text,
+            update_sponsor.greeting_text,
+            update_sponsor.end_text,
+            update_sponsor.won_text,
+            update_sponsor.lost_text
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        let tiers_after = match &update_sponsor.reward_tiers {
+            Some(tiers) => Self::replace_reward_tiers(&mut transaction, sponsor_id, tiers).await?,
+            None => tiers_before.clone(),
+        };
+
+        let settings = sponsor_snapshot(&after, &tiers_after);
+        let changes = diff_snapshots(&sponsor_snapshot(&before, &tiers_before), &settings);
+
+        if !changes.is_empty() {
+            Self::insert_sponsor_version(&mut transaction, sponsor_id, &settings, actor).await?;
+
+            sqlx::query!(
+                r#"
+                    INSERT INTO sponsor_audit_log (sponsor_id, actor, changes)
+                    VALUES ($1, $2, $3)
+                "#,
+                sponsor_id,
+                actor,
+                Json(&changes) as _
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok((after, tiers_after))
+    }
+
+    /// Records the settings of a new sponsor as its first version.
+    pub async fn create_sponsor_version(&self, sponsor: &Sponsor, tiers: &[RewardTier], actor: &str) -> Result<SponsorVersion> {
+        let mut connection = self.pool.acquire().await?;
+
+        Self::insert_sponsor_version(&mut connection, sponsor.id, &sponsor_snapshot(sponsor, tiers), actor).await
+    }
+
+    async fn insert_sponsor_version(
+        connection: &mut PgConnection,
+        sponsor_id: i32,
+        settings: &serde_json::Value,
+        actor: &str,
+    ) -> Result<SponsorVersion> {
+        Ok(sqlx::query_as!(
+            SponsorVersion,
+            r#"
+                INSERT INTO sponsor_versions (sponsor_id, version, settings, actor)
+                SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3
+                FROM sponsor_versions
+                WHERE sponsor_id = $1
+                RETURNING *
+            "#,
+            sponsor_id,
+            settings,
+            actor
+        )
+        .fetch_one(&mut *connection)
+        .await?)
+    }
+
+    /// Gets every version of the sponsor, the latest first.
+    pub async fn get_sponsor_versions(&self, sponsor_id: i32) -> Result<Vec<SponsorVersion>> {
+        Ok(sqlx::query_as!(
+            SponsorVersion,
+            r#"
+                SELECT * FROM sponsor_versions
+                WHERE sponsor_id = $1
+                ORDER BY version DESC
+            "#,
+            sponsor_id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub async fn get_sponsor_version(&self, sponsor_id: i32, version: i32) -> Result<Option<SponsorVersion>> {
+        Ok(sqlx::query_as!(
+            SponsorVersion,
+            r#"
+                SELECT * FROM sponsor_versions
+                WHERE sponsor_id = $1 AND version = $2
+            "#,
+            sponsor_id,
+            version
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Gets the changes of the sponsor from the audit log, the latest first.
+    pub async fn get_sponsor_audit_log(&self, sponsor_id: i32) -> Result<Vec<SponsorAuditEntry>> {
+        Ok(sqlx::query_as!(
+            SponsorAuditEntry,
+            r#"
+                SELECT * FROM sponsor_audit_log
+                WHERE sponsor_id = $1
+                ORDER BY created_at DESC, id DESC
+            "#,
+            sponsor_id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+
+    /// Creates a new attempt in the database, linked to the current version of the sponsor.
+    pub async fn create_attempt_with_sponsor(&self, user: &User, sponsor: &Sponsor, call_sid: String) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO attempts (
+                phone_number,
+                sponsor_question,
+                sponsor_name,
+                sponsor_token_mint,
+                sponsor_total_reward,
+                sponsor_attempt_reward,
+                sponsor_background_url,
+                sponsor_challenge_time,
+                call_sid,
+                sponsor_id,
+                sponsor_version
+            )
+                VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                    (SELECT MAX(version) FROM sponsor_versions WHERE sponsor_id = $10)
+                )
+            "#,
+            user.phone_number,
+            sponsor.challenge_text,
+            sponsor.name,
+            sponsor.token_mint,
+            sponsor.original_tokens,
+            sponsor.reward_tokens,
+            sponsor.background_url,
+            sponsor.challenge_time,
+            call_sid,
+            sponsor.id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())  
+    }
+
+
+    pub async fn update_attempt_video(&self, caller_phone_number: String, video_url: String, call_sid: String) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET video_url = $1
+                WHERE phone_number = $2 AND call_sid = $3
+            "#,
+            video_url,
             caller_phone_number,
             call_sid
         )
@@ -458,6 +1490,493 @@ impl Database {
         Ok(())
     }
 
+    pub async fn update_attempt_winner(&self, caller_phone_number: String, is_winner: bool, call_sid: String) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET is_winner = $1
+                WHERE phone_number = $2 AND call_sid = $3
+            "#,
+            is_winner,
+            caller_phone_number,
+            call_sid
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+
+    /// Records a status change reported by the twilio status callback and stores the latest
+    /// status on the attempt. The duration and end reason are only set once they are known,
+    /// an end reason that was already recorded is kept.
+    pub async fn record_call_status(
+        &self,
+        call_sid: &str,
+        status: &str,
+        call_duration: Option<i32>,
+        sip_response_code: Option<i32>,
+        end_reason: Option<&str>,
+    ) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO call_status_events (call_sid, status, call_duration, sip_response_code)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            call_sid,
+            status,
+            call_duration,
+            sip_response_code
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET call_status = $1,
+                    call_duration = COALESCE($2, call_duration),
+                    end_reason = COALESCE(end_reason, $3),
+                    ended_at = COALESCE(ended_at, CASE WHEN $3::TEXT IS NULL THEN NULL ELSE now() END),
+                    updated_at = now()
+                WHERE call_sid = $4
+            "#,
+            status,
+            call_duration,
+            end_reason,
+            call_sid
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Records why the call ended, replacing the end reason the status callback may have
+    /// recorded first.
+    pub async fn record_end_reason(&self, call_sid: &str, end_reason: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET end_reason = $1,
+                    updated_at = now()
+                WHERE call_sid = $2
+            "#,
+            end_reason,
+            call_sid
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the verdict of the injection guard with the attempt.
+    pub async fn update_attempt_guard(
+        &self,
+        call_sid: &str,
+        guard_flagged: bool,
+        guard_verdict: &str,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE attempts
+                SET guard_flagged = $1, guard_verdict = $2
+                WHERE call_sid = $3
+            "#,
+            guard_flagged,
+            guard_verdict,
+            call_sid
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores a single judgement of a call, every judge of the panel gets their own row.
+    pub async fn create_judgement(
+        &self,
+        call_sid: &str,
+        judge_index: i32,
+        temperature: Option<f32>,
+        won_prize: bool,
+        rating: i32,
+        explanation: &str,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO judgements (call_sid, judge_index, temperature, won_prize, rating, explanation)
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            call_sid,
+            judge_index,
+            temperature,
+            won_prize,
+            rating,
+            explanation
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Holds back the win of a call until an operator approves or rejects it.
+    pub async fn create_pending_payout(
+        &self,
+        call_sid: &str,
+        cached_call: &CachedCall,
+        reward_tokens: i64,
+        reward_tier: &str,
+        explanation: &str,
+        video_url: &str,
+        reason: &str,
+        deadline: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO pending_payouts (
+                    call_sid, sponsor_id, phone_number, caller_name, reward_tokens,
+                    reward_tier, video_url, transcript, explanation, reason, deadline
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+            call_sid,
+            cached_call.sponsor.id,
+            cached_call.phone_number,
+            cached_call.name,
+            reward_tokens,
+            reward_tier,
+            video_url,
+            cached_call.transcript(),
+            explanation,
+            reason,
+            deadline
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the payouts that still wait for an operator, the oldest first.
+    pub async fn get_pending_payouts(&self) -> Result<Vec<PendingPayout>> {
+        Ok(sqlx::query_as!(
+            PendingPayout,
+            r#"
+                SELECT * FROM pending_payouts
+                WHERE status = 'pending'
+                ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Decides a pending payout with the given status. Approved payouts are set to
+    /// `approving` first and only settled once they were paid.
+    /// Returns `None` if the payout does not exist or was already decided.
+    pub async fn decide_pending_payout(&self, id: i32, status: &str) -> Result<Option<PendingPayout>> {
+        Ok(sqlx::query_as!(
+            PendingPayout,
+            r#"
+                UPDATE pending_payouts
+                SET status = $1, decided_at = NOW()
+                WHERE id = $2 AND status = 'pending'
+                RETURNING *
+            "#,
+            status,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Settles a payout that is being approved with the given status, a payout that could not
+    /// be paid goes back to `pending` so it can be decided again.
+    pub async fn settle_pending_payout(&self, id: i32, status: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE pending_payouts
+                SET status = $1, decided_at = CASE WHEN $1 = 'pending' THEN NULL ELSE NOW() END
+                WHERE id = $2 AND status = 'approving'
+            "#,
+            status,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks all pending payouts whose deadline passed as expired and returns them.
+    pub async fn expire_pending_payouts(&self) -> Result<Vec<PendingPayout>> {
+        Ok(sqlx::query_as!(
+            PendingPayout,
+            r#"
+                UPDATE pending_payouts
+                SET status = 'expired', decided_at = NOW()
+                WHERE status = 'pending' AND deadline < NOW()
+                RETURNING *
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Counts the attempts of every sponsor by the reason their call ended, so the
+    /// drop-off rate during calls can be compared between sponsors.
+    pub async fn get_drop_off_report(&self) -> Result<Vec<DropOffReport>> {
+        Ok(sqlx::query_as!(
+            DropOffReport,
+            r#"
+                SELECT
+                    sponsors.id AS sponsor_id,
+                    sponsors.name AS sponsor_name,
+                    COUNT(attempts.id) AS "attempts!",
+                    COUNT(attempts.id) FILTER (WHERE attempts.end_reason = 'finished') AS "finished!",
+                    COUNT(attempts.id) FILTER (WHERE attempts.end_reason = 'hangup') AS "hung_up!",
+                    COUNT(attempts.id) FILTER (WHERE attempts.end_reason NOT IN ('finished', 'hangup')) AS "not_connected!",
+                    AVG(attempts.call_duration)::FLOAT8 AS average_duration
+                FROM sponsors
+                LEFT JOIN attempts ON attempts.sponsor_id = sponsors.id
+                GROUP BY sponsors.id
+                ORDER BY sponsors.id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Gets the sponsors that made their initial deposit, so their wallet holds tokens.
+    pub async fn get_funded_sponsors(&self) -> Result<Vec<Sponsor>> {
+        Ok(sqlx::query_as!(
+            Sponsor,
+            r#"
+                SELECT * FROM sponsors
+                WHERE initial_funded = true
+                ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Sums the prizes of the sponsor that were withdrawn from the pool, but are still in
+    /// the sponsor's wallet or on their way to the winner.
+    pub async fn get_outstanding_payouts(&self, sponsor_id: i32) -> Result<OutstandingPayouts> {
+        Ok(sqlx::query_as!(
+            OutstandingPayouts,
+            r#"
+                SELECT
+                    COALESCE(SUM(amount) FILTER (WHERE status IN ('unclaimed', 'pending', 'signing')), 0)::BIGINT AS "owed!",
+                    COALESCE(SUM(amount) FILTER (WHERE status = 'sent'), 0)::BIGINT AS "in_flight!"
+                FROM payouts
+                WHERE sponsor_id = $1
+            "#,
+            sponsor_id
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Stores the latest reconciliation of the sponsor's pool with their wallet.
+    pub async fn record_reconciliation(&self, reconciliation: &BalanceReconciliation) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO balance_reconciliations (
+                    sponsor_id, onchain_tokens, available_tokens, owed_tokens,
+                    in_flight_tokens, difference, mismatch, deactivated, checked_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now())
+                ON CONFLICT (sponsor_id) DO UPDATE
+                SET onchain_tokens = EXCLUDED.onchain_tokens,
+                    available_tokens = EXCLUDED.available_tokens,
+                    owed_tokens = EXCLUDED.owed_tokens,
+                    in_flight_tokens = EXCLUDED.in_flight_tokens,
+                    difference = EXCLUDED.difference,
+                    mismatch = EXCLUDED.mismatch,
+                    deactivated = EXCLUDED.deactivated,
+                    checked_at = EXCLUDED.checked_at
+            "#,
+            reconciliation.sponsor_id,
+            reconciliation.onchain_tokens,
+            reconciliation.available_tokens,
+            reconciliation.owed_tokens,
+            reconciliation.in_flight_tokens,
+            reconciliation.difference,
+            reconciliation.mismatch,
+            reconciliation.deactivated
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the latest reconciliation of the sponsor's pool with their wallet.
+    /// Returns `None` if the sponsor was not reconciled yet.
+    pub async fn get_reconciliation(&self, sponsor_id: i32) -> Result<Option<BalanceReconciliation>> {
+        Ok(sqlx::query_as!(
+            BalanceReconciliation,
+            r#"
+                SELECT
+                    sponsor_id, onchain_tokens, available_tokens, owed_tokens,
+                    in_flight_tokens, difference, mismatch, deactivated
+                FROM balance_reconciliations
+                WHERE sponsor_id = $1
+            "#,
+            sponsor_id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Gets the latest reconciliation of every sponsor, the mismatches first.
+    pub async fn get_reconciliation_report(&self) -> Result<Vec<ReconciliationReport>> {
+        Ok(sqlx::query_as!(
+            ReconciliationReport,
+            r#"
+                SELECT
+                    sponsors.id AS sponsor_id,
+                    sponsors.name AS sponsor_name,
+                    sponsors.active,
+                    balance_reconciliations.onchain_tokens,
+                    balance_reconciliations.available_tokens,
+                    balance_reconciliations.owed_tokens,
+                    balance_reconciliations.in_flight_tokens,
+                    balance_reconciliations.difference,
+                    balance_reconciliations.mismatch,
+                    balance_reconciliations.deactivated,
+                    balance_reconciliations.checked_at
+                FROM balance_reconciliations
+                JOIN sponsors ON sponsors.id = balance_reconciliations.sponsor_id
+                ORDER BY balance_reconciliations.mismatch DESC, sponsors.id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Gets the in-flight call with the given sid from the database.
+    /// Returns `None` if there is no call with the given sid.
+    pub async fn get_cached_call(&self, call_sid: &str) -> Result<Option<CachedCall>> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                SELECT data AS "data: Json<CachedCall>" FROM calls
+                WHERE call_sid = $1
+            "#,
+            call_sid
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|data| data.0))
+    }
+
+    /// Inserts the in-flight call into the database, replacing the stored call with the same sid.
+    pub async fn upsert_cached_call(&self, call_sid: &str, cached_call: &CachedCall) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO calls (call_sid, data)
+                VALUES ($1, $2)
+                ON CONFLICT (call_sid) DO UPDATE
+                SET data = EXCLUDED.data, updated_at = now()
+            "#,
+            call_sid,
+            Json(cached_call) as _
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Updates the in-flight call with the given sid. The row is locked for the duration
+    /// of the update, so concurrent webhooks on other instances cannot overwrite each other.
+    /// Returns `None` if there is no call with the given sid.
+    pub async fn update_cached_call<T>(
+        &self,
+        call_sid: &str,
+        update: impl FnOnce(&mut CachedCall) -> T,
+    ) -> Result<Option<T>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let cached_call = sqlx::query_scalar!(
+            r#"
+                SELECT data AS "data: Json<CachedCall>" FROM calls
+                WHERE call_sid = $1
+                FOR UPDATE
+            "#,
+            call_sid
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(Json(mut cached_call)) = cached_call else {
+            return Ok(None);
+        };
+
+        let result = update(&mut cached_call);
+
+        sqlx::query!(
+            r#"
+                UPDATE calls
+                SET data = $1, updated_at = now()
+                WHERE call_sid = $2
+            "#,
+            Json(&cached_call) as _,
+            call_sid
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Some(result))
+    }
+
+    /// Gets all in-flight calls that have not been updated since `inactive_since`.
+    pub async fn get_stale_cached_calls(
+        &self,
+        inactive_since: DateTime<Utc>,
+    ) -> Result<Vec<(String, CachedCall)>> {
+        Ok(sqlx::query!(
+            r#"
+                SELECT call_sid, data AS "data: Json<CachedCall>" FROM calls
+                WHERE updated_at < $1
+            "#,
+            inactive_since
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.call_sid, row.data.0))
+        .collect())
+    }
+
+    /// Deletes the in-flight call with the given sid and returns it.
+    /// Returns `None` if there is no call with the given sid.
+    pub async fn delete_cached_call(&self, call_sid: &str) -> Result<Option<CachedCall>> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                DELETE FROM calls
+                WHERE call_sid = $1
+                RETURNING data AS "data: Json<CachedCall>"
+            "#,
+            call_sid
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|data| data.0))
+    }
+
 
 
 }
@@ -469,14 +1988,14 @@ pub struct WithdrawnTokens {
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Sponsor {
     pub id: i32,
     pub name: String,
     pub user_id: String,
     pub active: bool,
     pub background_url: String,
-    pub private_key: String,
+    pub private_key: SponsorKey,
     pub public_key: String,
     pub token_mint: String,
     pub original_tokens: i64,
@@ -492,6 +2011,158 @@ pub struct Sponsor {
     pub lost_text: String,
     pub rating_threshold: i32,
     pub initial_funded: bool,
+    /// Whether calls that hang up before the end are still judged on their partial transcript
+    #[serde(default)]
+    pub judge_abandoned: bool,
+    /// The name of the LLM provider generating the completions of the sponsor's calls
+    #[serde(default)]
+    pub llm_provider: String,
+    /// How the judgements decide whether a caller wins, see `JudgingMode`
+    #[serde(default)]
+    pub judging_mode: String,
+    /// The number of judges in the quorum, average and appeal modes
+    #[serde(default)]
+    pub judge_count: i32,
+    /// The number of judges that have to award the prize in the quorum and appeal modes
+    #[serde(default)]
+    pub judge_quorum: i32,
+    /// What happens with wins of attempts flagged by the injection guard, see `GuardAction`
+    #[serde(default)]
+    pub guard_action: String,
+    /// Wins with more reward tokens than this are paid out only after an operator approves them
+    #[serde(default)]
+    pub payout_approval_threshold: Option<i64>,
+    /// What the sponsor gives away, see `PrizeKind`. The token mint is empty for SOL
+    #[serde(default)]
+    pub prize_kind: String,
+    /// The decimals of the prize, read from the mint when the sponsor was launched
+    #[serde(default)]
+    pub token_decimals: Option<i32>,
+    /// Whether every lost call adds `jackpot_contribution` from the pool to the jackpot,
+    /// which the next winner wins on top of their prize
+    #[serde(default)]
+    pub jackpot: bool,
+    #[serde(default)]
+    pub jackpot_contribution: i64,
+    /// The tokens in the jackpot, they are held in the wallet but not part of the pool
+    #[serde(default)]
+    pub jackpot_tokens: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DropOffReport {
+    pub sponsor_id: i32,
+    pub sponsor_name: String,
+    pub attempts: i64,
+    pub finished: i64,
+    pub hung_up: i64,
+    pub not_connected: i64,
+    pub average_duration: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutstandingPayouts {
+    /// Prizes that are not sent yet
+    pub owed: i64,
+    /// Prizes that are sent, but not confirmed yet
+    pub in_flight: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BalanceReconciliation {
+    pub sponsor_id: i32,
+    pub onchain_tokens: i64,
+    pub available_tokens: i64,
+    pub owed_tokens: i64,
+    pub in_flight_tokens: i64,
+    pub difference: i64,
+    pub mismatch: bool,
+    pub deactivated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconciliationReport {
+    pub sponsor_id: i32,
+    pub sponsor_name: String,
+    pub active: bool,
+    pub onchain_tokens: i64,
+    pub available_tokens: i64,
+    pub owed_tokens: i64,
+    pub in_flight_tokens: i64,
+    pub difference: i64,
+    pub mismatch: bool,
+    pub deactivated: bool,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPayout {
+    pub id: i32,
+    pub call_sid: String,
+    pub sponsor_id: i32,
+    pub phone_number: String,
+    pub caller_name: String,
+    pub reward_tokens: i64,
+    pub reward_tier: String,
+    pub video_url: String,
+    pub transcript: String,
+    pub explanation: String,
+    pub reason: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct Payout {
+    pub id: i32,
+    pub attempt_id: Option<i32>,
+    pub call_sid: String,
+    pub sponsor_id: i32,
+    pub receiver_pubkey: Option<String>,
+    pub amount: i64,
+    pub status: String,
+    pub signature: Option<String>,
+    pub last_valid_block_height: Option<i64>,
+    pub retries: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The prize of wins whose rating reaches `min_rating`, a win gets the tier with the
+/// highest rating it reaches.
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize)]
+pub struct RewardTier {
+    pub id: i32,
+    pub sponsor_id: i32,
+    pub min_rating: i32,
+    pub reward_tokens: i64,
+    pub name: String,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct NftPrize {
+    pub call_sid: String,
+    pub sponsor_id: i32,
+    pub name: String,
+    pub symbol: String,
+    /// Where the off-chain metadata of the NFT is served
+    pub uri: String,
+    pub caller_name: String,
+    pub sponsor_name: String,
+    pub challenge: String,
+    pub rating: Option<i32>,
+    pub video_url: String,
+    pub image_url: String,
+    /// The mint of the NFT, set once the mint transaction is signed
+    pub mint: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[allow(unused)]
@@ -501,6 +2172,64 @@ pub struct Winner {
     pub key: String,
     pub name: String,
     pub sponsor_id: i32,
+    pub call_sid: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub wallet: Option<String>,
+}
+
+/// The prize withdrawn for a winner, including the jackpot they won.
+#[derive(Debug, Clone)]
+pub struct ReservedPrize {
+    pub winner: Winner,
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Jackpot {
+    pub sponsor_id: i32,
+    pub jackpot_tokens: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SponsorVersion {
+    pub id: i32,
+    pub sponsor_id: i32,
+    pub version: i32,
+    /// The settings of the sponsor and its reward tiers, see `sponsor_snapshot`
+    pub settings: serde_json::Value,
+    /// The wallet of the owner who made the version
+    pub actor: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SponsorAuditEntry {
+    pub id: i32,
+    pub sponsor_id: i32,
+    /// The wallet of the owner who made the change
+    pub actor: String,
+    /// The fields that changed, as `{"field": {"old": .., "new": ..}}`
+    pub changes: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct AuthNonce {
+    pub nonce: String,
+    pub public_key: String,
+    pub domain: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrizeRefund {
+    pub sponsor_id: i32,
+    pub amount: i64,
+    pub prizes: i64,
 }
 
 #[allow(unused)]
@@ -0,0 +1,286 @@
+use crate::{
+    cache::CachedCall,
+    database::{Database, PendingPayout, Sponsor},
+    game::{
+        judge::{lost_handler, won_handler},
+        reward::Reward,
+    },
+    secrets::Secrets,
+    template::{Template, TemplateContext},
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::time::Duration;
+use twilio::{Client as TwilioClient, OutboundMessage};
+
+/// The challenge status of attempts whose win waits for an operator.
+pub const PENDING_APPROVAL_STATUS: &str = "pending_approval";
+
+/// The statuses of decided payouts, expired payouts are set by the database.
+/// Approved payouts stay `approving` until they are paid and go back to `pending` if that fails.
+const PENDING_PAYOUT: &str = "pending";
+const APPROVING_PAYOUT: &str = "approving";
+const APPROVED_PAYOUT: &str = "approved";
+const REJECTED_PAYOUT: &str = "rejected";
+
+/// Wins that are not approved within this time are rejected.
+const APPROVAL_DEADLINE: chrono::Duration = chrono::Duration::hours(48);
+
+/// How often expired pending payouts are rejected.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Sent to winners whose win has to be approved first, `{name}` is replaced with their name.
+const PENDING_APPROVAL_TEXT: &str = "Congratulations {name}, you won! Before we send your prize, \
+your win is checked by our team. You will receive another text message once it is approved.";
+
+/// Whether the sponsor wants wins of this size to be approved by an operator.
+pub fn needs_approval(sponsor: &Sponsor, reward_tokens: i64) -> bool {
+    sponsor
+        .payout_approval_threshold
+        .is_some_and(|threshold| reward_tokens > threshold)
+}
+
+/// Stores the win as a pending payout and lets the caller know that it is reviewed first.
+pub async fn hold_win(
+    twilio: &TwilioClient,
+    database: &Database,
+    secrets: &Secrets,
+    call_sid: &str,
+    cached_call: &CachedCall,
+    reward: &Reward,
+    explanation: &str,
+    video_url: &str,
+    reason: &str,
+) -> Result<()> {
+    log::debug!("Holding back win of call {call_sid} for approval: {reason}");
+
+    database
+        .create_pending_payout(
+            call_sid,
+            cached_call,
+            reward.tokens,
+            &reward.tier,
+            explanation,
+            video_url,
+            reason,
+            Utc::now() + APPROVAL_DEADLINE,
+        )
+        .await
+        .context("Creating pending payout")?;
+
+    database
+        .update_attempt_judgement(call_sid.to_owned(), PENDING_APPROVAL_STATUS.to_owned())
+        .await
+        .context("Updating attempt with pending approval status")?;
+
+    let text = Template::parse(PENDING_APPROVAL_TEXT).render(&TemplateContext {
+        name: Some(cached_call.name.clone()),
+        ..TemplateContext::for_sponsor(&cached_call.sponsor)
+    });
+
+    twilio
+        .send_message(OutboundMessage {
+            from: &secrets.twilio_phone_number,
+            to: &cached_call.phone_number,
+            body: &text,
+        })
+        .await
+        .context("Sending message")?;
+
+    Ok(())
+}
+
+/// Approves a pending payout and pays out the reviewed reward like any other win.
+/// Returns `false` if the payout was already decided. If the win can not be paid
+/// the payout goes back to pending so it can be approved again.
+pub async fn approve_payout(
+    twilio: TwilioClient,
+    database: Database,
+    secrets: Secrets,
+    payout_id: i32,
+) -> Result<bool> {
+    let Some(payout) = database
+        .decide_pending_payout(payout_id, APPROVING_PAYOUT)
+        .await
+        .context("Approving pending payout")?
+    else {
+        return Ok(false);
+    };
+
+    log::debug!("Approving payout of call {}", payout.call_sid);
+
+    if let Err(e) = pay_approved_win(twilio, database.clone(), secrets, payout).await {
+        database
+            .settle_pending_payout(payout_id, PENDING_PAYOUT)
+            .await
+            .context("Returning payout to pending")?;
+
+        return Err(e);
+    }
+
+    database
+        .settle_pending_payout(payout_id, APPROVED_PAYOUT)
+        .await
+        .context("Settling approved payout")?;
+
+    Ok(true)
+}
+
+async fn pay_approved_win(
+    twilio: TwilioClient,
+    database: Database,
+    secrets: Secrets,
+    payout: PendingPayout,
+) -> Result<()> {
+    let sponsor = database
+        .get_sponsor_by_id(payout.sponsor_id)
+        .await
+        .context("Getting sponsor of pending payout")?;
+
+    let rating = database
+        .get_call_rating(&payout.call_sid)
+        .await
+        .context("Getting rating of call")?;
+
+    // The reviewed reward is paid, not one computed from the tiers and jackpot of today
+    let reward = Reward {
+        tokens: payout.reward_tokens,
+        tier: payout.reward_tier,
+    };
+
+    let paid = won_handler(
+        twilio,
+        database.clone(),
+        secrets,
+        payout.phone_number,
+        payout.call_sid.clone(),
+        payout.caller_name,
+        sponsor,
+        payout.video_url,
+        rating,
+        &reward,
+    )
+    .await?;
+
+    if !paid {
+        bail!(
+            "The pool of sponsor {} can not cover the prize of {} tokens",
+            payout.sponsor_id,
+            reward.tokens
+        );
+    }
+
+    // The attempt keeps the judgement as its status like any other judged attempt
+    database
+        .update_attempt_judgement(payout.call_sid, payout.explanation)
+        .await
+        .context("Updating attempt with judgement")?;
+
+    Ok(())
+}
+
+/// Rejects a pending payout, the caller is told they lost.
+/// Returns `false` if the payout was already decided.
+pub async fn reject_payout(
+    twilio: TwilioClient,
+    database: Database,
+    secrets: Secrets,
+    payout_id: i32,
+) -> Result<bool> {
+    let Some(payout) = database
+        .decide_pending_payout(payout_id, REJECTED_PAYOUT)
+        .await
+        .context("Rejecting pending payout")?
+    else {
+        return Ok(false);
+    };
+
+    deny_win(twilio, database, secrets, payout).await?;
+
+    Ok(true)
+}
+
+/// Periodically rejects pending payouts whose deadline passed. Runs until the app shuts down.
+pub async fn run_payout_expiry(twilio: TwilioClient, database: Database, secrets: Secrets) {
+    let mut interval = tokio::time::interval(EXPIRY_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let expired = match database.expire_pending_payouts().await {
+            Ok(expired) => expired,
+            Err(e) => {
+                log::error!("Failed to expire pending payouts: {e:?}");
+                continue;
+            }
+        };
+
+        for payout in expired {
+            let call_sid = payout.call_sid.clone();
+
+            if let Err(e) =
+                deny_win(twilio.clone(), database.clone(), secrets.clone(), payout).await
+            {
+                log::error!("Failed to deny expired payout of call {call_sid}: {e:?}");
+            }
+        }
+    }
+}
+
+async fn deny_win(
+    twilio: TwilioClient,
+    database: Database,
+    secrets: Secrets,
+    payout: PendingPayout,
+) -> Result<()> {
+    log::debug!(
+        "Denied payout of call {}: {}",
+        payout.call_sid,
+        payout.status
+    );
+
+    database
+        .update_attempt_judgement(payout.call_sid.clone(), payout.status.clone())
+        .await
+        .context("Updating attempt with payout status")?;
+
+    let sponsor = database
+        .get_sponsor_by_id(payout.sponsor_id)
+        .await
+        .context("Getting sponsor of pending payout")?;
+
+    lost_handler(
+        twilio,
+        database,
+        secrets,
+        payout.phone_number,
+        payout.call_sid,
+        payout.caller_name,
+        sponsor,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sponsor(payout_approval_threshold: Option<i64>) -> Sponsor {
+        Sponsor {
+            payout_approval_threshold,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_threshold_needs_no_approval() {
+        assert!(!needs_approval(&sponsor(None), 1_000_000));
+    }
+
+    #[test]
+    fn wins_above_threshold_need_approval() {
+        assert!(needs_approval(&sponsor(Some(1_000)), 1_001));
+        assert!(!needs_approval(&sponsor(Some(1_000)), 1_000));
+        assert!(needs_approval(&sponsor(Some(0)), 1));
+    }
+}
@@ -1,169 +1,297 @@
-use crate::{cache::CachedCall, secrets::Secrets, CONFIG};
-use async_openai::{
-    config::OpenAIConfig,
-    types::{
-        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-        ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
-    },
-    Client as OpenAIClient,
+use crate::{
+    cache::CallStore,
+    database::Database,
+    game::error::GameError,
+    llm::{LlmProvider, LlmProviders, LlmRequest, LlmTask},
+    secrets::Secrets,
+    template::escape_speech,
+    CONFIG,
+};
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
+    ChatCompletionRequestUserMessage,
 };
 use axum::{extract::Request, response::IntoResponse, Extension};
-use std::{collections::HashMap, sync::Arc, time::Duration};
-use tokio::sync::Mutex;
+use chrono::Utc;
+use std::time::Duration;
 use twilio::{
     twiml::{Gather, GatherInput, Method, Redirect, Say, SpeechTimeout, Twiml, Voice},
     Call, Client,
 };
 
+/// Time after the challenge deadline before the respond handler ends the challenge itself,
+/// giving the timer started in the start handler the chance to redirect the call first.
+const CHALLENGE_END_GRACE: chrono::Duration = chrono::Duration::seconds(5);
+
 pub async fn start_handler(
     twilio: Extension<Client>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    cache: Extension<CallStore>,
+    database: Extension<Database>,
     secrets: Extension<Secrets>,
     request: Request,
 ) -> impl IntoResponse {
     twilio
         .clone()
         .respond_to_webhook_async(request, |call: Call| async move {
-            {
-                let mut cache = cache.lock().await;
-                let cached_call = cache
-                    .get_mut(&call.sid)
-                    .expect("Failed to get message conversation");
-
-                let challenge_time = cached_call.sponsor.challenge_time;
-                cached_call.end_last_message();
-
-                // Start the timer that will redirect the call to the /end route
-                tokio::spawn(async move {
-                    tokio::time::sleep(Duration::from_secs(challenge_time as _)).await;
-                    let url = format!("{}/end", secrets.global_url);
-                    let _ = twilio.update_call_url(&call.sid, &url).await;
-                });
+            match start_challenge(&twilio, &cache, &secrets, &call).await {
+                Ok(twiml) => twiml,
+                Err(e) => e.fallback(&call.sid, &cache, &database, None).await,
             }
+        })
+        .await
+}
 
-            let mut twiml = Twiml::new();
-
-            log::debug!("Gathering user response");
-
-            twiml.add(&Gather {
-                timeout_seconds: CONFIG.settings.timeout as u32,
-                action: Some("/challenge/respond".to_owned()),
-                input: Some(GatherInput::Speech),
-                speech_timeout: Some(SpeechTimeout::Auto),
-                speech_model: Some(CONFIG.settings.speech_model.to_owned()),
-                ..Default::default()
-            });
+/// Starts the challenge timer and gathers the first response of the caller.
+pub(super) async fn start_challenge(
+    twilio: &Client,
+    cache: &CallStore,
+    secrets: &Secrets,
+    call: &Call,
+) -> Result<Twiml, GameError> {
+    let challenge_time = cache
+        .update(&call.sid, |cached_call| {
+            let challenge_time = cached_call.sponsor.challenge_time;
+            cached_call.end_last_message();
+            cached_call.challenge_end =
+                Some(Utc::now() + chrono::Duration::seconds(challenge_time as _));
 
-            twiml
+            challenge_time
         })
         .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)?;
+
+    // Start the timer that will redirect the call to the /end route
+    let twilio = twilio.clone();
+    let url = format!("{}/end", secrets.global_url);
+    let call_sid = call.sid.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(challenge_time as _)).await;
+        let _ = twilio.update_call_url(&call_sid, &url).await;
+    });
+
+    let mut twiml = Twiml::new();
+
+    log::debug!("Gathering user response");
+
+    twiml.add(&Gather {
+        timeout_seconds: CONFIG.settings.timeout as u32,
+        action: Some("/challenge/respond".to_owned()),
+        input: Some(GatherInput::Speech),
+        speech_timeout: Some(SpeechTimeout::Auto),
+        speech_model: Some(CONFIG.settings.speech_model.to_owned()),
+        ..Default::default()
+    });
+
+    Ok(twiml)
 }
 
 pub async fn respond_handler(
     twilio: Extension<Client>,
-    openai: Extension<OpenAIClient<OpenAIConfig>>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    llm: Extension<LlmProviders>,
+    cache: Extension<CallStore>,
+    database: Extension<Database>,
     request: Request,
 ) -> impl IntoResponse {
     twilio
         .clone()
         .respond_to_webhook_async(request, |call: Call| async move {
-            log::debug!(
-                "Understood: {:?} with confidence {:?}",
-                call.speech_result,
-                call.speech_confidence
-            );
-
-            let mut twiml = Twiml::new();
-
-            // If there is a transcription, it is a response to a previous user message.
-            // If no transcription is available, the challenge has just been started and the cache
-            // should be updated with the system message and the timer should be started to end
-            // the gameshow after a certain amount of time.
-            if let Some(speech_result) = call.speech_result {
-                // Load the conversation from the cache
-                let mut cached_call = {
-                    let cache = cache.lock().await;
-                    cache
-                        .get(&call.sid)
-                        .expect("Failed to get message conversation")
-                        .clone()
-                };
-                log::debug!(
-                    "Loaded {} messages: {:?}",
-                    cached_call.messages.len(),
-                    cached_call.messages
-                );
+            match respond(&llm, &cache, &call).await {
+                Ok(twiml) => twiml,
+                Err(e) => {
+                    let retry = Some("/redirect-gather/challenge/respond");
+                    e.fallback(&call.sid, &cache, &database, retry).await
+                }
+            }
+        })
+        .await
+}
 
-                // Add the user message to the conversation
-                cached_call.add_user_message(
-                    ChatCompletionRequestUserMessageArgs::default()
-                        .content(speech_result)
-                        .build()
-                        .expect("Failed to build user message")
-                        .into(),
-                );
+/// Responds to the last thing the caller said during the challenge.
+pub(super) async fn respond(
+    llm: &LlmProviders,
+    cache: &CallStore,
+    call: &Call,
+) -> Result<Twiml, GameError> {
+    log::debug!(
+        "Understood: {:?} with confidence {:?}",
+        call.speech_result,
+        call.speech_confidence
+    );
+
+    let mut twiml = Twiml::new();
+
+    // Load the conversation from the cache
+    let cached_call = cache
+        .get(&call.sid)
+        .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)?;
+
+    // The timer that redirects the call to the /end route does not survive a restart
+    // of the instance that started the challenge, so fall back on the stored deadline.
+    if cached_call
+        .challenge_end
+        .is_some_and(|end| end + CHALLENGE_END_GRACE <= Utc::now())
+    {
+        log::debug!("Challenge deadline passed for call {}, ending", call.sid);
+        twiml.add(&Redirect {
+            method: Method::Post,
+            url: "/end".to_owned(),
+        });
+
+        return Ok(twiml);
+    }
 
-                // Generate a response to the conversation
-                let completion = generate_response(&openai, &cached_call.messages)
-                    .await
-                    .expect("Failed to generate response");
+    // If there is a transcription, it is a response to a previous user message.
+    // If no transcription is available, the challenge has just been started and the cache
+    // should be updated with the system message and the timer should be started to end
+    // the gameshow after a certain amount of time.
+    if let Some(speech_result) = &call.speech_result {
+        log::debug!(
+            "Loaded {} messages: {:?}",
+            cached_call.messages.len(),
+            cached_call.messages
+        );
 
-                log::debug!("Generated completion: {}", completion);
+        let user_message: ChatCompletionRequestMessage =
+            ChatCompletionRequestUserMessage::from(speech_result.as_str()).into();
+        let understood_at = Utc::now();
 
-                // Add the assistant message to the conversation
+        // Generate a response to the conversation with the user message
+        let mut messages = cached_call.messages.clone();
+        messages.push(user_message.clone());
+        let provider = llm.get(&cached_call.sponsor.llm_provider);
+        let completion = generate_response(provider.as_ref(), &messages).await?;
+
+        log::debug!("Generated completion: {}", completion);
+
+        // Append both messages to the stored conversation, so that writes of other
+        // webhooks during the completion are kept
+        cache
+            .update(&call.sid, |cached_call| {
+                cached_call.add_user_message_until(user_message, understood_at);
                 cached_call.add_system_message(
-                    ChatCompletionRequestAssistantMessageArgs::default()
-                        .content(completion.clone())
-                        .build()
-                        .expect("Failed to build assistant message")
-                        .into(),
+                    ChatCompletionRequestAssistantMessage::from(completion.as_str()).into(),
                 );
+            })
+            .await
+            .map_err(GameError::Cache)?
+            .ok_or(GameError::CallNotFound)?;
 
-                {
-                    let mut cache = cache.lock().await;
-                    cache.insert(call.sid, cached_call);
-                }
-
-                // Speak the generated response
-                twiml.add(&Say {
-                    txt: completion,
-                    voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
-                    language: CONFIG.settings.language.to_owned(),
-                });
-            }
+        // Speak the generated response
+        twiml.add(&Say {
+            txt: escape_speech(&completion),
+            voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
+            language: CONFIG.settings.language.to_owned(),
+        });
+    }
 
-            // This redirect is necessary to extract the timestamp in between
-            // the system message and the user response
-            twiml.add(&Redirect {
-                method: Method::Post,
-                url: "/redirect-gather/challenge/respond".to_owned(),
-            });
+    // This redirect is necessary to extract the timestamp in between
+    // the system message and the user response
+    twiml.add(&Redirect {
+        method: Method::Post,
+        url: "/redirect-gather/challenge/respond".to_owned(),
+    });
 
-            twiml
-        })
-        .await
+    Ok(twiml)
 }
 
 async fn generate_response(
-    openai: &Extension<OpenAIClient<OpenAIConfig>>,
+    llm: &dyn LlmProvider,
     messages: &[ChatCompletionRequestMessage],
-) -> Option<String> {
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(CONFIG.challenge.max_tokens as u32)
-        .model(CONFIG.challenge.model)
-        .messages(messages)
-        .build()
-        .ok()?;
-
-    openai
-        .chat()
-        .create(request)
-        .await
-        .ok()?
-        .choices
-        .first()?
-        .message
-        .content
-        .clone()
+) -> Result<String, GameError> {
+    llm.complete(LlmRequest {
+        task: LlmTask::Challenge,
+        messages: messages.to_vec(),
+        max_tokens: CONFIG.challenge.max_tokens as u32,
+        temperature: None,
+        response_format: None,
+    })
+    .await
+    .map_err(GameError::Completion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{scripted_llm, test_cached_call, test_call};
+    use crate::llm::ScriptedProvider;
+    use async_trait::async_trait;
+
+    /// Changes the cached call while the completion is generated, like another webhook would.
+    struct InterleavingProvider {
+        cache: CallStore,
+        call_sid: String,
+    }
+
+    #[async_trait]
+    impl LlmProvider for InterleavingProvider {
+        async fn complete(&self, _request: LlmRequest) -> anyhow::Result<String> {
+            self.cache
+                .update(&self.call_sid, |cached_call| cached_call.name = "Alice".to_owned())
+                .await?;
+
+            Ok("A dollar? You can do better than that.".to_owned())
+        }
+    }
+
+    #[tokio::test]
+    async fn start_missing_call_hangs_up() {
+        let twilio = Client::new("AC00000000000000000000000000000000", "token");
+        let cache = CallStore::memory();
+        let call = test_call(None);
+
+        let error = start_challenge(&twilio, &cache, &Secrets::default(), &call)
+            .await
+            .expect_err("Starting the challenge of an unknown call must fail");
+        assert!(matches!(error, GameError::CallNotFound));
+
+        let twiml = error
+            .fallback(&call.sid, &cache, &Database::unreachable(), None)
+            .await
+            .as_twiml();
+        assert!(twiml.contains("<Hangup"));
+    }
+
+    #[tokio::test]
+    async fn respond_completion_failure_retries() {
+        let cache = CallStore::memory();
+        let call = test_call(Some("I would sell it to you for a dollar"));
+        cache.insert(&call.sid, test_cached_call()).await.unwrap();
+
+        let error = respond(&scripted_llm(ScriptedProvider::new()), &cache, &call)
+            .await
+            .expect_err("Responding without a completion must fail");
+        assert!(matches!(error, GameError::Completion(_)));
+
+        let retry = Some("/redirect-gather/challenge/respond");
+        let twiml = error
+            .fallback(&call.sid, &cache, &Database::unreachable(), retry)
+            .await
+            .as_twiml();
+        assert!(twiml.contains("/redirect-gather/challenge/respond</Redirect>"));
+        assert!(!twiml.contains("<Hangup"));
+
+        // The unanswered message is not stored, so the caller can simply repeat it
+        let cached_call = cache.get(&call.sid).await.unwrap().unwrap();
+        assert!(cached_call.messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn respond_keeps_writes_made_during_the_completion() {
+        let cache = CallStore::memory();
+        let call = test_call(Some("I would sell it to you for a dollar"));
+        cache.insert(&call.sid, test_cached_call()).await.unwrap();
+
+        let llm = LlmProviders::new(InterleavingProvider {
+            cache: cache.clone(),
+            call_sid: call.sid.clone(),
+        });
+        respond(&llm, &cache, &call).await.unwrap();
+
+        let cached_call = cache.get(&call.sid).await.unwrap().unwrap();
+        assert_eq!(cached_call.name, "Alice");
+        assert_eq!(cached_call.messages.len(), 2);
+    }
 }
@@ -0,0 +1,163 @@
+use crate::game::judge::JudgeResponse;
+
+/// The largest panel a sponsor can configure.
+pub const MAX_JUDGE_COUNT: i32 = 5;
+
+/// How the judgements of a call decide whether the caller wins the prize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JudgingMode {
+    /// A single judgement decides.
+    Single,
+    /// The caller wins if at least `judge_quorum` judges award the prize.
+    Quorum,
+    /// The caller wins if the average rating reaches the sponsor's `rating_threshold`.
+    Average,
+    /// A single judgement decides losses, wins are appealed to the whole panel
+    /// and need a quorum like in [`JudgingMode::Quorum`].
+    Appeal,
+}
+
+impl JudgingMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JudgingMode::Single => "single",
+            JudgingMode::Quorum => "quorum",
+            JudgingMode::Average => "average",
+            JudgingMode::Appeal => "appeal",
+        }
+    }
+}
+
+impl From<&str> for JudgingMode {
+    fn from(mode: &str) -> Self {
+        match mode {
+            "quorum" => JudgingMode::Quorum,
+            "average" => JudgingMode::Average,
+            "appeal" => JudgingMode::Appeal,
+            _ => JudgingMode::Single,
+        }
+    }
+}
+
+/// Combines the judgements into the final verdict, `None` if there are no judgements.
+///
+/// The rating of the verdict is the rounded average rating, the explanation
+/// is taken from the first judgement that agrees with the verdict.
+pub fn decide(
+    mode: JudgingMode,
+    judgements: &[JudgeResponse],
+    quorum: i32,
+    rating_threshold: i32,
+) -> Option<JudgeResponse> {
+    let first = judgements.first()?;
+
+    let won_prize = match mode {
+        JudgingMode::Single => first.won_prize,
+        JudgingMode::Quorum | JudgingMode::Appeal => {
+            let wins = judgements.iter().filter(|j| j.won_prize).count();
+            wins >= quorum.max(1) as usize
+        }
+        JudgingMode::Average => average_rating(judgements) >= rating_threshold as f64,
+    };
+
+    let explanation = judgements
+        .iter()
+        .find(|j| j.won_prize == won_prize)
+        .unwrap_or(first)
+        .explanation
+        .clone();
+
+    Some(JudgeResponse {
+        won_prize,
+        rating: average_rating(judgements).round() as u8,
+        explanation,
+    })
+}
+
+fn average_rating(judgements: &[JudgeResponse]) -> f64 {
+    let total: f64 = judgements.iter().map(|j| j.rating as f64).sum();
+    total / judgements.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn judgement(won_prize: bool, rating: u8) -> JudgeResponse {
+        JudgeResponse {
+            won_prize,
+            rating,
+            explanation: format!("won: {won_prize}, rating: {rating}"),
+        }
+    }
+
+    #[test]
+    fn no_judgements_has_no_verdict() {
+        assert!(decide(JudgingMode::Quorum, &[], 2, 7).is_none());
+    }
+
+    #[test]
+    fn single_follows_first_judgement() {
+        let judgements = [judgement(true, 8), judgement(false, 2)];
+        let verdict = decide(JudgingMode::Single, &judgements, 2, 7).unwrap();
+
+        assert!(verdict.won_prize);
+        assert_eq!(verdict.explanation, "won: true, rating: 8");
+    }
+
+    #[test]
+    fn quorum_needs_enough_wins() {
+        let judgements = [judgement(true, 8), judgement(false, 4), judgement(true, 9)];
+
+        let verdict = decide(JudgingMode::Quorum, &judgements, 2, 7).unwrap();
+        assert!(verdict.won_prize);
+
+        let verdict = decide(JudgingMode::Quorum, &judgements, 3, 7).unwrap();
+        assert!(!verdict.won_prize);
+    }
+
+    #[test]
+    fn quorum_of_zero_still_needs_a_win() {
+        let judgements = [judgement(false, 8)];
+
+        let verdict = decide(JudgingMode::Quorum, &judgements, 0, 7).unwrap();
+        assert!(!verdict.won_prize);
+    }
+
+    #[test]
+    fn average_compares_against_threshold() {
+        let judgements = [judgement(true, 9), judgement(false, 6), judgement(false, 6)];
+
+        let verdict = decide(JudgingMode::Average, &judgements, 3, 7).unwrap();
+        assert!(verdict.won_prize);
+        assert_eq!(verdict.rating, 7);
+        assert_eq!(verdict.explanation, "won: true, rating: 9");
+
+        let verdict = decide(JudgingMode::Average, &judgements, 3, 8).unwrap();
+        assert!(!verdict.won_prize);
+    }
+
+    #[test]
+    fn appeal_loss_is_final() {
+        let judgements = [judgement(false, 3)];
+        let verdict = decide(JudgingMode::Appeal, &judgements, 1, 7).unwrap();
+
+        assert!(!verdict.won_prize);
+        assert_eq!(verdict.rating, 3);
+    }
+
+    #[test]
+    fn appealed_win_needs_quorum() {
+        let judgements = [judgement(true, 8), judgement(false, 5), judgement(false, 4)];
+        let verdict = decide(JudgingMode::Appeal, &judgements, 2, 7).unwrap();
+
+        assert!(!verdict.won_prize);
+        assert_eq!(verdict.explanation, "won: false, rating: 5");
+    }
+
+    #[test]
+    fn unknown_mode_is_single() {
+        assert_eq!(JudgingMode::from("majority"), JudgingMode::Single);
+        assert_eq!(JudgingMode::from("appeal"), JudgingMode::Appeal);
+    }
+}
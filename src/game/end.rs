@@ -1,56 +1,88 @@
-use crate::{cache::CachedCall, CONFIG};
-use async_openai::types::ChatCompletionRequestAssistantMessageArgs;
+use crate::{
+    cache::CallStore,
+    database::Database,
+    game::error::GameError,
+    template::{escape_speech, Template, TemplateContext},
+    CONFIG,
+};
+use async_openai::types::ChatCompletionRequestAssistantMessage;
 use axum::{extract::Request, response::IntoResponse, Extension};
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
 use twilio::{
     twiml::{Method, Redirect, Say, Twiml, Voice},
     Call, Client,
 };
 
-
 pub async fn end_handler(
     twilio: Extension<Client>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    cache: Extension<CallStore>,
+    database: Extension<Database>,
     request: Request,
 ) -> impl IntoResponse {
     twilio
         .clone()
         .respond_to_webhook_async(request, |call: Call| async move {
-            let end_text = {
-                let mut cache = cache.lock().await;
-                let cached_call = cache
-                    .get_mut(&call.sid)
-                    .expect("Failed to get message conversation");
-
-                let end_text = cached_call.sponsor.end_text.to_owned();
-
-                cached_call.add_system_message(
-                    ChatCompletionRequestAssistantMessageArgs::default()
-                        .content(cached_call.sponsor.end_text.to_owned())
-                        .build()
-                        .expect("Failed to build system message")
-                        .into(),
-                );
-
-                end_text
-            };
-
-            let mut twiml = Twiml::new();
-
-            twiml.add(&Say {
-                txt: end_text,
-                voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
-                language: CONFIG.settings.language.to_owned(),
-            });
-
+            match end_challenge(&cache, &call).await {
+                Ok(twiml) => twiml,
+                Err(e) => e.fallback(&call.sid, &cache, &database, None).await,
+            }
+        })
+        .await
+}
 
-            twiml.add(&Redirect {
-                url: "/judge".to_owned(),
-                method: Method::Post,
+/// Speaks the end text of the sponsor and sends the call to the /judge route.
+pub(super) async fn end_challenge(cache: &CallStore, call: &Call) -> Result<Twiml, GameError> {
+    let end_text = cache
+        .update(&call.sid, |cached_call| {
+            let end_text = Template::parse(&cached_call.sponsor.end_text).render(&TemplateContext {
+                name: Some(cached_call.name.clone()),
+                ..TemplateContext::for_sponsor(&cached_call.sponsor)
             });
 
-            twiml
+            cached_call.add_system_message(
+                ChatCompletionRequestAssistantMessage::from(end_text.as_str()).into(),
+            );
+
+            end_text
         })
         .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)?;
+
+    let mut twiml = Twiml::new();
+
+    twiml.add(&Say {
+        txt: escape_speech(&end_text),
+        voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
+        language: CONFIG.settings.language.to_owned(),
+    });
+
+    twiml.add(&Redirect {
+        url: "/judge".to_owned(),
+        method: Method::Post,
+    });
+
+    Ok(twiml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::test_call;
+
+    #[tokio::test]
+    async fn missing_call_hangs_up() {
+        let cache = CallStore::memory();
+        let call = test_call(None);
+
+        let error = end_challenge(&cache, &call)
+            .await
+            .expect_err("Ending the challenge of an unknown call must fail");
+        assert!(matches!(error, GameError::CallNotFound));
+
+        let twiml = error
+            .fallback(&call.sid, &cache, &Database::unreachable(), None)
+            .await
+            .as_twiml();
+        assert!(twiml.contains("<Hangup"));
+    }
 }
@@ -0,0 +1,155 @@
+use crate::{cache::CallStore, database::Database, template::escape_speech, CONFIG};
+use std::fmt;
+use twilio::twiml::{Hangup, Method, Redirect, Say, Twiml, Voice};
+
+/// The challenge status of attempts that ended because of an error on our side.
+pub const ERRORED_STATUS: &str = "errored";
+
+/// Spoken before retrying the current step of the call after a failure.
+const RETRY_TEXT: &str = "Sorry, I didn't quite catch that. Let's try that again.";
+
+/// Spoken before hanging up after a failure the call cannot recover from.
+const HANGUP_TEXT: &str =
+    "Sorry, something went wrong on our side and we have to end the call. Please try again later.";
+
+/// Everything that can go wrong while handling a game webhook.
+#[derive(Debug)]
+pub enum GameError {
+    /// The call is not in the call store, it either never started or was already judged.
+    CallNotFound,
+    /// Loading or storing the call failed.
+    Cache(anyhow::Error),
+    /// A database query failed.
+    Database(anyhow::Error),
+    /// Generating a completion failed.
+    Completion(anyhow::Error),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::CallNotFound => write!(f, "Call not found"),
+            GameError::Cache(e) => write!(f, "Call store error: {e:?}"),
+            GameError::Database(e) => write!(f, "Database error: {e:?}"),
+            GameError::Completion(e) => write!(f, "Completion error: {e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl GameError {
+    /// Turns the error into the TwiML the caller hears instead of a twilio application error.
+    ///
+    /// With a `retry` url the caller is told to try again and redirected there, the call
+    /// stays in the store. Without one, or if the call is gone and there is nothing to retry,
+    /// the call is removed from the store, the attempt is marked as errored and the caller
+    /// is told that the call ends before hanging up.
+    pub async fn fallback(
+        self,
+        call_sid: &str,
+        cache: &CallStore,
+        database: &Database,
+        retry: Option<&str>,
+    ) -> Twiml {
+        let mut twiml = Twiml::new();
+
+        let retry = retry.filter(|_| !matches!(self, GameError::CallNotFound));
+        if let Some(url) = retry {
+            log::error!("Call {call_sid} failed, retrying at {url}: {self}");
+
+            twiml.add(&say(RETRY_TEXT));
+            twiml.add(&Redirect {
+                url: url.to_owned(),
+                method: Method::Post,
+            });
+
+            return twiml;
+        }
+
+        log::error!("Call {call_sid} failed, hanging up: {self}");
+
+        // Removing the call first keeps the status callback and the reaper
+        // from overriding the errored status with an abandoned one
+        if let Err(e) = cache.remove(call_sid).await {
+            log::error!("Failed to remove errored call {call_sid}: {e:?}");
+        }
+
+        if let Err(e) = database
+            .update_attempt_judgement(call_sid.to_owned(), ERRORED_STATUS.to_owned())
+            .await
+        {
+            log::error!("Failed to mark attempt of call {call_sid} as errored: {e:?}");
+        }
+
+        twiml.add(&say(HANGUP_TEXT));
+        twiml.add(&Hangup);
+
+        twiml
+    }
+}
+
+fn say(text: &str) -> Say {
+    Say {
+        txt: escape_speech(text),
+        voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
+        language: CONFIG.settings.language.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{test_cached_call, test_call};
+
+    #[tokio::test]
+    async fn retry_redirects_and_keeps_call() {
+        let cache = CallStore::memory();
+        let database = Database::unreachable();
+        let call = test_call(None);
+        cache.insert(&call.sid, test_cached_call()).await.unwrap();
+
+        let twiml = GameError::Completion(anyhow::anyhow!("rate limited"))
+            .fallback(&call.sid, &cache, &database, Some("/redirect-gather/name"))
+            .await
+            .as_twiml();
+
+        assert!(twiml.contains(RETRY_TEXT));
+        assert!(twiml.contains("<Redirect method=\"POST\">/redirect-gather/name</Redirect>"));
+        assert!(!twiml.contains("<Hangup"));
+        assert!(cache.get(&call.sid).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn hangup_removes_call() {
+        let cache = CallStore::memory();
+        let database = Database::unreachable();
+        let call = test_call(None);
+        cache.insert(&call.sid, test_cached_call()).await.unwrap();
+
+        let twiml = GameError::Database(anyhow::anyhow!("connection refused"))
+            .fallback(&call.sid, &cache, &database, None)
+            .await
+            .as_twiml();
+
+        assert!(twiml.contains(HANGUP_TEXT));
+        assert!(twiml.contains("<Hangup"));
+        assert!(!twiml.contains("<Redirect"));
+        assert!(cache.get(&call.sid).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn missing_call_is_not_retried() {
+        let cache = CallStore::memory();
+        let database = Database::unreachable();
+        let call = test_call(None);
+
+        let twiml = GameError::CallNotFound
+            .fallback(&call.sid, &cache, &database, Some("/redirect-gather/name"))
+            .await
+            .as_twiml();
+
+        assert!(twiml.contains("<Hangup"));
+        assert!(!twiml.contains("<Redirect"));
+    }
+}
@@ -1,11 +1,10 @@
-use crate::{cache::CachedCall, CONFIG};
+use crate::{cache::CallStore, database::Database, game::error::GameError, CONFIG};
 use axum::{
     extract::{Path, Request},
+    http::StatusCode,
     response::IntoResponse,
     Extension, RequestExt,
 };
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
 use twilio::{
     twiml::{Gather, GatherInput, SpeechTimeout, Twiml},
     Call, Client,
@@ -13,42 +12,71 @@ use twilio::{
 
 pub async fn redirect_gather_handler(
     twilio: Extension<Client>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    cache: Extension<CallStore>,
+    database: Extension<Database>,
     mut request: Request,
 ) -> impl IntoResponse {
-    let path = request
-        .extract_parts::<Path<String>>()
-        .await
-        .expect("Failed to extract path")
-        .0;
+    let Ok(Path(path)) = request.extract_parts::<Path<String>>().await else {
+        log::error!("Failed to extract the gather path");
+        return StatusCode::NOT_FOUND.into_response();
+    };
 
     twilio
         .respond_to_webhook_async(request, |call: Call| async move {
-            {
-                // Update the last timestamp in the conversation cache
-                cache
-                    .lock()
-                    .await
-                    .get_mut(&call.sid)
-                    .expect("Failed to get message conversation")
-                    .end_last_message();
+            match gather(&cache, &call, &path).await {
+                Ok(twiml) => twiml,
+                Err(e) => e.fallback(&call.sid, &cache, &database, None).await,
             }
+        })
+        .await
+        .into_response()
+}
 
-            let mut twiml = Twiml::new();
+/// Collects the caller's response and sends it to the given path.
+pub(super) async fn gather(cache: &CallStore, call: &Call, path: &str) -> Result<Twiml, GameError> {
+    // Update the last timestamp in the conversation cache
+    cache
+        .update(&call.sid, |cached_call| cached_call.end_last_message())
+        .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)?;
 
-            log::debug!("Gathering user response");
+    let mut twiml = Twiml::new();
 
-            // Collect the user's response and send it to the name handler
-            twiml.add(&Gather {
-                timeout_seconds: CONFIG.settings.timeout as u32,
-                action: Some(format!("/{path}")),
-                input: Some(GatherInput::Speech),
-                speech_timeout: Some(SpeechTimeout::Auto),
-                speech_model: Some(CONFIG.settings.speech_model.to_owned()),
-                ..Default::default()
-            });
+    log::debug!("Gathering user response");
 
-            twiml
-        })
-        .await
+    // Collect the user's response and send it to the name handler
+    twiml.add(&Gather {
+        timeout_seconds: CONFIG.settings.timeout as u32,
+        action: Some(format!("/{path}")),
+        input: Some(GatherInput::Speech),
+        speech_timeout: Some(SpeechTimeout::Auto),
+        speech_model: Some(CONFIG.settings.speech_model.to_owned()),
+        ..Default::default()
+    });
+
+    Ok(twiml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::test_call;
+
+    #[tokio::test]
+    async fn missing_call_hangs_up() {
+        let cache = CallStore::memory();
+        let call = test_call(None);
+
+        let error = gather(&cache, &call, "name")
+            .await
+            .expect_err("Gathering a response of an unknown call must fail");
+        assert!(matches!(error, GameError::CallNotFound));
+
+        let twiml = error
+            .fallback(&call.sid, &cache, &Database::unreachable(), None)
+            .await
+            .as_twiml();
+        assert!(twiml.contains("<Hangup"));
+    }
 }
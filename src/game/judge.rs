@@ -1,28 +1,41 @@
-use crate::{cache::CachedCall, database::Database, secrets::Secrets, video::render_video, CONFIG};
-use anyhow::{Context, Result};
-use async_openai::{
-    config::OpenAIConfig,
-    types::{CreateChatCompletionRequestArgs, ResponseFormat, ResponseFormatJsonSchema},
-    Client as OpenAIClient,
+use crate::{
+    cache::{CachedCall, CallStore},
+    claim::claim_window,
+    database::{Database, ReservedPrize, Sponsor},
+    game::{
+        approval::{hold_win, needs_approval},
+        consensus::{decide, JudgingMode},
+        error::{GameError, ERRORED_STATUS},
+        guard::{check_transcript, GuardAction},
+        reward::{reward_for_rating, Reward},
+        status::FINISHED_END_REASON,
+        texts::won_context,
+    },
+    llm::{LlmProvider, LlmProviders, LlmRequest, LlmTask},
+    secrets::Secrets,
+    solana::{nft::nft_prize, prize::PrizeKind},
+    template::{Template, TemplateContext},
+    video::render_video,
+    CONFIG,
+};
+use anyhow::{anyhow, Context, Result};
+use async_openai::types::{
+    ChatCompletionRequestMessage, ResponseFormat, ResponseFormatJsonSchema,
 };
 use axum::{extract::Request, response::IntoResponse, Extension};
+use chrono::Utc;
 use reqwest::Client as ReqwestClient;
 use reqwest::header::COOKIE;
 use serde::Deserialize;
 use serde_json::json;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
 use twilio::{twiml::Twiml, Call, Client as TwilioClient, OutboundMessage};
-use crate::solana::transfer::transfer_solana_token;
-use crate::solana::keys::generate_private_key;
-use solana_sdk::signature::Signer;
 
 
 pub async fn judge_handler(
     twilio: Extension<TwilioClient>,
     reqwest: Extension<ReqwestClient>,
-    openai: Extension<OpenAIClient<OpenAIConfig>>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    llm: Extension<LlmProviders>,
+    cache: Extension<CallStore>,
     database: Extension<Database>,
     secrets: Extension<Secrets>,
     request: Request,
@@ -30,21 +43,23 @@ pub async fn judge_handler(
     twilio
         .clone()
         .respond_to_webhook_async(request, |call: Call| async move {
-            let cached_call = {
-                let mut cache = cache.lock().await;
-                let mut cached_call = cache
-                    .remove(&call.sid)
-                    .expect("Failed to get message conversation");
-
-                cached_call.end_last_message();
-                cached_call.clone()
+            let mut cached_call = match remove_cached_call(&cache, &call).await {
+                Ok(cached_call) => cached_call,
+                Err(e) => return e.fallback(&call.sid, &cache, &database, None).await,
             };
 
+            cached_call.end_last_message();
+
+            // The status callback can not tell finished calls from other calls that are not cached
+            if let Err(e) = database.record_end_reason(&call.sid, FINISHED_END_REASON).await {
+                log::error!("Failed to record end reason of call {}: {e:?}", call.sid);
+            }
+
             tokio::spawn(judge_conversation(
                 twilio.0,
                 reqwest.0,
                 call.from,
-                openai.0,
+                llm.0,
                 database.0,
                 secrets.0,
                 call.sid,
@@ -56,82 +71,79 @@ pub async fn judge_handler(
         .await
 }
 
-#[derive(Debug, Deserialize)]
+/// Removes the call from the cache, so it is judged exactly once.
+pub(super) async fn remove_cached_call(cache: &CallStore, call: &Call) -> Result<CachedCall, GameError> {
+    cache
+        .remove(&call.sid)
+        .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)
+}
+
+/// The temperature added for every further judge of a call.
+const JUDGE_TEMPERATURE_STEP: f32 = 0.4;
+
+/// The highest temperature a judge uses, higher ones make the judgement unreliable.
+const MAX_JUDGE_TEMPERATURE: f32 = 1.2;
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct JudgeResponse {
     pub won_prize: bool,
     pub rating: u8,
     pub explanation: String,
 }
 
-async fn judge_conversation(
+pub async fn judge_conversation(
     twilio: TwilioClient,
     reqwest: ReqwestClient,
     caller_phone_number: String,
-    openai: OpenAIClient<OpenAIConfig>,
+    llm: LlmProviders,
     database: Database,
     secrets: Secrets,
     call_sid: String,
     cached_call: CachedCall,
 ) {
-    let schema = json!({
-        "type": "object",
-        "properties": {
-            "won_prize": {
-                "type": "boolean",
-                "description": CONFIG.end.won_schema_property
-            },
-            "rating": {
-                "type": "integer",
-                "description": CONFIG.end.rating_schema_property
-            },
-            "explanation": {
-                "type": "string",
-                "description": CONFIG.end.explanation_schema_property
-            }
-        },
-        "required": ["won_prize", "rating", "explanation"],
-        "additionalProperties": false,
-    });
-
-    let response_format = ResponseFormat::JsonSchema {
-        json_schema: ResponseFormatJsonSchema {
-            description: Some(CONFIG.end.schema_description.to_owned()),
-            name: "call_analyzing".to_owned(),
-            schema: Some(schema),
-            strict: Some(true),
-        },
-    };
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(CONFIG.end.max_tokens as u32)
-        .model(CONFIG.end.model)
-        .messages(cached_call.messages.clone())
-        .response_format(response_format)
-        .build()
-        .expect("Failed to build chat completion request");
-
-    let response = openai
-        .chat()
-        .create(request)
-        .await
-        .expect("Failed to create chat completion");
-
-    let choice = response
-        .choices
-        .first()
-        .expect("Failed to get first choice");
-
-    let content = choice
-        .message
-        .content
-        .as_ref()
-        .expect("Failed to get content");
+    let result = judge_and_notify(
+        twilio,
+        reqwest,
+        caller_phone_number,
+        llm,
+        database.clone(),
+        secrets,
+        call_sid.clone(),
+        cached_call,
+    )
+    .await;
 
-    println!("user: {}, judgement: {}", caller_phone_number, content);
+    if let Err(e) = result {
+        log::error!("Failed to judge call {call_sid}: {e:?}");
+
+        if let Err(e) = database
+            .update_attempt_judgement(call_sid.clone(), ERRORED_STATUS.to_owned())
+            .await
+        {
+            log::error!("Failed to mark attempt of call {call_sid} as errored: {e:?}");
+        }
+    }
+}
 
-    let judged: JudgeResponse =
-        serde_json::from_str(&content).expect("Failed to judge conversation");
+/// Guards and judges the transcript, stores the judgement and lets the caller know whether
+/// they won. Errors after the judgement has been stored are only logged, the attempt keeps
+/// its judgement.
+async fn judge_and_notify(
+    twilio: TwilioClient,
+    reqwest: ReqwestClient,
+    caller_phone_number: String,
+    llm: LlmProviders,
+    database: Database,
+    secrets: Secrets,
+    call_sid: String,
+    cached_call: CachedCall,
+) -> Result<()> {
+    let flagged = guard_attempt(&llm, &database, &call_sid, &cached_call).await?;
+    let mut judged = judge_panel(&llm, &database, &call_sid, &cached_call).await?;
 
+    log::debug!("Judgement of call {call_sid}: {judged:?}");
 
     log::debug!(
         "Judged conversation a {}/10 with explanation: {}",
@@ -147,8 +159,7 @@ async fn judge_conversation(
     let _attempt = database
         .update_attempt_judgement(call_sid.clone(), judged.explanation.clone())
         .await
-        .context("Updating attempt with judgement")
-        .expect("Failed to update attempt with judgement");
+        .context("Updating attempt with judgement")?;
 
     tokio::spawn(render_video(
         reqwest.clone(),
@@ -165,47 +176,301 @@ async fn judge_conversation(
     let _attempt = database
         .update_attempt_video(caller_phone_number.clone(), video_url.clone(), call_sid.clone())
         .await
-        .context("Updating attempt with is_winner true")
-        .expect("Failed to update attempt with video url");
+        .context("Updating attempt with video url")?;
+
+    // The rating of the call decides the tier of the prize
+    let reward = match judged.won_prize {
+        true => {
+            let tiers = database
+                .get_reward_tiers(cached_call.sponsor.id)
+                .await
+                .context("Getting reward tiers")?;
+            Some(reward_for_rating(&cached_call.sponsor, &tiers, Some(judged.rating as i32)))
+        }
+        false => None,
+    };
 
+    if let Some(reward) = &reward {
+        let hold_reason = match flagged {
+            Some((GuardAction::ForceLoss, _)) => {
+                log::debug!("Denying win of flagged call {call_sid}");
+                judged.won_prize = false;
+                None
+            }
+            Some((GuardAction::ManualReview, reason)) => {
+                Some(format!("Held by the injection guard: {reason}"))
+            }
+            // The jackpot is won on top of the prize
+            _ if needs_approval(&cached_call.sponsor, reward.tokens + cached_call.sponsor.jackpot_tokens) => Some(format!(
+                "The prize of {} tokens is above the approval threshold",
+                reward.tokens + cached_call.sponsor.jackpot_tokens
+            )),
+            _ => None,
+        };
+
+        if let Some(reason) = hold_reason {
+            return hold_win(
+                &twilio,
+                &database,
+                &secrets,
+                &call_sid,
+                &cached_call,
+                reward,
+                &judged.explanation,
+                &video_url,
+                &reason,
+            )
+            .await
+            .context("Holding back win for approval");
+        }
+    }
 
-    let result = match judged.won_prize {
-        true => won_handler(twilio, database, secrets, caller_phone_number, call_sid.clone(), cached_call, video_url).await,
-        false => lost_handler(twilio, database, secrets, caller_phone_number, call_sid.clone(), cached_call).await,
+    let result = match reward {
+        Some(reward) => {
+            let paid = won_handler(
+                twilio.clone(),
+                database.clone(),
+                secrets.clone(),
+                caller_phone_number.clone(),
+                call_sid.clone(),
+                cached_call.name.clone(),
+                cached_call.sponsor.clone(),
+                video_url.clone(),
+                Some(judged.rating as i32),
+                &reward,
+            )
+            .await;
+
+            // The winner is not told they lost because the pool ran low, the win waits for
+            // the sponsor to top up and an operator to approve it
+            match paid {
+                Ok(false) => {
+                    let reason = format!("The pool of the sponsor can not cover the prize of {} tokens", reward.tokens);
+                    hold_win(&twilio, &database, &secrets, &call_sid, &cached_call, &reward, &judged.explanation, &video_url, &reason).await
+                }
+                paid => paid.map(|_| ()),
+            }
+        }
+        None => lost_handler(twilio, database, secrets, caller_phone_number, call_sid.clone(), cached_call.name, cached_call.sponsor).await,
     };
 
     if let Err(e) = result {
         log::error!("Failed to handle call judge result: {e:?}");
     }
+
+    Ok(())
 }
 
-async fn won_handler(
+/// Checks the transcript for attempts to manipulate the judge and stores the verdict
+/// with the attempt. Returns the guard action and the reason if the attempt was flagged or
+/// could not be checked.
+async fn guard_attempt(
+    llm: &LlmProviders,
+    database: &Database,
+    call_sid: &str,
+    cached_call: &CachedCall,
+) -> Result<Option<(GuardAction, String)>> {
+    let action = GuardAction::from(cached_call.sponsor.guard_action.as_str());
+    if action == GuardAction::Off {
+        return Ok(None);
+    }
+
+    let provider = llm.get(&cached_call.sponsor.llm_provider);
+    let verdict = match check_transcript(provider.as_ref(), &cached_call.messages).await {
+        Ok(verdict) => verdict,
+        // A win without a verdict can not be trusted, but should not be denied either. The
+        // attempt is not flagged, as no injection was detected.
+        Err(e) => {
+            log::error!("Failed to guard call {call_sid}: {e:?}");
+            let reason = format!("The transcript could not be checked: {e}");
+
+            database
+                .update_attempt_guard(call_sid, false, &reason)
+                .await
+                .context("Updating attempt with guard verdict")?;
+
+            return Ok(Some((GuardAction::ManualReview, reason)));
+        }
+    };
+
+    if verdict.injection {
+        log::debug!("Flagged call {call_sid}: {}", verdict.reason);
+    }
+
+    database
+        .update_attempt_guard(call_sid, verdict.injection, &verdict.reason)
+        .await
+        .context("Updating attempt with guard verdict")?;
+
+    Ok(verdict.injection.then_some((action, verdict.reason)))
+}
+
+/// Lets the judges of the sponsor's judging mode judge the call one after another and
+/// stores every judgement. Judges that fail are skipped, as long as one of them succeeds.
+async fn judge_panel(
+    llm: &LlmProviders,
+    database: &Database,
+    call_sid: &str,
+    cached_call: &CachedCall,
+) -> Result<JudgeResponse> {
+    let sponsor = &cached_call.sponsor;
+    let mode = JudgingMode::from(sponsor.judging_mode.as_str());
+    let judge_count = match mode {
+        JudgingMode::Single => 1,
+        _ => sponsor.judge_count.max(1),
+    };
+
+    let provider = llm.get(&sponsor.llm_provider);
+    let mut judgements = Vec::new();
+
+    for judge_index in 0..judge_count {
+        // Only wins are appealed, a lost first judgement is final
+        if mode == JudgingMode::Appeal && judgements.len() == 1 && !judgements[0].won_prize {
+            break;
+        }
+
+        let temperature = judge_temperature(judge_index);
+        let judged =
+            match judge_transcript(provider.as_ref(), &cached_call.messages, temperature).await {
+                Ok(judged) => judged,
+                Err(e) => {
+                    log::error!("Judge {judge_index} failed to judge call {call_sid}: {e:?}");
+                    continue;
+                }
+            };
+
+        database
+            .create_judgement(
+                call_sid,
+                judge_index,
+                temperature,
+                judged.won_prize,
+                judged.rating as i32,
+                &judged.explanation,
+            )
+            .await
+            .context("Storing judgement")?;
+
+        judgements.push(judged);
+    }
+
+    log::debug!(
+        "Call {call_sid} judged by {} judges in {} mode",
+        judgements.len(),
+        mode.as_str()
+    );
+
+    decide(
+        mode,
+        &judgements,
+        sponsor.judge_quorum,
+        sponsor.rating_threshold,
+    )
+    .ok_or_else(|| anyhow!("None of the judges could judge the call"))
+}
+
+/// The first judge uses the model's default temperature, every further
+/// judge a higher one, so the judges do not all reach the same conclusion.
+fn judge_temperature(judge_index: i32) -> Option<f32> {
+    (judge_index > 0)
+        .then(|| (judge_index as f32 * JUDGE_TEMPERATURE_STEP).min(MAX_JUDGE_TEMPERATURE))
+}
+
+/// Asks the LLM whether the caller won the challenge and how they did.
+pub(super) async fn judge_transcript(
+    llm: &dyn LlmProvider,
+    messages: &[ChatCompletionRequestMessage],
+    temperature: Option<f32>,
+) -> Result<JudgeResponse> {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "won_prize": {
+                "type": "boolean",
+                "description": CONFIG.end.won_schema_property
+            },
+            "rating": {
+                "type": "integer",
+                "description": CONFIG.end.rating_schema_property
+            },
+            "explanation": {
+                "type": "string",
+                "description": CONFIG.end.explanation_schema_property
+            }
+        },
+        "required": ["won_prize", "rating", "explanation"],
+        "additionalProperties": false,
+    });
+
+    let response_format = ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+            description: Some(CONFIG.end.schema_description.to_owned()),
+            name: "call_analyzing".to_owned(),
+            schema: Some(schema),
+            strict: Some(true),
+        },
+    };
+
+    let content = llm
+        .complete(LlmRequest {
+            task: LlmTask::Judge,
+            messages: messages.to_vec(),
+            max_tokens: CONFIG.end.max_tokens as u32,
+            temperature,
+            response_format: Some(response_format),
+        })
+        .await
+        .context("Creating chat completion")?;
+
+    serde_json::from_str(&content).context("Parsing judgement from completion choice")
+}
+
+/// Reserves the reward and the jackpot for the winner and sends them their claim link.
+/// Returns `false` if the pool of the sponsor can not cover the reward, nothing is reserved
+/// then and the caller decides what the winner is told.
+pub(crate) async fn won_handler(
     twilio: TwilioClient,
     database: Database,
     secrets: Secrets,
     caller_phone_number: String,
     call_sid: String,
-    cached_call: CachedCall,
+    name: String,
+    sponsor: Sponsor,
     video_url: String,
-) -> Result<()> {
-    log::debug!("Won prize for sponsor: {}", cached_call.sponsor.name);
-
-    // Withdraw tokens from the sponsor
-    let withdrawn = database
-        .withdraw_tokens(cached_call.sponsor.id)
+    rating: Option<i32>,
+    reward: &Reward,
+) -> Result<bool> {
+    log::debug!("Won prize for sponsor: {}", sponsor.name);
+
+    // A call is only paid out once, even if its win is handled again
+    if database
+        .get_payout_by_call_sid(&call_sid)
         .await
-        .context("Withdrawing tokens")?;
+        .context("Getting payout")?
+        .is_some()
+    {
+        log::warn!("Call {call_sid} was already paid out");
+        return Ok(true);
+    }
 
-    // If withdrawing tokens failed, redirect to lost handler
-    if withdrawn.is_none() {
-        return lost_handler(twilio, database, secrets, caller_phone_number, call_sid.clone(), cached_call).await;
-    };
+    // The NFT is minted once the caller claims it, its metadata is generated from the attempt
+    // before the prize is reserved, so every reserved NFT can be minted
+    if PrizeKind::from(sponsor.prize_kind.as_str()) == PrizeKind::Nft {
+        database
+            .create_nft_prize(&nft_prize(&sponsor, &call_sid, &name, rating, &video_url, &secrets.global_url))
+            .await
+            .context("Storing NFT prize")?;
+    }
 
-    // Generate a winner entry in the database
-    let _winner = database
-        .create_winner(cached_call.name.clone(), cached_call.sponsor.id)
+    // Withdraw tokens and the jackpot from the sponsor and reserve them until the caller claims the prize
+    let reserved = database
+        .reserve_prize(&call_sid, sponsor.id, &name, reward.tokens, Utc::now() + claim_window(&secrets))
         .await
-        .context("Creating winner")?;
+        .context("Reserving prize")?;
+
+    let Some(ReservedPrize { winner, amount }) = reserved else {
+        return Ok(false);
+    };
 
 
     let _attempt = database
@@ -214,22 +479,8 @@ async fn won_handler(
         .context("Updating attempt with is_winner true")?;
 
 
-    let receiver_private_key = generate_private_key();
-    let receiver_public_key = receiver_private_key.pubkey();
-
-
-    let signature = transfer_solana_token(
-        &secrets,
-        cached_call.sponsor.private_key,
-        receiver_public_key,
-        cached_call.sponsor.token_mint,
-        cached_call.sponsor.reward_tokens.try_into().unwrap()
-    ).await.expect("Failed to transfer tokens");
-    println!("user: {}, signature: {}", caller_phone_number, signature);
-
-    // Generate the winning link
-    let link = format!("https://claim.why.fun/?key={}", receiver_private_key.to_base58_string());
-    println!("user: {}, link: {}", caller_phone_number, link);
+    // Generate the winning link, the key is a single use claim token
+    let link = format!("https://claim.why.fun/?key={}", winner.key);
 
     database.update_attempt_winner_url(
         caller_phone_number.clone(), 
@@ -238,12 +489,8 @@ async fn won_handler(
     ).await.context("Updating attempt with winner url")?;
 
     // Generate the winning text
-    let text = cached_call
-        .sponsor
-        .won_text
-        .replace("{name}", &cached_call.name)
-        .replace("{link}", &link)
-        .replace("{video_url}", &video_url);
+    let text = Template::parse(&sponsor.won_text)
+        .render(&won_context(&sponsor, &name, reward, amount, &link, &video_url));
 
     twilio
         .send_message(OutboundMessage {
@@ -254,29 +501,37 @@ async fn won_handler(
         .await
         .context("Sending message")?;
 
-    Ok(())
+    Ok(true)
 }
 
-async fn lost_handler(
+pub(crate) async fn lost_handler(
     twilio: TwilioClient,
     database: Database,
     secrets: Secrets,
     caller_phone_number: String,
     call_sid: String,
-    cached_call: CachedCall,
+    name: String,
+    sponsor: Sponsor,
 ) -> Result<()> {
-    log::debug!("Lost prize for sponsor: {}", cached_call.sponsor.name);
+    log::debug!("Lost prize for sponsor: {}", sponsor.name);
 
     let _attempt = database
         .update_attempt_winner(caller_phone_number.clone(), false, call_sid.clone())
         .await
         .context("Updating attempt with is_winner false")?;
 
+    // A failed contribution only leaves the jackpot smaller, the caller is still told the result
+    match database.contribute_to_jackpot(&call_sid, sponsor.id).await {
+        Ok(Some(jackpot)) => log::debug!("Jackpot of sponsor {} grew to {jackpot}", sponsor.id),
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to contribute call {call_sid} to the jackpot: {e:?}"),
+    }
+
     // Generate the loosing text
-    let text = cached_call
-        .sponsor
-        .lost_text
-        .replace("{name}", &cached_call.name);
+    let text = Template::parse(&sponsor.lost_text).render(&TemplateContext {
+        name: Some(name.clone()),
+        ..TemplateContext::for_sponsor(&sponsor)
+    });
 
     twilio
         .send_message(OutboundMessage {
@@ -289,3 +544,26 @@ async fn lost_handler(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::test_call;
+
+    #[tokio::test]
+    async fn missing_call_hangs_up() {
+        let cache = CallStore::memory();
+        let call = test_call(None);
+
+        let error = remove_cached_call(&cache, &call)
+            .await
+            .expect_err("Judging an unknown call must fail");
+        assert!(matches!(error, GameError::CallNotFound));
+
+        let twiml = error
+            .fallback(&call.sid, &cache, &Database::unreachable(), None)
+            .await
+            .as_twiml();
+        assert!(twiml.contains("<Hangup"));
+    }
+}
@@ -1,7 +1,21 @@
+pub mod approval;
 pub mod challenge;
+pub mod consensus;
 pub mod end;
+pub mod error;
 pub mod gather;
+pub mod guard;
 pub mod judge;
 pub mod name;
+pub mod payout;
+pub mod reaper;
+pub mod reconcile;
+pub mod reward;
 pub mod recording;
 pub mod start;
+pub mod status;
+pub mod sweep;
+pub mod texts;
+
+#[cfg(test)]
+mod tests;
@@ -1,18 +1,17 @@
-use crate::cache::CachedCall;
-use crate::database::Sponsor;
+use crate::cache::CallStore;
+use crate::database::{Database, Sponsor};
+use crate::game::error::GameError;
+use crate::llm::{LlmProvider, LlmProviders, LlmRequest, LlmTask};
+use crate::template::{escape_speech, Template, TemplateContext};
 use crate::CONFIG;
-use anyhow::{anyhow, Context, Result};
+use anyhow::{Context, Result};
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestUserMessageArgs,
-    CreateChatCompletionRequestArgs, ResponseFormat, ResponseFormatJsonSchema,
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestUserMessage, ResponseFormat,
+    ResponseFormatJsonSchema,
 };
-use async_openai::{config::OpenAIConfig, Client as OpenAIClient};
 use axum::{extract::Request, response::IntoResponse, Extension};
 use serde::Deserialize;
 use serde_json::json;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
 use twilio::twiml::{Method, Redirect};
 use twilio::{
     twiml::{Say, Twiml, Voice},
@@ -21,95 +20,101 @@ use twilio::{
 
 pub async fn name_handler(
     twilio: Extension<TwilioClient>,
-    openai: Extension<OpenAIClient<OpenAIConfig>>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    llm: Extension<LlmProviders>,
+    cache: Extension<CallStore>,
+    database: Extension<Database>,
     request: Request,
 ) -> impl IntoResponse {
     twilio
         .clone()
         .respond_to_webhook_async(request, |call: Call| async move {
-            let speech_confidence = call.speech_confidence.map(|c| c * 100.0);
-            log::debug!(
-                "Understood: {:?} with confidence {:?}%",
-                call.speech_result,
-                speech_confidence
-            );
-
-            // Extract the sponsor from the cache
-            let sponsor = {
-                let cache = cache.lock().await;
-                cache
-                    .get(&call.sid)
-                    .expect("Failed to get message conversation")
-                    .sponsor
-                    .clone()
-            };
-
-            // Try to extract the name from the transcription
-            let name = match &call.speech_result {
-                Some(text) => match extract_name(openai, text).await {
-                    Ok(name) => name,
-                    Err(e) => {
-                        log::error!("Failed to extract name: {:?}", e);
-                        None
-                    }
-                },
-                None => None,
-            };
-            log::debug!("Extracted name: {:?}", name);
-
-            // Generate the response based on the extracted name
-            let (twiml, response) = generate_name_response(name.clone(), sponsor).await;
-
-            // Update the conversation cache
-            update_conversation_cache(
-                &cache,
-                call.sid,
-                call.speech_result.unwrap_or_default(),
-                name,
-                response,
-            )
-            .await;
-
-            twiml
+            match handle_name(&llm, &cache, &call).await {
+                Ok(twiml) => twiml,
+                Err(e) => {
+                    e.fallback(&call.sid, &cache, &database, Some("/redirect-gather/name"))
+                        .await
+                }
+            }
         })
         .await
 }
 
+/// Extracts the name of the caller from the transcription and starts the challenge,
+/// or asks for the name again if none could be extracted.
+pub(super) async fn handle_name(
+    llm: &LlmProviders,
+    cache: &CallStore,
+    call: &Call,
+) -> Result<Twiml, GameError> {
+    let speech_confidence = call.speech_confidence.map(|c| c * 100.0);
+    log::debug!(
+        "Understood: {:?} with confidence {:?}%",
+        call.speech_result,
+        speech_confidence
+    );
+
+    // Extract the sponsor from the cache
+    let sponsor = cache
+        .get(&call.sid)
+        .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)?
+        .sponsor;
+
+    // Try to extract the name from the transcription
+    let name = match &call.speech_result {
+        Some(text) => match extract_name(llm.get(&sponsor.llm_provider).as_ref(), text).await {
+            Ok(name) => name,
+            Err(e) => {
+                log::error!("Failed to extract name: {:?}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    log::debug!("Extracted name: {:?}", name);
+
+    // Generate the response based on the extracted name
+    let (twiml, response) = generate_name_response(name.clone(), sponsor).await;
+
+    // Update the conversation cache
+    update_conversation_cache(
+        cache,
+        &call.sid,
+        call.speech_result.clone().unwrap_or_default(),
+        name,
+        response,
+    )
+    .await?;
+
+    Ok(twiml)
+}
+
 /// Updates the cached call messages:
 /// 1. Adds the recognized user message
 /// 2. Adds the generated assistant message
 async fn update_conversation_cache(
-    cache: &Arc<Mutex<HashMap<String, CachedCall>>>,
-    call_sid: String,
+    cache: &CallStore,
+    call_sid: &str,
     user_message: String,
     name: Option<String>,
     assistant_message: String,
-) {
-    let mut cache = cache.lock().await;
-    let cached_call = cache
-        .get_mut(&call_sid)
-        .expect("Failed to get message conversation");
-
-    if let Some(name) = name {
-        cached_call.name = name;
-    }
-
-    cached_call.add_user_message(
-        ChatCompletionRequestUserMessageArgs::default()
-            .content(user_message)
-            .build()
-            .expect("Failed to build user message")
-            .into(),
-    );
+) -> Result<(), GameError> {
+    cache
+        .update(call_sid, |cached_call| {
+            if let Some(name) = name {
+                cached_call.name = name;
+            }
 
-    cached_call.add_system_message(
-        ChatCompletionRequestAssistantMessageArgs::default()
-            .content(assistant_message)
-            .build()
-            .expect("Failed to build assistant message")
-            .into(),
-    )
+            cached_call
+                .add_user_message(ChatCompletionRequestUserMessage::from(user_message).into());
+            cached_call.add_system_message(
+                ChatCompletionRequestAssistantMessage::from(assistant_message).into(),
+            )
+        })
+        .await
+        .map_err(GameError::Cache)?
+        .ok_or(GameError::CallNotFound)
 }
 
 /// Generates the response based on the extracted name (if any):
@@ -118,10 +123,10 @@ async fn update_conversation_cache(
 async fn generate_name_response(name: Option<String>, sponsor: Sponsor) -> (Twiml, String) {
     // If a name could be extracted, start the challenge, otherwise ask for the name again
     let response = match &name {
-        Some(name) => sponsor
-            .start_text
-            .replace("{name}", name)
-            .replace("{duration}", &sponsor.challenge_time.to_string()),
+        Some(name) => Template::parse(&sponsor.start_text).render(&TemplateContext {
+            name: Some(name.clone()),
+            ..TemplateContext::for_sponsor(&sponsor)
+        }),
         None => CONFIG.texts.name_not_found.to_owned(),
     };
 
@@ -133,7 +138,7 @@ async fn generate_name_response(name: Option<String>, sponsor: Sponsor) -> (Twim
     // Generate the twilio response
     let mut twiml = Twiml::new();
     twiml.add(&Say {
-        txt: response.clone(),
+        txt: escape_speech(&response),
         voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
         language: CONFIG.settings.language.to_owned(),
     });
@@ -151,10 +156,7 @@ struct ExtractedName {
     name: String,
 }
 
-async fn extract_name(
-    openai: Extension<OpenAIClient<OpenAIConfig>>,
-    text: &str,
-) -> Result<Option<String>> {
+async fn extract_name(llm: &dyn LlmProvider, text: &str) -> Result<Option<String>> {
     let schema = json!({
         "type": "object",
         "properties": {
@@ -176,26 +178,15 @@ async fn extract_name(
         },
     };
 
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(CONFIG.name.max_tokens as u32)
-        .model(CONFIG.name.model)
-        .messages([ChatCompletionRequestUserMessageArgs::default()
-            .content(text)
-            .build()?
-            .into()])
-        .response_format(response_format)
-        .build()?;
-
-    let response = openai.chat().create(request).await?;
-    let choice = response
-        .choices
-        .first()
-        .ok_or_else(|| anyhow!("No first completion choice could be generated"))?;
-    let content = choice
-        .message
-        .content
-        .as_ref()
-        .ok_or_else(|| anyhow!("No content in the completion choice"))?;
+    let content = llm
+        .complete(LlmRequest {
+            task: LlmTask::Name,
+            messages: vec![ChatCompletionRequestUserMessage::from(text).into()],
+            max_tokens: CONFIG.name.max_tokens as u32,
+            temperature: None,
+            response_format: Some(response_format),
+        })
+        .await?;
 
     let extracted: ExtractedName =
         serde_json::from_str(&content).context("Extracting name from completion choice")?;
@@ -205,3 +196,58 @@ async fn extract_name(
         _ => Ok(Some(extracted.name)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{scripted_llm, test_call};
+    use crate::llm::ScriptedProvider;
+
+    #[tokio::test]
+    async fn missing_call_hangs_up() {
+        let cache = CallStore::memory();
+        let call = test_call(Some("My name is Alice"));
+
+        let error = handle_name(&scripted_llm(ScriptedProvider::new()), &cache, &call)
+            .await
+            .expect_err("Handling the name of an unknown call must fail");
+        assert!(matches!(error, GameError::CallNotFound));
+
+        let twiml = error
+            .fallback(
+                &call.sid,
+                &cache,
+                &Database::unreachable(),
+                Some("/redirect-gather/name"),
+            )
+            .await
+            .as_twiml();
+        assert!(twiml.contains("<Hangup"));
+    }
+
+    #[tokio::test]
+    async fn start_text_announces_the_jackpot() {
+        let sponsor = Sponsor {
+            start_text: "Hi {name}, the jackpot is {jackpot} tokens.".to_owned(),
+            jackpot_tokens: 12_500_000,
+            token_decimals: Some(6),
+            ..Default::default()
+        };
+
+        let (_, response) = generate_name_response(Some("Alice".to_owned()), sponsor).await;
+        assert_eq!(response, "Hi Alice, the jackpot is 12.5 tokens.");
+    }
+
+    #[tokio::test]
+    async fn names_are_escaped_in_the_twiml() {
+        let sponsor = Sponsor {
+            start_text: "Hi {name}!".to_owned(),
+            ..Default::default()
+        };
+
+        let (twiml, response) =
+            generate_name_response(Some("Tom & <Jerry>".to_owned()), sponsor).await;
+        assert_eq!(response, "Hi Tom & <Jerry>!");
+        assert!(twiml.as_twiml().contains("Hi Tom &amp; &lt;Jerry&gt;!"));
+    }
+}
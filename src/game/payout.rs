@@ -0,0 +1,238 @@
+use crate::{
+    database::{Database, Payout},
+    solana::{
+        nft::{nft_minted, sign_nft_mint},
+        prize::Prize,
+        service::SolanaService,
+        transfer::{
+            send_prize_transfer, sign_prize_transfer, transfer_status, SignedTransfer,
+            TransferStatus,
+        },
+    },
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use solana_sdk::pubkey::Pubkey;
+use std::{str::FromStr, time::Duration};
+
+/// How often unsettled payouts are checked and sent again.
+const PAYOUT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Payouts whose transfer failed this often are abandoned and refunded to the sponsor.
+const MAX_PAYOUT_RETRIES: i32 = 10;
+
+/// How long a payout stays claimed by an instance that did not record a signature yet.
+const SIGNING_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Periodically returns expired unclaimed prizes to the sponsors, confirms sent payouts and
+/// sends the payouts whose transfer failed again. Runs until the app shuts down.
+pub async fn run_payout_worker(database: Database, solana: SolanaService) {
+    let mut interval = tokio::time::interval(PAYOUT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match database.expire_unclaimed_prizes().await {
+            Ok(refunds) => {
+                for refund in refunds {
+                    log::info!(
+                        "Returned {} tokens of {} unclaimed prizes to sponsor {}",
+                        refund.amount,
+                        refund.prizes,
+                        refund.sponsor_id
+                    );
+                }
+            }
+            Err(e) => log::error!("Failed to expire unclaimed prizes: {e:?}"),
+        }
+
+        let payouts = match database.get_unsettled_payouts().await {
+            Ok(payouts) => payouts,
+            Err(e) => {
+                log::error!("Failed to get unsettled payouts: {e:?}");
+                continue;
+            }
+        };
+
+        for payout in payouts {
+            let id = payout.id;
+
+            if let Err(e) = process_payout(&database, &solana, payout).await {
+                log::error!("Failed to process payout {id}: {e:?}");
+            }
+        }
+    }
+}
+
+/// Moves the payout one step closer to being settled. A payout with a signature is only
+/// sent again once its transfer definitely failed, and only the instance that claimed the
+/// payout signs and sends it, so the prize is never sent twice.
+pub async fn process_payout(
+    database: &Database,
+    solana: &SolanaService,
+    payout: Payout,
+) -> Result<()> {
+    if let (Some(signature), Some(last_valid_block_height)) =
+        (&payout.signature, payout.last_valid_block_height)
+    {
+        let status = transfer_status(solana, signature, last_valid_block_height as u64).await?;
+
+        let error = match status {
+            TransferStatus::Confirmed => {
+                log::debug!("Confirmed payout {} with signature {signature}", payout.id);
+                return database
+                    .confirm_payout(payout.id)
+                    .await
+                    .context("Confirming payout");
+            }
+            TransferStatus::Pending => return Ok(()),
+            TransferStatus::Failed(error) => error,
+            TransferStatus::Expired => "The transfer expired".to_owned(),
+        };
+
+        if !record_failure(database, &payout, &error).await? {
+            return Ok(());
+        }
+    }
+
+    let stale_before = Utc::now() - SIGNING_TIMEOUT;
+    let Some(payout) = database
+        .claim_payout(payout.id, stale_before)
+        .await
+        .context("Claiming payout")?
+    else {
+        // Another instance is sending the payout
+        return Ok(());
+    };
+
+    let transfer = match sign_payout(database, solana, &payout).await {
+        Ok(Some(transfer)) => transfer,
+        Ok(None) => {
+            log::debug!("The NFT of payout {} was minted already", payout.id);
+            return database
+                .confirm_payout(payout.id)
+                .await
+                .context("Confirming payout");
+        }
+        Err(e) => {
+            record_failure(database, &payout, &format!("{e:#}")).await?;
+            return Err(e);
+        }
+    };
+
+    let recorded = database
+        .record_payout_signature(
+            payout.id,
+            payout.updated_at,
+            &transfer.signature.to_string(),
+            transfer.last_valid_block_height as i64,
+        )
+        .await
+        .context("Recording payout signature")?;
+
+    if !recorded {
+        bail!("The claim of the payout was taken over while signing");
+    }
+
+    // The transfer may have been sent even if this fails, its status is checked next time
+    send_prize_transfer(solana, &transfer).await?;
+    log::debug!(
+        "Sent payout {} with signature {}",
+        payout.id,
+        transfer.signature
+    );
+
+    Ok(())
+}
+
+/// Signs the transfer of the prize to the receiver of the payout. NFT prizes are minted to the
+/// receiver instead, the mint is recorded before the transaction is sent.
+/// Returns `None` if the recorded mint of the NFT prize exists, it must not be minted again.
+async fn sign_payout(
+    database: &Database,
+    solana: &SolanaService,
+    payout: &Payout,
+) -> Result<Option<SignedTransfer>> {
+    let sponsor = database
+        .get_sponsor_by_id(payout.sponsor_id)
+        .await
+        .context("Getting sponsor of payout")?;
+    let receiver_pubkey = payout
+        .receiver_pubkey
+        .as_deref()
+        .context("Payout has no receiver")?;
+    let receiver_pubkey =
+        Pubkey::from_str(receiver_pubkey).context("Parsing receiver public key")?;
+    let amount = payout
+        .amount
+        .try_into()
+        .context("Converting payout amount")?;
+
+    let prize = Prize::of_sponsor(solana, &sponsor).await?;
+
+    if prize == Prize::Nft {
+        let nft = database
+            .get_nft_prize(&payout.call_sid)
+            .await
+            .context("Getting NFT prize")?
+            .context("Payout has no NFT prize")?;
+
+        if let Some(mint) = &nft.mint {
+            if nft_minted(solana, mint).await? {
+                return Ok(None);
+            }
+        }
+
+        let mint = sign_nft_mint(solana, &sponsor.private_key, &receiver_pubkey, &nft).await?;
+
+        database
+            .record_nft_mint(&payout.call_sid, &mint.mint.to_string())
+            .await
+            .context("Recording NFT mint")?;
+
+        return Ok(Some(mint.transfer));
+    }
+
+    Ok(Some(
+        sign_prize_transfer(
+            solana,
+            &sponsor.private_key,
+            &receiver_pubkey,
+            &prize,
+            amount,
+        )
+        .await?,
+    ))
+}
+
+/// Records the failed transfer and abandons the payout once it failed too often.
+/// Returns whether the payout should be sent again, which is not the case either if
+/// another instance already handled the failure.
+async fn record_failure(database: &Database, payout: &Payout, error: &str) -> Result<bool> {
+    log::warn!("Payout {} failed: {error}", payout.id);
+
+    let Some(retries) = database
+        .record_payout_failure(payout.id, payout.updated_at, error)
+        .await
+        .context("Recording payout failure")?
+    else {
+        return Ok(false);
+    };
+
+    if retries < MAX_PAYOUT_RETRIES {
+        return Ok(true);
+    }
+
+    log::error!(
+        "Abandoning payout {} of call {} after {retries} failed transfers, refunding {} tokens",
+        payout.id,
+        payout.call_sid,
+        payout.amount
+    );
+    database
+        .abandon_payout(payout.id)
+        .await
+        .context("Abandoning payout")?;
+
+    Ok(false)
+}
@@ -0,0 +1,147 @@
+use crate::{
+    cache::{CachedCall, CallStore},
+    database::Database,
+    game::judge::judge_conversation,
+    llm::LlmProviders,
+    secrets::Secrets,
+};
+use anyhow::{Context, Result};
+use async_openai::types::ChatCompletionRequestMessage;
+use chrono::Utc;
+use reqwest::Client as ReqwestClient;
+use std::time::Duration;
+use twilio::{Client as TwilioClient, TwilioError};
+
+/// Calls without any webhook activity for this long are checked with twilio.
+const STALE_CALL_TIMEOUT: chrono::Duration = chrono::Duration::minutes(10);
+
+/// How often the reaper looks for stale calls.
+const REAPER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The challenge status of attempts that hung up before the call ended.
+pub const ABANDONED_STATUS: &str = "abandoned";
+
+/// Periodically cleans up calls that never reached the /judge route, for example
+/// because the caller hung up during the challenge. Runs until the app shuts down.
+pub async fn run_reaper(
+    twilio: TwilioClient,
+    reqwest: ReqwestClient,
+    llm: LlmProviders,
+    database: Database,
+    secrets: Secrets,
+    cache: CallStore,
+) {
+    let mut interval = tokio::time::interval(REAPER_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let stale_calls = match cache.stale_calls(Utc::now() - STALE_CALL_TIMEOUT).await {
+            Ok(stale_calls) => stale_calls,
+            Err(e) => {
+                log::error!("Failed to list stale calls: {e:?}");
+                continue;
+            }
+        };
+
+        for (call_sid, _) in stale_calls {
+            // Only clean up calls that twilio no longer considers active,
+            // a caller could be silent for a long time without hanging up
+            match twilio.get_call(&call_sid).await {
+                Ok(call) if !call.status.is_final() => continue,
+                Ok(_) | Err(TwilioError::HTTPError(reqwest::StatusCode::NOT_FOUND)) => {}
+                Err(e) => {
+                    log::error!("Failed to get status of stale call {call_sid}: {e:?}");
+                    continue;
+                }
+            }
+
+            let result = abandon_call(
+                twilio.clone(),
+                reqwest.clone(),
+                llm.clone(),
+                database.clone(),
+                secrets.clone(),
+                &cache,
+                &call_sid,
+            )
+            .await;
+
+            if let Err(e) = result {
+                log::error!("Failed to clean up abandoned call {call_sid}: {e:?}");
+            }
+        }
+    }
+}
+
+/// Removes a call that ended before reaching the /judge route from the cache.
+/// If the caller said anything during the challenge and either the challenge time
+/// ran out or the sponsor wants abandoned calls to be judged, the transcript is judged
+/// as usual. Otherwise the attempt is marked as abandoned and the recording is removed.
+pub async fn abandon_call(
+    twilio: TwilioClient,
+    reqwest: ReqwestClient,
+    llm: LlmProviders,
+    database: Database,
+    secrets: Secrets,
+    cache: &CallStore,
+    call_sid: &str,
+) -> Result<()> {
+    // Removing the call makes sure only a single instance cleans it up
+    let Some(mut cached_call) = cache
+        .remove(call_sid)
+        .await
+        .context("Removing abandoned call")?
+    else {
+        return Ok(());
+    };
+
+    log::debug!("Cleaning up abandoned call {call_sid}");
+
+    let challenge_finished = cached_call
+        .challenge_end
+        .is_some_and(|end| end <= Utc::now());
+
+    if (challenge_finished || cached_call.sponsor.judge_abandoned)
+        && has_challenge_response(&cached_call)
+    {
+        cached_call.end_last_message();
+
+        tokio::spawn(judge_conversation(
+            twilio,
+            reqwest,
+            cached_call.phone_number.clone(),
+            llm,
+            database,
+            secrets,
+            call_sid.to_owned(),
+            cached_call,
+        ));
+
+        return Ok(());
+    }
+
+    database
+        .update_attempt_judgement(call_sid.to_owned(), ABANDONED_STATUS.to_owned())
+        .await
+        .context("Updating attempt with abandoned status")?;
+
+    database
+        .update_attempt_winner(cached_call.phone_number, false, call_sid.to_owned())
+        .await
+        .context("Updating attempt with is_winner false")?;
+
+    let _ = tokio::fs::remove_dir_all(format!("cache/recordings/{call_sid}")).await;
+
+    Ok(())
+}
+
+/// Whether the caller responded to the challenge, the first user message is the name.
+fn has_challenge_response(cached_call: &CachedCall) -> bool {
+    cached_call
+        .messages
+        .iter()
+        .filter(|message| matches!(message, ChatCompletionRequestMessage::User(_)))
+        .count()
+        > 1
+}
@@ -0,0 +1,150 @@
+use crate::{
+    database::{BalanceReconciliation, Database, Sponsor},
+    game::reward::highest_reward,
+    solana::{error::parse_pubkey, prize::Prize, service::SolanaService, transfer::prize_balance},
+};
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+/// How often the pools of the sponsors are compared with their wallets.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Periodically compares the pool of every funded sponsor with the token balance of their
+/// wallet, and deactivates sponsors that can not pay out another prize. Runs until the app
+/// shuts down.
+pub async fn run_reconciliation(database: Database, solana: SolanaService) {
+    let mut interval = tokio::time::interval(RECONCILE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let sponsors = match database.get_funded_sponsors().await {
+            Ok(sponsors) => sponsors,
+            Err(e) => {
+                log::error!("Failed to get funded sponsors: {e:?}");
+                continue;
+            }
+        };
+
+        for sponsor in sponsors {
+            if let Err(e) = reconcile_sponsor(&database, &solana, &sponsor).await {
+                log::error!("Failed to reconcile sponsor {}: {e:?}", sponsor.id);
+            }
+        }
+    }
+}
+
+async fn reconcile_sponsor(
+    database: &Database,
+    solana: &SolanaService,
+    sponsor: &Sponsor,
+) -> Result<()> {
+    let sponsor_pubkey = parse_pubkey(&sponsor.public_key, "sponsor")?;
+    let prize = Prize::of_sponsor(solana, sponsor).await?;
+    let onchain_tokens = prize_balance(solana, &sponsor_pubkey, &prize)
+        .await?
+        .try_into()
+        .context("Converting token balance")?;
+    let outstanding = database
+        .get_outstanding_payouts(sponsor.id)
+        .await
+        .context("Getting outstanding payouts")?;
+
+    // The jackpot is held in the wallet next to the pool
+    let available_tokens = sponsor.available_tokens + sponsor.jackpot_tokens;
+    let balance = compare_balance(
+        onchain_tokens,
+        available_tokens,
+        outstanding.owed,
+        outstanding.in_flight,
+    );
+
+    if balance.mismatch {
+        log::warn!(
+            "Pool of sponsor {} is off by {} tokens from their wallet",
+            sponsor.id,
+            balance.difference
+        );
+    }
+
+    let tiers = database
+        .get_reward_tiers(sponsor.id)
+        .await
+        .context("Getting reward tiers")?;
+    let highest_prize = highest_reward(sponsor, tiers.iter().map(|tier| tier.reward_tokens));
+
+    let deactivated = sponsor.active && balance.spendable < highest_prize;
+    if deactivated {
+        database
+            .deactivate_sponsor(sponsor.id)
+            .await
+            .context("Deactivating sponsor")?;
+        log::warn!(
+            "Deactivated sponsor {}, their wallet can not cover a prize of {} tokens",
+            sponsor.id,
+            highest_prize
+        );
+    }
+
+    database
+        .record_reconciliation(&BalanceReconciliation {
+            sponsor_id: sponsor.id,
+            onchain_tokens,
+            available_tokens,
+            owed_tokens: outstanding.owed,
+            in_flight_tokens: outstanding.in_flight,
+            difference: balance.difference,
+            mismatch: balance.mismatch,
+            deactivated,
+        })
+        .await
+        .context("Recording reconciliation")
+}
+
+#[derive(Debug, PartialEq)]
+struct Balance {
+    /// How many tokens the wallet holds more than the pool and the outstanding prizes
+    difference: i64,
+    mismatch: bool,
+    /// The tokens of the wallet that are not owed to winners
+    spendable: i64,
+}
+
+/// Compares the wallet balance with the pool plus the prizes that were not paid out yet.
+/// Prizes that were sent may or may not have left the wallet, so both are accepted.
+fn compare_balance(onchain: i64, available: i64, owed: i64, in_flight: i64) -> Balance {
+    let difference = onchain - (available + owed + in_flight);
+
+    Balance {
+        difference,
+        mismatch: difference > 0 || difference < -in_flight,
+        spendable: onchain - owed - in_flight,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balance_matches_pool_and_owed_prizes() {
+        let balance = compare_balance(1500, 1000, 500, 0);
+
+        assert!(!balance.mismatch);
+        assert_eq!(balance.spendable, 1000);
+    }
+
+    #[test]
+    fn sent_prizes_may_have_left_the_wallet() {
+        assert!(!compare_balance(1500, 1000, 0, 500).mismatch);
+        assert!(!compare_balance(1000, 1000, 0, 500).mismatch);
+        assert!(compare_balance(900, 1000, 0, 500).mismatch);
+    }
+
+    #[test]
+    fn flags_drift_in_both_directions() {
+        assert_eq!(compare_balance(1200, 1000, 0, 0).difference, 200);
+        assert!(compare_balance(1200, 1000, 0, 0).mismatch);
+        assert!(compare_balance(800, 1000, 0, 0).mismatch);
+    }
+}
@@ -15,10 +15,16 @@ pub async fn recording_handler(
             );
 
             // Download the recording
-            let mp3 = twilio
-                .download_recording(&recording.sid)
-                .await
-                .expect("Failed to download recording");
+            let mp3 = match twilio.download_recording(&recording.sid).await {
+                Ok(mp3) => mp3,
+                Err(e) => {
+                    log::error!(
+                        "Failed to download recording of call {}: {e:?}",
+                        recording.call_sid
+                    );
+                    return Twiml::new();
+                }
+            };
 
             let audio_path = format!("cache/recordings/{}/audio.mp3", recording.call_sid);
 
@@ -27,9 +33,12 @@ pub async fn recording_handler(
                 tokio::fs::create_dir_all(format!("cache/recordings/{}", recording.call_sid)).await;
 
             // Write the audio to disk
-            tokio::fs::write(&audio_path, mp3)
-                .await
-                .expect("Failed to write recording");
+            if let Err(e) = tokio::fs::write(&audio_path, mp3).await {
+                log::error!(
+                    "Failed to write recording of call {}: {e:?}",
+                    recording.call_sid
+                );
+            }
 
             Twiml::new()
         })
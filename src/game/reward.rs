@@ -0,0 +1,209 @@
+use crate::{
+    api::RewardTierArgs,
+    database::{RewardTier, Sponsor},
+    solana::prize::{PrizeKind, MIN_SOL_PRIZE_LAMPORTS},
+};
+use anyhow::{bail, Result};
+
+/// The highest rating a judge gives.
+pub const MAX_RATING: i32 = 10;
+
+/// What a winner is paid, picked by the rating of their call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reward {
+    pub tokens: i64,
+    /// The name of the tier the rating reached, empty if it reached none or the tier has no name
+    pub tier: String,
+}
+
+/// The reward of a win with the rating. Wins get the tier with the highest rating they
+/// reach, wins below every tier and wins of sponsors without tiers get the sponsor's
+/// `reward_tokens`. A win without a rating reaches no tier.
+pub fn reward_for_rating(sponsor: &Sponsor, tiers: &[RewardTier], rating: Option<i32>) -> Reward {
+    let tier = rating.and_then(|rating| {
+        tiers
+            .iter()
+            .filter(|tier| tier.min_rating <= rating)
+            .max_by_key(|tier| tier.min_rating)
+    });
+
+    match tier {
+        Some(tier) => Reward {
+            tokens: tier.reward_tokens,
+            tier: tier.name.clone(),
+        },
+        None => Reward {
+            tokens: sponsor.reward_tokens,
+            tier: String::new(),
+        },
+    }
+}
+
+/// The highest reward a win of the sponsor can be paid with the tiers, the pool of the
+/// sponsor has to cover it for them to run games.
+pub fn highest_reward(sponsor: &Sponsor, tier_rewards: impl IntoIterator<Item = i64>) -> i64 {
+    tier_rewards
+        .into_iter()
+        .fold(sponsor.reward_tokens, i64::max)
+}
+
+/// Checks the reward tiers a sponsor configured. Every win of an NFT sponsor is worth a
+/// single NFT, so they can not have tiers.
+pub fn validate_tiers(kind: PrizeKind, tiers: &[RewardTierArgs]) -> Result<()> {
+    if kind == PrizeKind::Nft && !tiers.is_empty() {
+        bail!("NFT prizes can not have reward tiers");
+    }
+
+    for (index, tier) in tiers.iter().enumerate() {
+        if !(0..=MAX_RATING).contains(&tier.min_rating) {
+            bail!("The rating of a reward tier has to be between 0 and {MAX_RATING}");
+        }
+
+        if tier.reward_tokens <= 0 {
+            bail!("The reward of a reward tier has to be positive");
+        }
+
+        if kind == PrizeKind::Sol && tier.reward_tokens < MIN_SOL_PRIZE_LAMPORTS as i64 {
+            bail!("The SOL prize of a reward tier is too small to be paid out");
+        }
+
+        if tiers[..index]
+            .iter()
+            .any(|other| other.min_rating == tier.min_rating)
+        {
+            bail!("Two reward tiers have the rating {}", tier.min_rating);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the jackpot a sponsor configured. A jackpot of NFTs can not be won at once, so
+/// NFT sponsors can not run one.
+pub fn validate_jackpot(kind: PrizeKind, jackpot: bool, contribution: i64) -> Result<()> {
+    if !jackpot {
+        return Ok(());
+    }
+
+    if kind == PrizeKind::Nft {
+        bail!("NFT prizes can not have a jackpot");
+    }
+
+    if contribution <= 0 {
+        bail!("The jackpot contribution has to be positive");
+    }
+
+    Ok(())
+}
+
+/// The current jackpot of the sponsor as the host announces it.
+pub fn jackpot_text(sponsor: &Sponsor) -> String {
+    display_tokens(sponsor.jackpot_tokens, sponsor.token_decimals)
+}
+
+/// The amount in whole tokens as shown to callers, e.g. `12.5` for 12500000 of a token
+/// with 6 decimals.
+pub fn display_tokens(amount: i64, decimals: Option<i32>) -> String {
+    let decimals = decimals.unwrap_or_default().clamp(0, 18) as usize;
+    let unit = 10u64.pow(decimals as u32);
+    let amount = amount.unsigned_abs();
+
+    let fraction = format!("{:0decimals$}", amount % unit);
+    match fraction.trim_end_matches('0') {
+        "" => (amount / unit).to_string(),
+        fraction => format!("{}.{fraction}", amount / unit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(min_rating: i32, reward_tokens: i64, name: &str) -> RewardTier {
+        RewardTier {
+            id: min_rating,
+            sponsor_id: 1,
+            min_rating,
+            reward_tokens,
+            name: name.to_owned(),
+        }
+    }
+
+    fn args(min_rating: i32, reward_tokens: i64) -> RewardTierArgs {
+        RewardTierArgs {
+            min_rating,
+            reward_tokens,
+            name: String::new(),
+        }
+    }
+
+    #[test]
+    fn wins_get_the_highest_tier_they_reach() {
+        let sponsor = Sponsor {
+            reward_tokens: 5,
+            ..Default::default()
+        };
+        let tiers = [tier(9, 50, "gold"), tier(7, 10, "silver")];
+
+        assert_eq!(reward_for_rating(&sponsor, &tiers, Some(8)).tokens, 10);
+        assert_eq!(
+            reward_for_rating(&sponsor, &tiers, Some(10)),
+            Reward {
+                tokens: 50,
+                tier: "gold".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn wins_below_every_tier_get_the_flat_prize() {
+        let sponsor = Sponsor {
+            reward_tokens: 5,
+            ..Default::default()
+        };
+        let tiers = [tier(7, 10, "silver")];
+
+        assert_eq!(reward_for_rating(&sponsor, &tiers, Some(6)).tokens, 5);
+        assert_eq!(reward_for_rating(&sponsor, &tiers, None).tokens, 5);
+        assert_eq!(reward_for_rating(&sponsor, &[], Some(10)).tier, "");
+    }
+
+    #[test]
+    fn highest_reward_covers_every_tier() {
+        let sponsor = Sponsor {
+            reward_tokens: 5,
+            ..Default::default()
+        };
+
+        assert_eq!(highest_reward(&sponsor, [10, 50]), 50);
+        assert_eq!(highest_reward(&sponsor, [1]), 5);
+        assert_eq!(highest_reward(&sponsor, []), 5);
+    }
+
+    #[test]
+    fn rejects_invalid_tiers() {
+        assert!(validate_tiers(PrizeKind::Spl, &[args(7, 10), args(9, 50)]).is_ok());
+        assert!(validate_tiers(PrizeKind::Spl, &[args(11, 10)]).is_err());
+        assert!(validate_tiers(PrizeKind::Spl, &[args(7, 0)]).is_err());
+        assert!(validate_tiers(PrizeKind::Spl, &[args(7, 10), args(7, 20)]).is_err());
+        assert!(validate_tiers(PrizeKind::Sol, &[args(7, 10)]).is_err());
+        assert!(validate_tiers(PrizeKind::Nft, &[args(7, 1)]).is_err());
+        assert!(validate_tiers(PrizeKind::Nft, &[]).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_jackpots() {
+        assert!(validate_jackpot(PrizeKind::Spl, true, 100).is_ok());
+        assert!(validate_jackpot(PrizeKind::Spl, true, 0).is_err());
+        assert!(validate_jackpot(PrizeKind::Nft, true, 1).is_err());
+        assert!(validate_jackpot(PrizeKind::Nft, false, 0).is_ok());
+    }
+
+    #[test]
+    fn shows_whole_tokens() {
+        assert_eq!(display_tokens(12_500_000, Some(6)), "12.5");
+        assert_eq!(display_tokens(10_000_000, Some(6)), "10");
+        assert_eq!(display_tokens(1, Some(9)), "0.000000001");
+        assert_eq!(display_tokens(42, None), "42");
+    }
+}
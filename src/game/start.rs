@@ -1,16 +1,17 @@
 use crate::{
-    cache::CachedCall,
+    cache::{CachedCall, CallStore},
     database::{Database, Sponsor},
+    game::error::GameError,
     secrets::Secrets,
+    template::{escape_speech, Template, TemplateContext},
     CONFIG,
 };
+use anyhow::Result;
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestSystemMessage,
 };
 use axum::{extract::Request, response::IntoResponse, Extension};
 use chrono::Utc;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
 use twilio::{
     twiml::{Method, Redirect, Reject, Say, Twiml, Voice},
     Call, Client as TwilioClient,
@@ -18,7 +19,7 @@ use twilio::{
 
 pub async fn start_handler(
     twilio: Extension<TwilioClient>,
-    cache: Extension<Arc<Mutex<HashMap<String, CachedCall>>>>,
+    cache: Extension<CallStore>,
     database: Extension<Database>,
     secrets: Extension<Secrets>,
     request: Request,
@@ -26,89 +27,104 @@ pub async fn start_handler(
     twilio
         .clone()
         .respond_to_webhook_async(request, |call: Call| async move {
-            log::debug!("Received call from {} with id {}", call.from, call.sid);
-
-            // Get or insert the user into the database
-            let mut user = database
-                .get_or_insert_user_by_phone_number(&call.from)
-                .await
-                .expect("Failed to get or insert user");
-
-            log::debug!(
-                "User {} has {} attempts today, last attempt at {}",
-                user.phone_number,
-                user.attempts_today,
-                user.last_attempt
-            );
-
-            // If the user is banned, reject the call
-            if user.banned {
-                log::debug!("Rejecting call from banned user {}", user.phone_number);
-                return generate_reject_twiml();
-            }
-
-            // Reset the daily attempt count if the last attempt was not today
-            if user.last_attempt.date_naive() != Utc::now().date_naive() {
-                user.attempts_today = 1;
+            match start_call(&twilio, &cache, &database, &secrets, &call).await {
+                Ok(twiml) => twiml,
+                Err(e) => e.fallback(&call.sid, &cache, &database, None).await,
             }
+        })
+        .await
+}
 
-            // Update the user in the database
-            database
-                .update_user(&user)
-                .await
-                .expect("Failed to update user");
+/// Checks whether the caller may play, picks a sponsor and starts the call.
+async fn start_call(
+    twilio: &TwilioClient,
+    cache: &CallStore,
+    database: &Database,
+    secrets: &Secrets,
+    call: &Call,
+) -> Result<Twiml, GameError> {
+    log::debug!("Received call from {} with id {}", call.from, call.sid);
 
-            // If the user has exceeded the daily response limit, reject the call
-            if user.attempts_today > CONFIG.settings.daily_response_limit as i32 {
-                log::debug!("Rejecting call from {} without response", call.from);
-                return generate_reject_twiml();
-            }
+    // Get or insert the user into the database
+    let mut user = database
+        .get_or_insert_user_by_phone_number(&call.from)
+        .await
+        .map_err(GameError::Database)?;
 
-            // If the user has exceeded the daily attempt limit, respond with a messsage
-            // notifying the user that they have exceeded the limit
-            if user.attempts_today > CONFIG.settings.daily_attempt_limit as i32 {
-                log::debug!("Rejecting call from {} with response", call.from);
-                return generate_out_of_attempts_twiml();
-            }
+    log::debug!(
+        "User {} has {} attempts today, last attempt at {}",
+        user.phone_number,
+        user.attempts_today,
+        user.last_attempt
+    );
 
-            // Get the sponsor for the call
-            let sponsor = database
-                .get_random_sponsor()
-                .await
-                .expect("Failed to get sponsor");
+    // If the user is banned, reject the call
+    if user.banned {
+        log::debug!("Rejecting call from banned user {}", user.phone_number);
+        return Ok(generate_reject_twiml());
+    }
 
+    // Reset the daily attempt count if the last attempt was not today
+    if user.last_attempt.date_naive() != Utc::now().date_naive() {
+        user.attempts_today = 1;
+    }
 
-            // Create the attempt in the database
-            database
-                .create_attempt_with_sponsor(&user, &sponsor, call.sid.clone())
-                .await
-                .expect("Failed to create attempt");
+    // Update the user in the database
+    database
+        .update_user(&user)
+        .await
+        .map_err(GameError::Database)?;
 
-            
-            let twiml = generate_start_twiml(&sponsor.greeting_text);
+    // If the user has exceeded the daily response limit, reject the call
+    if user.attempts_today > CONFIG.settings.daily_response_limit as i32 {
+        log::debug!("Rejecting call from {} without response", call.from);
+        return Ok(generate_reject_twiml());
+    }
 
-            // let mut twiml = Twiml::new();
+    // If the user has exceeded the daily attempt limit, respond with a messsage
+    // notifying the user that they have exceeded the limit
+    if user.attempts_today > CONFIG.settings.daily_attempt_limit as i32 {
+        log::debug!("Rejecting call from {} with response", call.from);
+        return Ok(generate_out_of_attempts_twiml());
+    }
 
-            // twiml.add(&Say {
-            //     txt: sponsor.start_text.to_owned(),
-            //     voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
-            //     language: CONFIG.settings.language.to_owned(),
-            // });
-        
-            // twiml.add(&Redirect {
-            //     method: Method::Post,
-            //     url: "/challenge/start".to_owned(),
-            // });
+    // Get the sponsor for the call
+    let sponsor = database
+        .get_random_sponsor()
+        .await
+        .map_err(GameError::Database)?;
 
-            // Add the call to the cache
-            initialize_cached_call(&cache, call.sid.clone(), sponsor).await;
+    // Create the attempt in the database
+    database
+        .create_attempt_with_sponsor(&user, &sponsor, call.sid.clone())
+        .await
+        .map_err(GameError::Database)?;
 
-            // Start call recording
-            tokio::spawn(start_call_recording(twilio.0, secrets.0, call.sid.clone()));
+    // The host announces the jackpot as it is when the call starts
+    let greeting =
+        Template::parse(&sponsor.greeting_text).render(&TemplateContext::for_sponsor(&sponsor));
+    let twiml = generate_start_twiml(&greeting);
 
-            twiml
-        })
+    // Add the call to the cache
+    initialize_cached_call(cache, call.sid.clone(), call.from.clone(), sponsor, greeting)
         .await
+        .map_err(GameError::Cache)?;
+
+    // Report every further status change of the call to /call-status
+    tokio::spawn(register_status_callback(
+        twilio.clone(),
+        secrets.clone(),
+        call.sid.clone(),
+    ));
+
+    // Start call recording
+    tokio::spawn(start_call_recording(
+        twilio.clone(),
+        secrets.clone(),
+        call.sid.clone(),
+    ));
+
+    Ok(twiml)
 }
 
 /// Generate the TwiML for the start of the call.
@@ -118,7 +134,7 @@ fn generate_start_twiml(greeting: &str) -> Twiml {
     let mut twiml = Twiml::new();
 
     twiml.add(&Say {
-        txt: greeting.to_owned(),
+        txt: escape_speech(greeting),
         voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
         language: CONFIG.settings.language.to_owned(),
     });
@@ -144,11 +160,13 @@ fn generate_reject_twiml() -> Twiml {
 fn generate_out_of_attempts_twiml() -> Twiml {
     let mut twiml = Twiml::new();
 
+    let context = TemplateContext {
+        attempts: Some(CONFIG.settings.daily_attempt_limit as i64),
+        ..Default::default()
+    };
+
     twiml.add(&Say {
-        txt: CONFIG.texts.out_of_attempts.replace(
-            "$attempts",
-            &CONFIG.settings.daily_attempt_limit.to_string(),
-        ),
+        txt: Template::parse_config(CONFIG.texts.out_of_attempts).render_speech(&context),
         voice: Voice::Custom(CONFIG.settings.voice.to_owned()),
         language: CONFIG.settings.language.to_owned(),
     });
@@ -158,32 +176,40 @@ fn generate_out_of_attempts_twiml() -> Twiml {
 
 /// Initialize the conversation cache with two messages:
 /// - The system message with the sponsor's system instruction
-/// - The assistant message with the sponsor's greeting text
+/// - The assistant message with the greeting the caller heard
 ///
 /// The system message is not audible and ignored by the subtitle
 /// generation, it only serves to instruct the model on how to respond.
 async fn initialize_cached_call(
-    cache: &Arc<Mutex<HashMap<String, CachedCall>>>,
+    cache: &CallStore,
     call_sid: String,
+    phone_number: String,
     sponsor: Sponsor,
-) {
-    let mut cached_call = CachedCall::new(sponsor.clone());
+    greeting: String,
+) -> Result<()> {
+    let mut cached_call = CachedCall::new(sponsor.clone(), phone_number);
     cached_call.add_system_message(
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content(sponsor.system_instruction)
-            .build()
-            .expect("Failed to build system message")
-            .into(),
+        ChatCompletionRequestSystemMessage::from(sponsor.system_instruction).into(),
     );
     cached_call.add_system_message(
-        ChatCompletionRequestAssistantMessageArgs::default()
-            .content(sponsor.greeting_text)
-            .build()
-            .expect("Failed to build system message")
-            .into(),
+        ChatCompletionRequestAssistantMessage::from(greeting).into(),
     );
 
-    cache.lock().await.insert(call_sid, cached_call);
+    cache.insert(&call_sid, cached_call).await
+}
+
+/// Register the status callback of the call, so the app is notified when the call ends.
+/// Status changes before the call was answered are only reported when the status
+/// callback is also configured on the twilio phone number itself.
+async fn register_status_callback(twilio: TwilioClient, secrets: Secrets, call_sid: String) {
+    let callback = format!("{}/call-status", secrets.global_url);
+
+    if let Err(e) = twilio
+        .update_call_status_callback(&call_sid, &callback)
+        .await
+    {
+        log::error!("Failed to register status callback for call {call_sid}: {e:?}");
+    }
 }
 
 /// Start the call recording. The recording may fail to start if the call status
@@ -206,3 +232,29 @@ async fn start_call_recording(twilio: TwilioClient, secrets: Secrets, call_sid:
         CONFIG.settings.record_retry
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::test_call;
+
+    #[tokio::test]
+    async fn database_failure_hangs_up() {
+        let twilio = TwilioClient::new("AC00000000000000000000000000000000", "token");
+        let cache = CallStore::memory();
+        let database = Database::unreachable();
+        let call = test_call(None);
+
+        let error = start_call(&twilio, &cache, &database, &Secrets::default(), &call)
+            .await
+            .expect_err("Starting a call without a database must fail");
+        assert!(matches!(error, GameError::Database(_)));
+
+        let twiml = error
+            .fallback(&call.sid, &cache, &database, None)
+            .await
+            .as_twiml();
+        assert!(twiml.contains("<Hangup"));
+        assert!(cache.get(&call.sid).await.unwrap().is_none());
+    }
+}
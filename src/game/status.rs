@@ -0,0 +1,78 @@
+use crate::{
+    cache::CallStore, database::Database, game::reaper::abandon_call, llm::LlmProviders,
+    secrets::Secrets,
+};
+use axum::{extract::Request, response::IntoResponse, Extension};
+use reqwest::Client as ReqwestClient;
+use twilio::{twiml::Twiml, Call, CallStatus, Client as TwilioClient};
+
+/// The end reason of calls that reached the /judge route, it is recorded by the route.
+pub const FINISHED_END_REASON: &str = "finished";
+
+/// The end reason of calls that were hung up before reaching the /judge route.
+pub const HANGUP_END_REASON: &str = "hangup";
+
+/// Receives every status change of a call from twilio. The status, duration and
+/// reason the call ended are stored on the attempt, and calls that end before
+/// being judged are cleaned up right away.
+pub async fn call_status_handler(
+    twilio: Extension<TwilioClient>,
+    reqwest: Extension<ReqwestClient>,
+    llm: Extension<LlmProviders>,
+    cache: Extension<CallStore>,
+    database: Extension<Database>,
+    secrets: Extension<Secrets>,
+    request: Request,
+) -> impl IntoResponse {
+    twilio
+        .clone()
+        .respond_to_webhook_async(request, |call: Call| async move {
+            log::debug!(
+                "Call {} changed status to {}",
+                call.sid,
+                call.status.as_str()
+            );
+
+            let end_reason = match call.status {
+                // Calls that are still cached never reached the /judge route. Calls that are not
+                // cached either reached it, which recorded them as finished, or were never played
+                CallStatus::Completed => match cache.get(&call.sid).await {
+                    Ok(Some(_)) => Some(HANGUP_END_REASON),
+                    Ok(None) => Some(call.status.as_str()),
+                    Err(e) => {
+                        log::error!("Failed to load call {}: {e:?}", call.sid);
+                        None
+                    }
+                },
+                status if status.is_final() => Some(status.as_str()),
+                _ => None,
+            };
+
+            if let Err(e) = database
+                .record_call_status(
+                    &call.sid,
+                    call.status.as_str(),
+                    call.call_duration.map(|d| d as i32),
+                    call.sip_response_code.map(|c| c as i32),
+                    end_reason,
+                )
+                .await
+            {
+                log::error!("Failed to record status of call {}: {e:?}", call.sid);
+            }
+
+            // Clean up the call right away instead of waiting for the stale call reaper
+            if call.status.is_final() {
+                if let Err(e) = abandon_call(
+                    twilio.0, reqwest.0, llm.0, database.0, secrets.0, &cache, &call.sid,
+                )
+                .await
+                {
+                    log::error!("Failed to clean up call {}: {e:?}", call.sid);
+                }
+            }
+
+            Twiml::new()
+        })
+        .await
+}
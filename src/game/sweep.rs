@@ -0,0 +1,219 @@
+use crate::{
+    api::Attempt,
+    claim::claim_window,
+    database::{Database, Sponsor},
+    secrets::Secrets,
+    solana::{
+        prize::Prize,
+        service::SolanaService,
+        transfer::{send_prize_transfer, sign_prize_sweep, transfer_status, TransferStatus},
+    },
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use solana_sdk::signature::Keypair;
+use std::time::Duration;
+
+/// How often unclaimed prizes are swept back to the sponsors.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically returns the prizes that were sent to generated keypairs before the claim
+/// tokens and were not claimed within the claim window. The tokens are returned to the
+/// sponsor's pool once the sweep landed, which is checked on the next run. Runs until the
+/// app shuts down.
+pub async fn run_prize_sweep(database: Database, secrets: Secrets, solana: SolanaService) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let won_before = Utc::now() - claim_window(&secrets);
+        let attempts = match database.get_unswept_legacy_prizes(won_before).await {
+            Ok(attempts) => attempts,
+            Err(e) => {
+                log::error!("Failed to get unswept prizes: {e:?}");
+                continue;
+            }
+        };
+
+        for attempt in attempts {
+            if let Err(e) = sweep_prize(&database, &solana, &attempt).await {
+                log::error!("Failed to sweep prize of call {}: {e:?}", attempt.call_sid);
+            }
+        }
+    }
+}
+
+async fn sweep_prize(database: &Database, solana: &SolanaService, attempt: &Attempt) -> Result<()> {
+    let sponsor = attempt_sponsor(database, attempt).await?;
+    let sponsor_id = sponsor.id;
+
+    // A recorded sweep is only signed again once it can not land anymore
+    if let (Some(signature), Some(last_valid_block_height), Some(tokens)) = (
+        &attempt.prize_sweep_signature,
+        attempt.prize_sweep_last_valid_block_height,
+        attempt.prize_swept_tokens,
+    ) {
+        match transfer_status(solana, signature, last_valid_block_height as u64).await? {
+            TransferStatus::Confirmed => {
+                return credit_sweep(database, attempt, sponsor_id, tokens).await;
+            }
+            TransferStatus::Pending => return Ok(()),
+            TransferStatus::Failed(error) => {
+                log::warn!("Sweep of call {} failed: {error}", attempt.call_sid)
+            }
+            TransferStatus::Expired => {
+                log::warn!("Sweep of call {} expired", attempt.call_sid)
+            }
+        }
+    }
+
+    let prize_keypair = prize_keypair(&attempt.winner_url)?;
+    let prize = Prize::of_sponsor(solana, &sponsor).await?;
+
+    let Some(sweep) =
+        sign_prize_sweep(solana, &sponsor.private_key, &prize_keypair, &prize).await?
+    else {
+        // Prizes that hold nothing were never sent or already swept, they are
+        // recorded anyway so they are not checked again
+        return credit_sweep(database, attempt, sponsor_id, 0).await;
+    };
+
+    let tokens = sweep.amount.try_into().context("Converting swept tokens")?;
+    let signature = sweep.transfer.signature.to_string();
+
+    let recorded = database
+        .record_prize_sweep_signature(
+            &attempt.call_sid,
+            attempt.prize_sweep_signature.as_deref(),
+            &signature,
+            tokens,
+            sweep.transfer.last_valid_block_height as i64,
+        )
+        .await
+        .context("Recording prize sweep signature")?;
+
+    if !recorded {
+        bail!("Another sweep of the prize was recorded while signing");
+    }
+
+    // The sweep may have been sent even if this fails, its status is checked next time
+    send_prize_transfer(solana, &sweep.transfer).await?;
+    log::debug!(
+        "Sent sweep of call {} with signature {signature}",
+        attempt.call_sid
+    );
+
+    Ok(())
+}
+
+/// Returns the swept tokens to the sponsor's pool.
+async fn credit_sweep(
+    database: &Database,
+    attempt: &Attempt,
+    sponsor_id: i32,
+    tokens: i64,
+) -> Result<()> {
+    database
+        .record_prize_sweep(&attempt.call_sid, sponsor_id, tokens)
+        .await
+        .context("Recording prize sweep")?;
+
+    log::info!(
+        "Swept {tokens} unclaimed tokens of call {} back to sponsor {sponsor_id}",
+        attempt.call_sid
+    );
+
+    Ok(())
+}
+
+/// How the sponsor of an attempt is found.
+#[derive(Debug, PartialEq)]
+enum SponsorLookup<'a> {
+    Id(i32),
+    /// Attempts from before the sponsor id was recorded on them only know the token mint and
+    /// the name of their sponsor
+    TokenMintAndName(&'a str, &'a str),
+}
+
+fn sponsor_lookup(attempt: &Attempt) -> Result<SponsorLookup<'_>> {
+    if let Some(id) = attempt.sponsor_id {
+        return Ok(SponsorLookup::Id(id));
+    }
+
+    match (&attempt.sponsor_token_mint, &attempt.sponsor_name) {
+        (Some(token_mint), Some(name)) => Ok(SponsorLookup::TokenMintAndName(token_mint, name)),
+        _ => bail!("Attempt has no sponsor"),
+    }
+}
+
+async fn attempt_sponsor(database: &Database, attempt: &Attempt) -> Result<Sponsor> {
+    match sponsor_lookup(attempt)? {
+        SponsorLookup::Id(id) => database
+            .get_sponsor_by_id(id)
+            .await
+            .context("Getting sponsor of attempt"),
+        SponsorLookup::TokenMintAndName(token_mint, name) => database
+            .get_sponsor_by_token_mint_and_name(token_mint, name)
+            .await
+            .context("Getting sponsor of attempt")?
+            .with_context(|| format!("No single sponsor {name} with the token mint {token_mint}")),
+    }
+}
+
+/// Recovers the generated keypair from the base58 key in the winner url.
+fn prize_keypair(winner_url: &str) -> Result<Keypair> {
+    let (_, key) = winner_url
+        .split_once("key=")
+        .context("Winner url has no key")?;
+    let bytes = bs58::decode(key).into_vec().context("Decoding prize key")?;
+
+    Keypair::from_bytes(&bytes).context("Parsing prize keypair")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    #[test]
+    fn recovers_keypair_from_winner_url() {
+        let keypair = Keypair::new();
+        let winner_url = format!("https://claim.why.fun/?key={}", keypair.to_base58_string());
+
+        assert_eq!(
+            prize_keypair(&winner_url).unwrap().pubkey(),
+            keypair.pubkey()
+        );
+    }
+
+    #[test]
+    fn attempts_without_sponsor_id_are_matched_by_token_mint_and_name() {
+        let attempt = Attempt {
+            sponsor_name: Some("Test Sponsor".to_owned()),
+            sponsor_token_mint: Some("TokenMint111".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sponsor_lookup(&attempt).unwrap(),
+            SponsorLookup::TokenMintAndName("TokenMint111", "Test Sponsor")
+        );
+        assert_eq!(
+            sponsor_lookup(&Attempt {
+                sponsor_id: Some(7),
+                ..attempt
+            })
+            .unwrap(),
+            SponsorLookup::Id(7)
+        );
+        assert!(sponsor_lookup(&Attempt::default()).is_err());
+    }
+
+    #[test]
+    fn claim_token_is_not_a_keypair() {
+        let winner_url = "https://claim.why.fun/?key=4f5b8a7e-3c1d-4e2f-9a6b-0c8d7e6f5a4b";
+
+        assert!(prize_keypair(winner_url).is_err());
+    }
+}
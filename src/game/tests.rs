@@ -0,0 +1,114 @@
+//! Shared fixtures for the tests of the game webhooks.
+
+use crate::{
+    cache::{CachedCall, CallStore},
+    database::Sponsor,
+    game::{challenge, end, gather, judge, name},
+    llm::{LlmProviders, LlmTask, ScriptedProvider},
+    secrets::Secrets,
+};
+use twilio::{Call, CallStatus, Client as TwilioClient};
+
+pub const TEST_CALL_SID: &str = "CA00000000000000000000000000000000";
+pub const TEST_PHONE_NUMBER: &str = "+15005550006";
+
+/// An in-progress call as twilio sends it to the webhooks.
+pub fn test_call(speech_result: Option<&str>) -> Call {
+    Call {
+        from: TEST_PHONE_NUMBER.to_owned(),
+        to: "+15005550001".to_owned(),
+        sid: TEST_CALL_SID.to_owned(),
+        status: CallStatus::InProgress,
+        speech_confidence: speech_result.map(|_| 0.9),
+        speech_result: speech_result.map(str::to_owned),
+        call_duration: None,
+        sip_response_code: None,
+    }
+}
+
+/// A call that was started with a minimal sponsor.
+pub fn test_cached_call() -> CachedCall {
+    let sponsor = Sponsor {
+        id: 1,
+        name: "Test Sponsor".to_owned(),
+        challenge_time: 30,
+        start_text: "Hi {name}, you have {duration} seconds.".to_owned(),
+        end_text: "Time is up!".to_owned(),
+        won_text: "You won, {name}!".to_owned(),
+        lost_text: "You lost, {name}.".to_owned(),
+        ..Default::default()
+    };
+
+    CachedCall::new(sponsor, TEST_PHONE_NUMBER.to_owned())
+}
+
+/// Uses the scripted provider for every sponsor.
+pub fn scripted_llm(provider: ScriptedProvider) -> LlmProviders {
+    LlmProviders::new(provider)
+}
+
+/// Plays a whole call from the name question to the judgement without network access.
+#[tokio::test]
+async fn call_flow_with_scripted_llm() {
+    let twilio = TwilioClient::new("AC00000000000000000000000000000000", "token");
+    let cache = CallStore::memory();
+    let llm = scripted_llm(
+        ScriptedProvider::new()
+            .respond(LlmTask::Name, r#"{"name": "Alice"}"#)
+            .respond(LlmTask::Challenge, "A dollar? You can do better than that.")
+            .respond(
+                LlmTask::Judge,
+                r#"{"won_prize": true, "rating": 9, "explanation": "Convincing pitch"}"#,
+            ),
+    );
+    cache
+        .insert(TEST_CALL_SID, test_cached_call())
+        .await
+        .unwrap();
+
+    let twiml = gather::gather(&cache, &test_call(None), "name")
+        .await
+        .unwrap()
+        .as_twiml();
+    assert!(twiml.contains("<Gather"));
+
+    let twiml = name::handle_name(&llm, &cache, &test_call(Some("My name is Alice")))
+        .await
+        .unwrap()
+        .as_twiml();
+    assert!(twiml.contains("Hi Alice, you have 30 seconds."));
+    assert!(twiml.contains("/challenge/start</Redirect>"));
+
+    let twiml = challenge::start_challenge(&twilio, &cache, &Secrets::default(), &test_call(None))
+        .await
+        .unwrap()
+        .as_twiml();
+    assert!(twiml.contains("/challenge/respond"));
+
+    let twiml = challenge::respond(&llm, &cache, &test_call(Some("I'll sell it for a dollar")))
+        .await
+        .unwrap()
+        .as_twiml();
+    assert!(twiml.contains("A dollar? You can do better than that."));
+
+    let twiml = end::end_challenge(&cache, &test_call(None))
+        .await
+        .unwrap()
+        .as_twiml();
+    assert!(twiml.contains("Time is up!"));
+    assert!(twiml.contains("/judge</Redirect>"));
+
+    let cached_call = judge::remove_cached_call(&cache, &test_call(None))
+        .await
+        .unwrap();
+    assert_eq!(cached_call.name, "Alice");
+    assert_eq!(cached_call.messages.len(), 5);
+    assert!(cache.get(TEST_CALL_SID).await.unwrap().is_none());
+
+    let provider = llm.get(&cached_call.sponsor.llm_provider);
+    let judged = judge::judge_transcript(provider.as_ref(), &cached_call.messages, None)
+        .await
+        .unwrap();
+    assert!(judged.won_prize);
+    assert_eq!(judged.rating, 9);
+}
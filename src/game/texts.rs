@@ -0,0 +1,202 @@
+use crate::{
+    database::{RewardTier, Sponsor},
+    game::reward::{display_tokens, reward_for_rating, Reward, MAX_RATING},
+    template::{Template, TemplateContext, TemplateError, Variable},
+};
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+pub const DEFAULT_GREETING_TEXT: &str =
+    "Welcome to Why dot Fun. Please tell me your name to start the game.";
+
+pub const DEFAULT_END_TEXT: &str = "Alright, your time is up! Thank you for participating. You will receive a text message with the results of your attempt. If you are calling from the United States, visit claim.why.fun to check your result. Callers from the US will not receive a text message, please check your result on claim.why.fun. Thank you for playing today!";
+
+pub const DEFAULT_WON_TEXT: &str = "Congratulations {name}, you won! Claim your prize: {link}. View the video of your attempt here: {video_url} (it will be ready in around 15 minutes)";
+
+pub const DEFAULT_LOST_TEXT: &str = "Unfortunately, you did not win this time. Better luck next time! Check out https://x.com/whydotfun for tips and tricks to improve your chances.";
+
+/// The texts a sponsor can customise. Each is rendered at a different point of the call, so
+/// each can use different variables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SponsorText {
+    /// Spoken when the call is answered, before the caller said their name
+    Greeting,
+    /// Spoken once the caller said their name
+    Start,
+    /// Spoken when the time of the challenge is up
+    End,
+    /// Sent to winners
+    Won,
+    /// Sent to everyone else
+    Lost,
+}
+
+impl SponsorText {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SponsorText::Greeting => "greeting text",
+            SponsorText::Start => "start text",
+            SponsorText::End => "end text",
+            SponsorText::Won => "won text",
+            SponsorText::Lost => "lost text",
+        }
+    }
+
+    /// The variables that have a value where the text is used.
+    pub fn variables(&self) -> &'static [Variable] {
+        match self {
+            SponsorText::Greeting => &[Variable::Sponsor, Variable::Duration, Variable::Jackpot],
+            SponsorText::Start => &[
+                Variable::Name,
+                Variable::Sponsor,
+                Variable::Duration,
+                Variable::Jackpot,
+            ],
+            SponsorText::End | SponsorText::Lost => {
+                &[Variable::Name, Variable::Sponsor, Variable::Duration]
+            }
+            SponsorText::Won => &[
+                Variable::Name,
+                Variable::Sponsor,
+                Variable::Duration,
+                Variable::Link,
+                Variable::VideoUrl,
+                Variable::Tier,
+                Variable::Reward,
+                Variable::Jackpot,
+            ],
+        }
+    }
+}
+
+/// The rendered texts of a sponsor with example values, so sponsors can see what callers get.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextPreviews {
+    pub greeting_text: String,
+    pub start_text: String,
+    pub end_text: String,
+    pub won_text: String,
+    pub lost_text: String,
+}
+
+/// Checks that the text only uses the variables of its kind.
+pub fn validate_text(kind: SponsorText, text: &str) -> Result<()> {
+    if text.trim().is_empty() {
+        bail!("The {} can not be empty", kind.as_str());
+    }
+
+    let allowed = kind
+        .variables()
+        .iter()
+        .map(|variable| format!("{{{}}}", variable.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match Template::parse(text).check(kind.variables()) {
+        Ok(()) => Ok(()),
+        Err(TemplateError::Unknown(placeholder)) => bail!(
+            "The {} uses the unknown placeholder {placeholder}, it can use {allowed}",
+            kind.as_str()
+        ),
+        Err(TemplateError::Unavailable(variable)) => bail!(
+            "The {} can not use the placeholder {{{}}}, it can use {allowed}",
+            kind.as_str(),
+            variable.as_str()
+        ),
+    }
+}
+
+/// The values of the won text. The amount is everything the winner is paid, the prize of
+/// their tier and the jackpot.
+pub fn won_context(
+    sponsor: &Sponsor,
+    name: &str,
+    reward: &Reward,
+    amount: i64,
+    link: &str,
+    video_url: &str,
+) -> TemplateContext {
+    TemplateContext {
+        name: Some(name.to_owned()),
+        link: Some(link.to_owned()),
+        video_url: Some(video_url.to_owned()),
+        tier: Some(reward.tier.clone()),
+        reward: Some(display_tokens(amount, sponsor.token_decimals)),
+        jackpot: Some(display_tokens(
+            amount - reward.tokens,
+            sponsor.token_decimals,
+        )),
+        ..TemplateContext::for_sponsor(sponsor)
+    }
+}
+
+/// The texts of the sponsor as a caller named Alice gets them for a perfect win.
+pub fn preview_texts(sponsor: &Sponsor, tiers: &[RewardTier]) -> TextPreviews {
+    let reward = reward_for_rating(sponsor, tiers, Some(MAX_RATING));
+    let context = won_context(
+        sponsor,
+        "Alice",
+        &reward,
+        reward.tokens + sponsor.jackpot_tokens,
+        "https://claim.why.fun/?key=example",
+        "https://why.fun/video/example",
+    );
+    let call_context = TemplateContext {
+        name: Some("Alice".to_owned()),
+        ..TemplateContext::for_sponsor(sponsor)
+    };
+
+    TextPreviews {
+        greeting_text: Template::parse(&sponsor.greeting_text).render(&call_context),
+        start_text: Template::parse(&sponsor.start_text).render(&call_context),
+        end_text: Template::parse(&sponsor.end_text).render(&call_context),
+        won_text: Template::parse(&sponsor.won_text).render(&context),
+        lost_text: Template::parse(&sponsor.lost_text).render(&call_context),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_texts_are_valid() {
+        assert!(validate_text(SponsorText::Greeting, DEFAULT_GREETING_TEXT).is_ok());
+        assert!(validate_text(SponsorText::End, DEFAULT_END_TEXT).is_ok());
+        assert!(validate_text(SponsorText::Won, DEFAULT_WON_TEXT).is_ok());
+        assert!(validate_text(SponsorText::Lost, DEFAULT_LOST_TEXT).is_ok());
+    }
+
+    #[test]
+    fn rejects_placeholders_the_text_can_not_use() {
+        let error = validate_text(SponsorText::Greeting, "Hi {name}!").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The greeting text can not use the placeholder {name}, it can use {sponsor}, {duration}, {jackpot}"
+        );
+
+        assert!(validate_text(SponsorText::Lost, "Sorry {name}, see {video_url}").is_err());
+        assert!(validate_text(SponsorText::Won, "You won {reward}, {nmae}").is_err());
+        assert!(validate_text(SponsorText::End, " ").is_err());
+    }
+
+    #[test]
+    fn previews_a_perfect_win() {
+        let sponsor = Sponsor {
+            challenge_time: 30,
+            reward_tokens: 5,
+            jackpot_tokens: 20,
+            greeting_text: "The jackpot is {jackpot}.".to_owned(),
+            won_text: "{name} won {reward} in {duration} seconds: {link}".to_owned(),
+            ..Default::default()
+        };
+
+        let previews = preview_texts(&sponsor, &[]);
+
+        assert_eq!(previews.greeting_text, "The jackpot is 20.");
+        assert_eq!(
+            previews.won_text,
+            "Alice won 25 in 30 seconds: https://claim.why.fun/?key=example"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use super::{openai::create_completion, LlmProvider, LlmRequest};
+use anyhow::Result;
+use async_openai::{config::OpenAIConfig, Client as OpenAIClient};
+use async_trait::async_trait;
+
+/// Generates completions with a self-hosted server implementing the OpenAI API,
+/// for example vLLM or Ollama.
+pub struct CompatibleProvider {
+    client: OpenAIClient<OpenAIConfig>,
+    /// Replaces the models from the config, which the server usually does not know.
+    model: Option<String>,
+}
+
+impl CompatibleProvider {
+    pub fn new(api_base: &str, api_key: &str, model: Option<String>) -> Self {
+        let config = OpenAIConfig::new()
+            .with_api_base(api_base)
+            .with_api_key(api_key);
+
+        Self {
+            client: OpenAIClient::with_config(config),
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CompatibleProvider {
+    async fn complete(&self, request: LlmRequest) -> Result<String> {
+        let model = match &self.model {
+            Some(model) => model.clone(),
+            None => request.task.model().to_owned(),
+        };
+
+        create_completion(&self.client, &model, request).await
+    }
+}
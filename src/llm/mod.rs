@@ -0,0 +1,130 @@
+use crate::{secrets::Secrets, CONFIG};
+use anyhow::Result;
+use async_openai::{
+    types::{ChatCompletionRequestMessage, ResponseFormat},
+    Client as OpenAIClient,
+};
+use async_trait::async_trait;
+use std::{collections::HashMap, sync::Arc};
+
+pub mod compatible;
+pub mod openai;
+#[cfg(test)]
+pub mod scripted;
+
+pub use compatible::CompatibleProvider;
+pub use openai::OpenAiProvider;
+#[cfg(test)]
+pub use scripted::ScriptedProvider;
+
+/// The name of the provider used by sponsors that do not pick one.
+pub const DEFAULT_PROVIDER: &str = "openai";
+
+/// The name of the provider talking to a self-hosted OpenAI-compatible server.
+pub const COMPATIBLE_PROVIDER: &str = "compatible";
+
+/// What a completion is used for, every task has its own model in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LlmTask {
+    /// Extracting the name of the caller from their transcription.
+    Name,
+    /// Responding to the caller during the challenge.
+    Challenge,
+    /// Judging the transcript of the call.
+    Judge,
+    /// Checking the transcript for attempts to manipulate the judge.
+    Guard,
+}
+
+impl LlmTask {
+    /// The configured model of the task.
+    pub fn model(&self) -> &'static str {
+        match self {
+            LlmTask::Name => CONFIG.name.model,
+            LlmTask::Challenge => CONFIG.challenge.model,
+            LlmTask::Judge | LlmTask::Guard => CONFIG.end.model,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmRequest {
+    pub task: LlmTask,
+    pub messages: Vec<ChatCompletionRequestMessage>,
+    pub max_tokens: u32,
+    /// Overrides the model's default temperature.
+    pub temperature: Option<f32>,
+    /// Forces the completion to match a json schema, used for name extraction and judging.
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// A backend that generates the completions of the game.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Generates the content of the next assistant message.
+    async fn complete(&self, request: LlmRequest) -> Result<String>;
+}
+
+/// All configured providers by name, sponsors pick one with their `llm_provider`.
+#[derive(Clone)]
+pub struct LlmProviders {
+    default: Arc<dyn LlmProvider>,
+    providers: HashMap<String, Arc<dyn LlmProvider>>,
+}
+
+impl LlmProviders {
+    pub fn new(default: impl LlmProvider + 'static) -> Self {
+        let default: Arc<dyn LlmProvider> = Arc::new(default);
+
+        Self {
+            providers: HashMap::from([(DEFAULT_PROVIDER.to_owned(), default.clone())]),
+            default,
+        }
+    }
+
+    /// Creates the OpenAI provider and, if `LLM_COMPATIBLE_URL` is set,
+    /// the provider for the self-hosted OpenAI-compatible server.
+    pub fn from_secrets(secrets: &Secrets) -> Self {
+        let mut providers = Self::new(OpenAiProvider::new(OpenAIClient::new()));
+
+        if let Some(api_base) = &secrets.llm_compatible_url {
+            providers = providers.with(
+                COMPATIBLE_PROVIDER,
+                CompatibleProvider::new(
+                    api_base,
+                    secrets
+                        .llm_compatible_api_key
+                        .as_deref()
+                        .unwrap_or_default(),
+                    secrets.llm_compatible_model.clone(),
+                ),
+            );
+        }
+
+        providers
+    }
+
+    pub fn with(mut self, name: &str, provider: impl LlmProvider + 'static) -> Self {
+        self.providers.insert(name.to_owned(), Arc::new(provider));
+        self
+    }
+
+    /// Whether a provider with the given name is configured.
+    pub fn contains(&self, name: &str) -> bool {
+        self.providers.contains_key(name)
+    }
+
+    /// Gets the provider with the given name, falling back on the default provider.
+    pub fn get(&self, name: &str) -> Arc<dyn LlmProvider> {
+        match self.providers.get(name) {
+            Some(provider) => provider.clone(),
+            None => {
+                if !name.is_empty() {
+                    log::warn!("Unknown LLM provider {name}, using {DEFAULT_PROVIDER}");
+                }
+
+                self.default.clone()
+            }
+        }
+    }
+}
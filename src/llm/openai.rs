@@ -0,0 +1,54 @@
+use super::{LlmProvider, LlmRequest};
+use anyhow::{anyhow, Result};
+use async_openai::{
+    config::OpenAIConfig, types::CreateChatCompletionRequestArgs, Client as OpenAIClient,
+};
+use async_trait::async_trait;
+
+/// Generates completions with the OpenAI API and the models from the config.
+pub struct OpenAiProvider {
+    client: OpenAIClient<OpenAIConfig>,
+}
+
+impl OpenAiProvider {
+    pub fn new(client: OpenAIClient<OpenAIConfig>) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    async fn complete(&self, request: LlmRequest) -> Result<String> {
+        let model = request.task.model();
+        create_completion(&self.client, model, request).await
+    }
+}
+
+/// Creates a chat completion with any client speaking the OpenAI API.
+pub(super) async fn create_completion(
+    client: &OpenAIClient<OpenAIConfig>,
+    model: &str,
+    request: LlmRequest,
+) -> Result<String> {
+    let mut args = CreateChatCompletionRequestArgs::default();
+    args.max_tokens(request.max_tokens)
+        .model(model)
+        .messages(request.messages);
+
+    if let Some(temperature) = request.temperature {
+        args.temperature(temperature);
+    }
+
+    if let Some(response_format) = request.response_format {
+        args.response_format(response_format);
+    }
+
+    let response = client.chat().create(args.build()?).await?;
+
+    response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .ok_or_else(|| anyhow!("No content in the completion choice"))
+}
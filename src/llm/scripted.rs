@@ -0,0 +1,43 @@
+use super::{LlmProvider, LlmRequest, LlmTask};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+/// Answers every task with scripted responses in order, so the call flow
+/// can be tested without network access. Fails once a task runs out of responses.
+#[derive(Default)]
+pub struct ScriptedProvider {
+    responses: Mutex<HashMap<LlmTask, VecDeque<String>>>,
+}
+
+impl ScriptedProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next response of the task.
+    pub fn respond(self, task: LlmTask, response: impl Into<String>) -> Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(task)
+            .or_default()
+            .push_back(response.into());
+        self
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ScriptedProvider {
+    async fn complete(&self, request: LlmRequest) -> Result<String> {
+        self.responses
+            .lock()
+            .unwrap()
+            .get_mut(&request.task)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| anyhow!("No scripted {:?} response left", request.task))
+    }
+}
@@ -1,18 +1,18 @@
-use async_openai::Client as OpenaiClient;
 use axum::response::IntoResponse;
 use axum::{
     routing::{get, post},
     Extension, Router,
 };
-use cache::CachedCall;
+use cache::CallStore;
 use database::Database;
+use llm::LlmProviders;
 use reqwest::header::HeaderValue;
 use reqwest::Client as ReqwestClient;
 use reqwest::StatusCode;
 use secrets::Secrets;
+use solana::service::SolanaService;
 use static_toml::static_toml;
-use std::{collections::HashMap, sync::Arc};
-use tokio::{net::TcpListener, sync::Mutex};
+use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use twilio::Client as TwilioClient;
@@ -26,9 +26,11 @@ mod cache;
 mod claim;
 mod database;
 mod game;
+mod llm;
 mod review;
 mod secrets;
 mod solana;
+mod template;
 mod video;
 mod webcall;
 
@@ -45,13 +47,33 @@ async fn main() {
     log::info!("Connecting to the database");
     let database = Database::new(&secrets).await;
 
+    // Rotate the sponsor keys to the current master key and exit when asked to
+    if std::env::args().nth(1).as_deref() == Some("rotate-sponsor-keys") {
+        solana::sponsor_key::rotate_sponsor_keys(&database, &secrets)
+            .await
+            .expect("Failed to rotate sponsor keys");
+        return;
+    }
+
+    // Encrypt the sponsor keys that were stored before they were encrypted and exit when asked to
+    if std::env::args().nth(1).as_deref() == Some("seal-sponsor-keys") {
+        solana::sponsor_key::seal_plaintext_sponsor_keys(&database, &secrets)
+            .await
+            .expect("Failed to seal sponsor keys");
+        return;
+    }
+
+    // Initialize the Solana client
+    log::info!("Initializing the Solana client");
+    let solana = SolanaService::from_secrets(&secrets).expect("Failed to initialize the Solana client");
+
     // Initialize the twilio client
     log::info!("Initializing the Twilio client");
     let twilio = TwilioClient::new(&secrets.twilio_account_sid, &secrets.twilio_auth_token);
 
-    // Initialize the OpenAI client
-    log::info!("Initializing the OpenAI client");
-    let openai = OpenaiClient::new();
+    // Initialize the LLM providers
+    log::info!("Initializing the LLM providers");
+    let llm = LlmProviders::from_secrets(&secrets);
 
     // Initialize the twitter client
     log::info!("Initializing the Twitter client");
@@ -68,8 +90,52 @@ async fn main() {
     let reqwest = ReqwestClient::new();
 
     // Initialize the conversation cache, maps the call id to all messages
-    log::info!("Initializing the conversation cache");
-    let cache = Arc::new(Mutex::new(HashMap::<String, CachedCall>::new()));
+    log::info!("Initializing the {} conversation cache", secrets.call_store);
+    let cache = match secrets.call_store.as_str() {
+        "memory" => CallStore::memory(),
+        _ => CallStore::postgres(database.clone()),
+    };
+
+    // Start the reaper that cleans up calls that hung up before being judged
+    log::info!("Starting the stale call reaper");
+    tokio::spawn(game::reaper::run_reaper(
+        twilio.clone(),
+        reqwest.clone(),
+        llm.clone(),
+        database.clone(),
+        secrets.clone(),
+        cache.clone(),
+    ));
+
+    // Start the worker that confirms prize transfers and retries failed ones
+    log::info!("Starting the payout worker");
+    tokio::spawn(game::payout::run_payout_worker(
+        database.clone(),
+        solana.clone(),
+    ));
+
+    // Start returning unclaimed prizes of generated keypairs to the sponsors
+    log::info!("Starting the unclaimed prize sweep");
+    tokio::spawn(game::sweep::run_prize_sweep(
+        database.clone(),
+        secrets.clone(),
+        solana.clone(),
+    ));
+
+    // Start rejecting held back wins that were not approved in time
+    log::info!("Starting the payout approval expiry");
+    tokio::spawn(game::approval::run_payout_expiry(
+        twilio.clone(),
+        database.clone(),
+        secrets.clone(),
+    ));
+
+    // Start comparing the pools of the sponsors with their wallets
+    log::info!("Starting the balance reconciliation");
+    tokio::spawn(game::reconcile::run_reconciliation(
+        database.clone(),
+        solana.clone(),
+    ));
 
     // Initialize the TCP listener
     log::info!(
@@ -97,14 +163,25 @@ async fn main() {
         .route("/end", post(game::end::end_handler))
         .route("/judge", post(game::judge::judge_handler))
         .route("/recording", post(game::recording::recording_handler))
+        .route("/call-status", post(game::status::call_status_handler))
         .route("/api/attempts/:id", get(api::attempt_single::attempt_single))
-        .route("/api/sponsors", post(api::sponsor_list::sponsor_list))
-        .route("/api/sponsor/update", post(api::update_sponsor::update_sponsor))
+        .route("/api/auth/challenge", post(api::auth::challenge))
+        .route("/api/auth/verify", post(api::auth::verify))
+        .route("/api/sponsors", get(api::sponsor_list::sponsor_list).post(api::sponsor_list::sponsor_list))
+        .route("/api/sponsor/update", post(api::update_sponsor::update_sponsor).patch(api::update_sponsor::update_sponsor))
+        .route("/api/sponsor/audit", get(api::sponsor_audit::sponsor_audit))
+        .route("/api/sponsor/versions", get(api::sponsor_versions::sponsor_versions))
+        .route("/api/sponsor/versions/diff", get(api::sponsor_versions::sponsor_version_diff))
+        .route("/api/sponsor/rollback", post(api::sponsor_versions::rollback_sponsor))
         .route("/api/attempts", get(api::attempt_list::attempt_list))
         .route("/api/launchpad", post(api::launchpad::launchpad))
         .route("/api/payment", post(api::payment::payment))
         .route("/api/deposit", post(api::deposit::deposit))
         .route("/api/activate-sponsor", post(api::activate_sponsor::activate_sponsor))
+        .route("/api/sponsor/top-up", post(api::top_up::top_up))
+        .route("/api/sponsor/top-up/verify", post(api::top_up::verify_top_up))
+        .route("/api/sponsor/withdraw", post(api::withdraw::withdraw))
+        .route("/api/nft/:call_sid", get(api::nft_metadata::nft_metadata))
         .route("/api/verify-winner", post(api::verify_winner::verify_winner))
         .route(
             "/redirect-gather/*path",
@@ -118,10 +195,11 @@ async fn main() {
         .layer(cors)
         .layer(Extension(secrets))
         .layer(Extension(twilio))
-        .layer(Extension(openai))
+        .layer(Extension(llm))
         .layer(Extension(twitter))
         .layer(Extension(reqwest))
         .layer(Extension(database))
+        .layer(Extension(solana))
         .layer(Extension(cache));
 
     // Start the webserver
@@ -1,3 +1,5 @@
+use crate::database::{Database, DropOffReport, PendingPayout, ReconciliationReport};
+use crate::game::approval::{approve_payout, reject_payout};
 use crate::secrets::Secrets;
 use anyhow::{anyhow, Context, Result};
 use askama::Template;
@@ -7,12 +9,13 @@ use axum::{
     middleware::{self, Next},
     response::{Redirect, Response},
     routing::{get, post},
-    Extension, Form, Router,
+    Extension, Form, Json, Router,
 };
 use axum_extra::extract::CookieJar;
 use reqwest::Client as ReqwestClient;
 use serde::{Deserialize, Serialize};
 use tower_http::services::ServeDir;
+use twilio::Client as TwilioClient;
 use twitter_v2::{authorization::Oauth1aToken, TwitterApi};
 
 mod twitter;
@@ -22,16 +25,42 @@ pub fn router() -> Router {
         .route("/", get(review_page))
         .route("/approve", post(approve_draft))
         .route("/reject", post(reject_draft))
+        .route("/call-stats", get(call_stats))
+        .route("/reconciliation", get(reconciliation))
+        .route("/payouts/approve", post(approve_pending_payout))
+        .route("/payouts/reject", post(reject_pending_payout))
         .nest_service("/drafts", ServeDir::new("cache/drafts"))
         .layer(middleware::from_fn(check_token))
 }
 
-async fn review_page() -> Result<DraftTemplate, StatusCode> {
+async fn review_page(database: Extension<Database>) -> Result<DraftTemplate, StatusCode> {
     Ok(DraftTemplate {
         drafts: get_drafts().await.map_err(|e| {
             log::error!("Failed to get drafts: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?,
+        payouts: database.get_pending_payouts().await.map_err(|e| {
+            log::error!("Failed to get pending payouts: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?,
+    })
+}
+
+/// Reports how the calls of every sponsor ended, to compare drop-off rates.
+async fn call_stats(database: Extension<Database>) -> Result<Json<Vec<DropOffReport>>, StatusCode> {
+    database.get_drop_off_report().await.map(Json).map_err(|e| {
+        log::error!("Failed to get drop-off report: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Reports how the pool of every sponsor compares with the tokens in their wallet.
+async fn reconciliation(
+    database: Extension<Database>,
+) -> Result<Json<Vec<ReconciliationReport>>, StatusCode> {
+    database.get_reconciliation_report().await.map(Json).map_err(|e| {
+        log::error!("Failed to get reconciliation report: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
     })
 }
 
@@ -94,6 +123,38 @@ async fn reject_draft(draft: Form<Draft>) -> Redirect {
     Redirect::to("/review")
 }
 
+async fn approve_pending_payout(
+    twilio: Extension<TwilioClient>,
+    database: Extension<Database>,
+    secrets: Extension<Secrets>,
+    payout: Form<PayoutDecision>,
+) -> Redirect {
+    log::debug!("Approving pending payout {}", payout.id);
+    match approve_payout(twilio.0, database.0, secrets.0, payout.id).await {
+        Ok(true) => {}
+        Ok(false) => log::warn!("Pending payout {} was already decided", payout.id),
+        Err(e) => log::error!("Failed to approve pending payout {}: {:?}", payout.id, e),
+    }
+
+    Redirect::to("/review")
+}
+
+async fn reject_pending_payout(
+    twilio: Extension<TwilioClient>,
+    database: Extension<Database>,
+    secrets: Extension<Secrets>,
+    payout: Form<PayoutDecision>,
+) -> Redirect {
+    log::debug!("Rejecting pending payout {}", payout.id);
+    match reject_payout(twilio.0, database.0, secrets.0, payout.id).await {
+        Ok(true) => {}
+        Ok(false) => log::warn!("Pending payout {} was already decided", payout.id),
+        Err(e) => log::error!("Failed to reject pending payout {}: {:?}", payout.id, e),
+    }
+
+    Redirect::to("/review")
+}
+
 async fn check_token(
     secrets: Extension<Secrets>,
     cookies: CookieJar,
@@ -112,6 +173,7 @@ async fn check_token(
 #[template(path = "review.html")]
 pub struct DraftTemplate {
     drafts: Vec<Draft>,
+    payouts: Vec<PendingPayout>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -119,3 +181,8 @@ pub struct Draft {
     pub call_sid: String,
     pub comment: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct PayoutDecision {
+    pub id: i32,
+}
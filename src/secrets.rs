@@ -1,6 +1,6 @@
 use std::env::var;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Secrets {
     pub global_url: String,
     pub database_url: String,
@@ -17,17 +17,33 @@ pub struct Secrets {
     pub twitter_access_token: String,
     pub twitter_access_secret: String,
     pub rpc_url: String,
+    pub solana_commitment: String,
+    pub solana_compute_unit_limit: u32,
+    pub solana_compute_unit_price: u64,
     pub spaces_secret_key: String,
     pub spaces_access_key: String,
     pub spaces_url: String,
     pub treasury_private_key: String,
     pub treasury_public_key: String,
+    pub sponsor_master_key: String,
+    pub sponsor_previous_master_keys: Option<String>,
+    pub call_store: String,
+    pub claim_window_days: i64,
+    pub llm_compatible_url: Option<String>,
+    pub llm_compatible_api_key: Option<String>,
+    pub llm_compatible_model: Option<String>,
+    pub session_secret: String,
+    pub auth_domain: String,
 }
 
 impl Secrets {
     pub fn from_env() -> Self {
+        let global_url = var("GLOBAL_URL").expect("GLOBAL_URL must be set");
+
         Self {
-            global_url: var("GLOBAL_URL").expect("GLOBAL_URL must be set"),
+            // Sponsors sign in for the host of the app unless the launchpad runs elsewhere
+            auth_domain: var("AUTH_DOMAIN").unwrap_or_else(|_| host_of(&global_url).to_owned()),
+            global_url,
             database_url: var("DATABASE_URL").expect("DATABASE_URL must be set"),
             twilio_phone_number: var("TWILIO_PHONE_NUMBER")
                 .expect("TWILIO_PHONE_NUMBER must be set"),
@@ -45,11 +61,34 @@ impl Secrets {
             twitter_access_secret: var("TWITTER_ACCESS_SECRET")
                 .expect("TWITTER_ACCESS_SECRET must be set"),
             rpc_url: var("RPC_URL").expect("RPC_URL must be set"),
+            solana_commitment: var("SOLANA_COMMITMENT").unwrap_or_else(|_| "confirmed".to_owned()),
+            solana_compute_unit_limit: var("SOLANA_COMPUTE_UNIT_LIMIT")
+                .map(|units| units.parse().expect("SOLANA_COMPUTE_UNIT_LIMIT must be a number"))
+                .unwrap_or(40000),
+            solana_compute_unit_price: var("SOLANA_COMPUTE_UNIT_PRICE")
+                .map(|price| price.parse().expect("SOLANA_COMPUTE_UNIT_PRICE must be a number"))
+                .unwrap_or(1000),
             spaces_secret_key: var("SPACES_SECRET_KEY").expect("SPACES_SECRET_KEY must be set"),
             spaces_access_key: var("SPACES_ACCESS_KEY").expect("SPACES_ACCESS_KEY must be set"),
             spaces_url: var("SPACES_URL").expect("SPACES_URL must be set"),
             treasury_private_key: var("TREASURY_PRIVATE_KEY").expect("TREASURY_PRIVATE_KEY must be set"),
             treasury_public_key: var("TREASURY_PUBLIC_KEY").expect("TREASURY_PUBLIC_KEY must be set"),
+            sponsor_master_key: var("SPONSOR_MASTER_KEY").expect("SPONSOR_MASTER_KEY must be set"),
+            sponsor_previous_master_keys: var("SPONSOR_PREVIOUS_MASTER_KEYS").ok(),
+            call_store: var("CALL_STORE").unwrap_or_else(|_| "postgres".to_owned()),
+            claim_window_days: var("CLAIM_WINDOW_DAYS")
+                .map(|days| days.parse().expect("CLAIM_WINDOW_DAYS must be a number"))
+                .unwrap_or(30),
+            llm_compatible_url: var("LLM_COMPATIBLE_URL").ok(),
+            llm_compatible_api_key: var("LLM_COMPATIBLE_API_KEY").ok(),
+            llm_compatible_model: var("LLM_COMPATIBLE_MODEL").ok(),
+            session_secret: var("SESSION_SECRET").expect("SESSION_SECRET must be set"),
         }
     }
 }
+
+/// The host of the url, e.g. `why.fun` for `https://why.fun/`.
+fn host_of(url: &str) -> &str {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.split('/').next().unwrap_or(url)
+}
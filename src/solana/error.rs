@@ -0,0 +1,74 @@
+use solana_client::client_error::ClientError;
+use solana_sdk::{
+    program_error::ProgramError, signature::Signature, transaction::TransactionError,
+};
+use std::fmt;
+
+/// Everything that can go wrong while talking to the chain.
+#[derive(Debug)]
+pub enum SolanaError {
+    /// An address, key or amount could not be parsed.
+    Invalid(String),
+    /// A request to the RPC node failed.
+    Rpc(Box<ClientError>),
+    /// An instruction could not be built.
+    Instruction(ProgramError),
+    /// A sponsor key could not be decrypted.
+    Key(anyhow::Error),
+    /// A payment or deposit transaction does not make the expected transfer.
+    Rejected(String),
+    /// A payment or deposit transaction was already processed.
+    Replayed(Signature),
+    /// The transaction landed, but failed.
+    Failed(TransactionError),
+    /// Storing a processed transaction failed.
+    Database(anyhow::Error),
+}
+
+impl fmt::Display for SolanaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolanaError::Invalid(e) => write!(f, "Invalid input: {e}"),
+            SolanaError::Rpc(e) => write!(f, "RPC error: {e}"),
+            SolanaError::Instruction(e) => write!(f, "Instruction error: {e}"),
+            SolanaError::Key(e) => write!(f, "Sponsor key error: {e:?}"),
+            SolanaError::Rejected(e) => write!(f, "Rejected transaction: {e}"),
+            SolanaError::Replayed(signature) => {
+                write!(f, "The transaction {signature} was already processed")
+            }
+            SolanaError::Failed(e) => write!(f, "The transaction failed: {e}"),
+            SolanaError::Database(e) => write!(f, "Database error: {e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for SolanaError {}
+
+impl From<ClientError> for SolanaError {
+    fn from(e: ClientError) -> Self {
+        SolanaError::Rpc(Box::new(e))
+    }
+}
+
+impl From<ProgramError> for SolanaError {
+    fn from(e: ProgramError) -> Self {
+        SolanaError::Instruction(e)
+    }
+}
+
+impl SolanaError {
+    /// Whether the error was caused by the request rather than by us or the chain.
+    pub fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            SolanaError::Invalid(_) | SolanaError::Rejected(_) | SolanaError::Replayed(_)
+        )
+    }
+}
+
+/// Parses a base58 address, naming what it is the address of in the error.
+pub fn parse_pubkey(address: &str, name: &str) -> Result<solana_sdk::pubkey::Pubkey, SolanaError> {
+    address
+        .parse()
+        .map_err(|_| SolanaError::Invalid(format!("Invalid {name} address {address}")))
+}
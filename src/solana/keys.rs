@@ -1,11 +1,10 @@
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::Signer;
 use solana_sdk::transaction::Transaction;
-use solana_sdk::commitment_config::CommitmentConfig;
-use crate::Secrets;
+use crate::solana::error::SolanaError;
+use crate::solana::service::SolanaService;
+use crate::solana::transfer::CREATE_ACCOUNT_COMPUTE_UNITS;
 
 
 pub fn generate_private_key() -> Keypair {
@@ -29,40 +28,44 @@ pub fn _derive_public_key_from_private_key(private_key: &str) -> String {
     return keypair.pubkey().to_string();
 }
 
+/// Gets the associated token account of the wallet, and creates it first if it does not exist yet.
 pub async fn get_or_create_ata(
+    solana: &SolanaService,
     payer: &Keypair,
     wallet_address: &Pubkey,
     token_mint_address: &Pubkey,
     token_program_id: &Pubkey,
-    secrets: &Secrets,
-) -> Result<Pubkey, Box<dyn std::error::Error>> {
-
-    let commitment_config = CommitmentConfig::confirmed();
-    let rpc_client = RpcClient::new_with_commitment(&secrets.rpc_url, commitment_config);
+) -> Result<Pubkey, SolanaError> {
 
     // Check if the associated token account already exists
-    let ata_address = spl_associated_token_account::get_associated_token_address(
-        &wallet_address,
-        &token_mint_address,
+    let ata_address = spl_associated_token_account::get_associated_token_address_with_program_id(
+        wallet_address,
+        token_mint_address,
+        token_program_id,
     );
 
-    if rpc_client.get_account(&ata_address).is_ok() {
+    if solana
+        .rpc_client()
+        .get_account_with_commitment(&ata_address, solana.commitment())
+        .await?
+        .value
+        .is_some()
+    {
         log::debug!("ATA already exists: {}", ata_address);
         return Ok(ata_address);
     }
 
     // Create the associated token account if it doesn't exist
-    let create_ata_ix = spl_associated_token_account::instruction::create_associated_token_account(
+    let create_ata_ix = spl_associated_token_account::instruction::create_associated_token_account_idempotent(
         &payer.pubkey(),
-        &wallet_address,
-        &token_mint_address,
-        &token_program_id,
+        wallet_address,
+        token_mint_address,
+        token_program_id,
     );
 
-    let modify_compute_units = ComputeBudgetInstruction::set_compute_unit_limit(40000);
-    let set_priority_fee = ComputeBudgetInstruction::set_compute_unit_price(1000);
+    let [modify_compute_units, set_priority_fee] = solana.compute_budget(CREATE_ACCOUNT_COMPUTE_UNITS);
 
-    let latest_blockhash = rpc_client.get_latest_blockhash()?;
+    let (latest_blockhash, _) = solana.latest_blockhash().await?;
 
     let transaction = Transaction::new_signed_with_payer(
         &[create_ata_ix, modify_compute_units, set_priority_fee],
@@ -71,11 +74,14 @@ pub async fn get_or_create_ata(
         latest_blockhash,
     );
 
-    let signature = rpc_client.send_transaction(&transaction)?;
+    // The account has to exist before a transfer to it can be signed
+    let signature = solana
+        .rpc_client()
+        .send_and_confirm_transaction(&transaction)
+        .await?;
 
-    println!("signature: {}", signature.to_string());
+    log::debug!("Created ATA {} with signature {}", ata_address, signature);
 
-    // Return the ATA address after confirming the transaction
     Ok(ata_address)
 }
 
@@ -1,6 +1,14 @@
+pub mod error;
 pub mod keys;
+pub mod nft;
+pub mod prize;
+pub mod service;
+pub mod sponsor_key;
 pub mod transfer;
 pub mod generate_payment;
-pub mod verify_payment;
 pub mod generate_deposit;
-pub mod verify_deposit;
\ No newline at end of file
+pub mod verify;
+pub mod withdraw;
+
+#[cfg(test)]
+mod tests;
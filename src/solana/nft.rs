@@ -0,0 +1,385 @@
+use crate::{
+    database::{NftPrize, Sponsor},
+    solana::{
+        error::{parse_pubkey, SolanaError},
+        service::SolanaService,
+        sponsor_key::SponsorKey,
+        transfer::SignedTransfer,
+    },
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use solana_sdk::{
+    hash::hashv,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{keypair_from_seed, Keypair, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{
+    extension::{metadata_pointer, ExtensionType},
+    instruction::{initialize_mint2, mint_to, set_authority, AuthorityType},
+    state::Mint,
+};
+use spl_token_metadata_interface::state::{Field, TokenMetadata};
+
+/// The symbol of every NFT prize.
+pub const NFT_SYMBOL: &str = "WHYFUN";
+
+/// Wallets cut the names of NFTs off after 32 characters.
+const MAX_NAME_LENGTH: usize = 32;
+
+/// The compute units that creating the mint with its metadata needs on top of a transfer.
+const MINT_NFT_COMPUTE_UNITS: u32 = 160_000;
+
+/// An NFT mint that is signed, but not necessarily sent yet.
+pub struct SignedMint {
+    pub transfer: SignedTransfer,
+    pub mint: Pubkey,
+}
+
+/// The metadata of the NFT for the winner of the call, generated from the attempt. The
+/// off-chain metadata is served by the app from the returned uri.
+pub fn nft_prize(
+    sponsor: &Sponsor,
+    call_sid: &str,
+    caller_name: &str,
+    rating: Option<i32>,
+    video_url: &str,
+    global_url: &str,
+) -> NftPrize {
+    let name: String = format!("{} winner", sponsor.name)
+        .chars()
+        .take(MAX_NAME_LENGTH)
+        .collect();
+
+    NftPrize {
+        call_sid: call_sid.to_owned(),
+        sponsor_id: sponsor.id,
+        name: name.trim_end().to_owned(),
+        symbol: NFT_SYMBOL.to_owned(),
+        uri: format!("{global_url}/api/nft/{call_sid}"),
+        caller_name: caller_name.to_owned(),
+        sponsor_name: sponsor.name.clone(),
+        challenge: sponsor.challenge_text.clone(),
+        rating,
+        video_url: video_url.to_owned(),
+        image_url: sponsor.background_url.clone(),
+        mint: None,
+        created_at: Utc::now(),
+    }
+}
+
+/// The off-chain metadata of the NFT in the format wallets and marketplaces read.
+pub fn offchain_metadata(prize: &NftPrize) -> Value {
+    let mut attributes = vec![
+        json!({ "trait_type": "Caller", "value": prize.caller_name }),
+        json!({ "trait_type": "Sponsor", "value": prize.sponsor_name }),
+        json!({ "trait_type": "Challenge", "value": prize.challenge }),
+    ];
+    if let Some(rating) = prize.rating {
+        attributes.push(json!({ "trait_type": "Rating", "value": rating }));
+    }
+
+    json!({
+        "name": prize.name,
+        "symbol": prize.symbol,
+        "description": format!(
+            "{} won the challenge of {} on why.fun: {}",
+            prize.caller_name, prize.sponsor_name, prize.challenge
+        ),
+        "image": prize.image_url,
+        "animation_url": prize.video_url,
+        "attributes": attributes,
+        "properties": {
+            "category": "video",
+            "files": [{ "uri": prize.video_url, "type": "video/mp4" }],
+        },
+    })
+}
+
+/// Checks whether the mint of an NFT prize exists, the NFT was minted to the receiver then.
+pub async fn nft_minted(solana: &SolanaService, mint: &str) -> Result<bool, SolanaError> {
+    let mint = parse_pubkey(mint, "NFT mint")?;
+
+    Ok(solana
+        .rpc_client()
+        .get_account_with_commitment(&mint, solana.commitment())
+        .await?
+        .value
+        .is_some())
+}
+
+/// Signs the transaction that creates a token-2022 mint holding the metadata of the prize,
+/// mints the single NFT to the receiver and removes the mint authority, so the supply stays 1.
+/// The sponsor pays the rent and the fees. Every transaction for the same prize uses the same
+/// mint, once one of them landed the others fail.
+pub async fn sign_nft_mint(
+    solana: &SolanaService,
+    sponsor_key: &SponsorKey,
+    receiver_pubkey: &Pubkey,
+    prize: &NftPrize,
+) -> Result<SignedMint, SolanaError> {
+    log::debug!("Sign Solana NFT mint of call {}", prize.call_sid);
+
+    let sponsor_keypair = solana.open_key(sponsor_key)?;
+    let mint = mint_keypair(&sponsor_keypair, &prize.call_sid)?;
+
+    let metadata = token_metadata(prize, &mint.pubkey());
+    let (mint_space, metadata_space) = mint_space(&metadata)?;
+    let rent = solana
+        .rpc_client()
+        .get_minimum_balance_for_rent_exemption(mint_space + metadata_space)
+        .await?;
+
+    let mut instructions = mint_instructions(
+        &sponsor_keypair.pubkey(),
+        &mint.pubkey(),
+        receiver_pubkey,
+        &metadata,
+        mint_space,
+        rent,
+    )?;
+    instructions.extend(solana.compute_budget(MINT_NFT_COMPUTE_UNITS));
+
+    let (latest_blockhash, last_valid_block_height) = solana.latest_blockhash().await?;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&sponsor_keypair.pubkey()),
+        &[&sponsor_keypair, &mint],
+        latest_blockhash,
+    );
+
+    Ok(SignedMint {
+        transfer: SignedTransfer {
+            signature: transaction.signatures[0],
+            transaction,
+            last_valid_block_height,
+        },
+        mint: mint.pubkey(),
+    })
+}
+
+/// The keypair of the mint of the NFT prize of the call, derived from the sponsor's key so
+/// it is the same on every retry without being stored.
+fn mint_keypair(sponsor_keypair: &Keypair, call_sid: &str) -> Result<Keypair, SolanaError> {
+    let seed = hashv(&[
+        b"nft-mint".as_slice(),
+        &sponsor_keypair.to_bytes(),
+        call_sid.as_bytes(),
+    ]);
+
+    keypair_from_seed(seed.as_ref())
+        .map_err(|e| SolanaError::Invalid(format!("Invalid NFT mint seed: {e}")))
+}
+
+/// The on-chain metadata of the NFT. The challenge is only part of the off-chain metadata,
+/// as everything on-chain has to fit into the mint transaction.
+fn token_metadata(prize: &NftPrize, mint: &Pubkey) -> TokenMetadata {
+    let mut additional_metadata = vec![("caller".to_owned(), prize.caller_name.clone())];
+    if let Some(rating) = prize.rating {
+        additional_metadata.push(("rating".to_owned(), rating.to_string()));
+    }
+    additional_metadata.push(("video".to_owned(), prize.video_url.clone()));
+
+    TokenMetadata {
+        mint: *mint,
+        name: prize.name.clone(),
+        symbol: prize.symbol.clone(),
+        uri: prize.uri.clone(),
+        additional_metadata,
+        ..Default::default()
+    }
+}
+
+/// The space of the mint account when it is created and the space the metadata adds to it.
+/// The metadata is written into the account afterwards, the rent has to cover both already.
+fn mint_space(metadata: &TokenMetadata) -> Result<(usize, usize), SolanaError> {
+    let mint_space =
+        ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::MetadataPointer])?;
+
+    Ok((mint_space, metadata.tlv_size_of()?))
+}
+
+fn mint_instructions(
+    authority: &Pubkey,
+    mint: &Pubkey,
+    receiver: &Pubkey,
+    metadata: &TokenMetadata,
+    mint_space: usize,
+    rent: u64,
+) -> Result<Vec<Instruction>, SolanaError> {
+    let program = spl_token_2022::id();
+    let receiver_token_account =
+        get_associated_token_address_with_program_id(receiver, mint, &program);
+
+    let mut instructions = vec![
+        system_instruction::create_account(authority, mint, rent, mint_space as u64, &program),
+        // The mint points to itself for its metadata
+        metadata_pointer::instruction::initialize(&program, mint, Some(*authority), Some(*mint))?,
+        initialize_mint2(&program, mint, authority, None, 0)?,
+        spl_token_metadata_interface::instruction::initialize(
+            &program,
+            mint,
+            authority,
+            mint,
+            authority,
+            metadata.name.clone(),
+            metadata.symbol.clone(),
+            metadata.uri.clone(),
+        ),
+    ];
+
+    for (key, value) in &metadata.additional_metadata {
+        instructions.push(spl_token_metadata_interface::instruction::update_field(
+            &program,
+            mint,
+            authority,
+            Field::Key(key.clone()),
+            value.clone(),
+        ));
+    }
+
+    instructions.extend([
+        create_associated_token_account_idempotent(authority, receiver, mint, &program),
+        mint_to(&program, mint, &receiver_token_account, authority, &[], 1)?,
+        set_authority(
+            &program,
+            mint,
+            None,
+            AuthorityType::MintTokens,
+            authority,
+            &[],
+        )?,
+    ]);
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{program_option::COption, system_program};
+    use spl_token::instruction::TokenInstruction;
+
+    fn sponsor() -> Sponsor {
+        Sponsor {
+            id: 7,
+            name: "Sponsor with a rather long name".to_owned(),
+            challenge_text: "Sell me this pen".to_owned(),
+            background_url: "https://cdn.why.fun/background.png".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn prize(rating: Option<i32>) -> NftPrize {
+        nft_prize(
+            &sponsor(),
+            "CA123",
+            "Alice",
+            rating,
+            "https://cdn.why.fun/CA123.mp4",
+            "https://api.why.fun",
+        )
+    }
+
+    #[test]
+    fn metadata_is_generated_from_the_attempt() {
+        let prize = prize(Some(8));
+
+        assert_eq!(prize.name, "Sponsor with a rather long name");
+        assert!(prize.name.chars().count() <= MAX_NAME_LENGTH);
+        assert_eq!(prize.uri, "https://api.why.fun/api/nft/CA123");
+
+        let metadata = offchain_metadata(&prize);
+        assert_eq!(metadata["animation_url"], "https://cdn.why.fun/CA123.mp4");
+        assert_eq!(metadata["image"], "https://cdn.why.fun/background.png");
+        assert!(metadata["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "trait_type": "Rating", "value": 8 })));
+    }
+
+    #[test]
+    fn unrated_attempt_has_no_rating() {
+        let prize = prize(None);
+        let metadata = token_metadata(&prize, &Pubkey::new_unique());
+
+        assert!(metadata
+            .additional_metadata
+            .iter()
+            .all(|(key, _)| key != "rating"));
+        assert_eq!(
+            offchain_metadata(&prize)["attributes"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn retries_use_the_same_mint() {
+        let sponsor_keypair = Keypair::new();
+        let mint = mint_keypair(&sponsor_keypair, "CA123").unwrap();
+
+        assert_eq!(
+            mint_keypair(&sponsor_keypair, "CA123").unwrap().pubkey(),
+            mint.pubkey()
+        );
+        assert_ne!(
+            mint_keypair(&sponsor_keypair, "CA456").unwrap().pubkey(),
+            mint.pubkey()
+        );
+        assert_ne!(
+            mint_keypair(&Keypair::new(), "CA123").unwrap().pubkey(),
+            mint.pubkey()
+        );
+    }
+
+    #[test]
+    fn mints_a_single_nft_to_the_receiver() {
+        let (authority, mint, receiver) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let metadata = token_metadata(&prize(Some(8)), &mint);
+        let (mint_space, metadata_space) = mint_space(&metadata).unwrap();
+        assert!(metadata_space > 0);
+
+        let instructions =
+            mint_instructions(&authority, &mint, &receiver, &metadata, mint_space, 1).unwrap();
+
+        assert_eq!(instructions[0].program_id, system_program::id());
+        assert!(instructions[1..]
+            .iter()
+            .all(|instruction| instruction.program_id == spl_token_2022::id()
+                || instruction.program_id == spl_associated_token_account::id()));
+
+        let [.., mint_to, fix_supply] = instructions.as_slice() else {
+            unreachable!()
+        };
+        assert!(matches!(
+            TokenInstruction::unpack(&mint_to.data),
+            Ok(TokenInstruction::MintTo { amount: 1 })
+        ));
+        assert_eq!(
+            mint_to.accounts[1].pubkey,
+            get_associated_token_address_with_program_id(&receiver, &mint, &spl_token_2022::id())
+        );
+        assert!(matches!(
+            TokenInstruction::unpack(&fix_supply.data),
+            Ok(TokenInstruction::SetAuthority {
+                authority_type: spl_token::instruction::AuthorityType::MintTokens,
+                new_authority: COption::None,
+            })
+        ));
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{
+    database::Sponsor,
+    solana::{
+        error::{parse_pubkey, SolanaError},
+        service::SolanaService,
+        verify::ExpectedTransfer,
+    },
+};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, system_instruction};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Mint};
+
+/// The number of decimals of SOL, a lamport is the smallest unit.
+pub const SOL_DECIMALS: u8 = spl_token::native_mint::DECIMALS;
+
+/// The smallest SOL prize. A transfer that leaves a new wallet below the rent exempt minimum
+/// fails, so smaller prizes could not be paid out to callers with an empty wallet.
+pub const MIN_SOL_PRIZE_LAMPORTS: u64 = 1_000_000;
+
+/// The SOL a sponsor deposits for every NFT prize. It pays the rent of the mint with its
+/// metadata, the winner's token account and the fees of minting.
+pub const NFT_MINT_LAMPORTS: u64 = 10_000_000;
+
+/// What a sponsor gives away as prize, stored as `prize_kind` on the sponsor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrizeKind {
+    /// Native SOL, the amounts of the sponsor are in lamports.
+    Sol,
+    /// Tokens of a mint of the token program.
+    Spl,
+    /// Tokens of a mint of the token-2022 program.
+    Token2022,
+    /// An NFT that is minted for every winner, the amounts of the sponsor are NFTs and
+    /// are funded with SOL.
+    Nft,
+}
+
+impl PrizeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrizeKind::Sol => "sol",
+            PrizeKind::Spl => "spl",
+            PrizeKind::Token2022 => "token_2022",
+            PrizeKind::Nft => "nft",
+        }
+    }
+
+    /// The program that owns the mints of the kind, `None` for SOL and for NFTs, whose
+    /// mints are only created when they are paid out.
+    pub fn token_program(&self) -> Option<Pubkey> {
+        match self {
+            PrizeKind::Sol | PrizeKind::Nft => None,
+            PrizeKind::Spl => Some(spl_token::id()),
+            PrizeKind::Token2022 => Some(spl_token_2022::id()),
+        }
+    }
+}
+
+impl From<&str> for PrizeKind {
+    /// Sponsors were launched with SPL tokens before the kind could be chosen, so anything
+    /// unknown is SPL.
+    fn from(value: &str) -> Self {
+        match value {
+            "sol" => PrizeKind::Sol,
+            "token_2022" => PrizeKind::Token2022,
+            "nft" => PrizeKind::Nft,
+            _ => PrizeKind::Spl,
+        }
+    }
+}
+
+/// The asset a sponsor pays its prizes in, with everything needed to move it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prize {
+    Sol,
+    Nft,
+    Token {
+        program: Pubkey,
+        mint: Pubkey,
+        decimals: u8,
+    },
+}
+
+impl Prize {
+    /// Looks up the prize of the sponsor.
+    pub async fn of_sponsor(
+        solana: &SolanaService,
+        sponsor: &Sponsor,
+    ) -> Result<Self, SolanaError> {
+        Self::resolve(
+            solana,
+            PrizeKind::from(sponsor.prize_kind.as_str()),
+            &sponsor.token_mint,
+        )
+        .await
+    }
+
+    /// Looks up the prize of the kind. The mint of a token has to be owned by the program of
+    /// the kind, its decimals are read from the mint.
+    pub async fn resolve(
+        solana: &SolanaService,
+        kind: PrizeKind,
+        token_mint: &str,
+    ) -> Result<Self, SolanaError> {
+        let Some(program) = kind.token_program() else {
+            return Ok(match kind {
+                PrizeKind::Nft => Prize::Nft,
+                _ => Prize::Sol,
+            });
+        };
+
+        let mint = parse_pubkey(token_mint, "token mint")?;
+        let account = solana.rpc_client().get_account(&mint).await?;
+        if account.owner != program {
+            return Err(SolanaError::Invalid(format!(
+                "The mint {mint} is not a {} mint",
+                kind.as_str()
+            )));
+        }
+
+        let state = StateWithExtensions::<Mint>::unpack(&account.data)
+            .map_err(|_| SolanaError::Invalid(format!("The account {mint} is not a mint")))?;
+
+        Ok(Prize::Token {
+            program,
+            mint,
+            decimals: state.base.decimals,
+        })
+    }
+
+    pub fn kind(&self) -> PrizeKind {
+        match self {
+            Prize::Sol => PrizeKind::Sol,
+            Prize::Nft => PrizeKind::Nft,
+            Prize::Token { program, .. } if *program == spl_token_2022::id() => {
+                PrizeKind::Token2022
+            }
+            Prize::Token { .. } => PrizeKind::Spl,
+        }
+    }
+
+    pub fn decimals(&self) -> u8 {
+        match self {
+            Prize::Sol => SOL_DECIMALS,
+            Prize::Nft => 0,
+            Prize::Token { decimals, .. } => *decimals,
+        }
+    }
+
+    /// The account that holds the prize for the wallet, the wallet itself for SOL and NFTs
+    /// and its associated token account otherwise.
+    pub fn holding_account(&self, wallet: &Pubkey) -> Pubkey {
+        match self {
+            Prize::Sol | Prize::Nft => *wallet,
+            Prize::Token { program, mint, .. } => {
+                get_associated_token_address_with_program_id(wallet, mint, program)
+            }
+        }
+    }
+
+    /// The instruction that creates the holding account of the wallet if it does not exist
+    /// yet, `None` for SOL and NFTs.
+    pub fn create_account_instruction(
+        &self,
+        payer: &Pubkey,
+        wallet: &Pubkey,
+    ) -> Option<Instruction> {
+        match self {
+            Prize::Sol | Prize::Nft => None,
+            Prize::Token { program, mint, .. } => Some(create_associated_token_account_idempotent(
+                payer, wallet, mint, program,
+            )),
+        }
+    }
+
+    /// The instruction that moves `amount` from the sender to the holding account of the
+    /// receiver, which has to exist already. Tokens are moved with a checked transfer, which
+    /// token-2022 mints require. NFTs are not transferred, only the SOL that funds them.
+    pub fn transfer_instruction(
+        &self,
+        sender: &Pubkey,
+        receiver: &Pubkey,
+        amount: u64,
+    ) -> Result<Instruction, SolanaError> {
+        match self {
+            Prize::Sol => Ok(system_instruction::transfer(sender, receiver, amount)),
+            Prize::Nft => {
+                let lamports = amount.checked_mul(NFT_MINT_LAMPORTS).ok_or_else(|| {
+                    SolanaError::Invalid(format!("Invalid number of NFTs {amount}"))
+                })?;
+                Ok(system_instruction::transfer(sender, receiver, lamports))
+            }
+            Prize::Token {
+                program,
+                mint,
+                decimals,
+            } => Ok(spl_token_2022::instruction::transfer_checked(
+                program,
+                &self.holding_account(sender),
+                mint,
+                &self.holding_account(receiver),
+                sender,
+                &[],
+                amount,
+                *decimals,
+            )?),
+        }
+    }
+
+    /// The transfer of `amount` to the holding account of the receiver that a deposit has
+    /// to make.
+    pub fn expected_transfer(&self, receiver: &Pubkey, amount: u64) -> ExpectedTransfer {
+        match self {
+            Prize::Sol => ExpectedTransfer::Lamports {
+                receiver: *receiver,
+                amount,
+            },
+            Prize::Nft => ExpectedTransfer::Lamports {
+                receiver: *receiver,
+                amount: amount.saturating_mul(NFT_MINT_LAMPORTS),
+            },
+            Prize::Token {
+                program,
+                mint,
+                decimals,
+            } => ExpectedTransfer::Tokens {
+                token_program: *program,
+                mint: *mint,
+                decimals: *decimals,
+                receiver_token_account: self.holding_account(receiver),
+                amount,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::verify::check_transfer;
+    use solana_sdk::{
+        hash::Hash,
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::Transaction,
+    };
+    use spl_token::instruction::TokenInstruction;
+
+    fn token(program: Pubkey) -> Prize {
+        Prize::Token {
+            program,
+            mint: Pubkey::new_unique(),
+            decimals: 6,
+        }
+    }
+
+    fn prizes() -> [Prize; 4] {
+        [
+            Prize::Sol,
+            Prize::Nft,
+            token(spl_token::id()),
+            token(spl_token_2022::id()),
+        ]
+    }
+
+    #[test]
+    fn kind_round_trips() {
+        for kind in [
+            PrizeKind::Sol,
+            PrizeKind::Spl,
+            PrizeKind::Token2022,
+            PrizeKind::Nft,
+        ] {
+            assert_eq!(PrizeKind::from(kind.as_str()), kind);
+        }
+        assert_eq!(PrizeKind::from(""), PrizeKind::Spl);
+    }
+
+    #[test]
+    fn prize_has_the_kind_of_its_program() {
+        assert_eq!(
+            prizes().map(|prize| prize.kind()),
+            [
+                PrizeKind::Sol,
+                PrizeKind::Nft,
+                PrizeKind::Spl,
+                PrizeKind::Token2022
+            ]
+        );
+        assert_eq!(Prize::Sol.decimals(), 9);
+    }
+
+    #[test]
+    fn sol_is_a_system_transfer_between_wallets() {
+        let (sender, receiver) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instruction = Prize::Sol
+            .transfer_instruction(&sender, &receiver, 5000)
+            .unwrap();
+
+        assert_eq!(instruction.program_id, system_program::id());
+        assert_eq!(instruction.accounts[1].pubkey, receiver);
+        assert_eq!(Prize::Sol.holding_account(&receiver), receiver);
+        assert!(Prize::Sol
+            .create_account_instruction(&sender, &receiver)
+            .is_none());
+    }
+
+    #[test]
+    fn nfts_are_funded_with_sol() {
+        let (sender, receiver) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instruction = Prize::Nft
+            .transfer_instruction(&sender, &receiver, 3)
+            .unwrap();
+
+        assert_eq!(instruction.program_id, system_program::id());
+        assert!(matches!(
+            bincode::deserialize(&instruction.data),
+            Ok(system_instruction::SystemInstruction::Transfer { lamports })
+                if lamports == 3 * NFT_MINT_LAMPORTS
+        ));
+        assert!(Prize::Nft
+            .transfer_instruction(&sender, &receiver, u64::MAX)
+            .is_err());
+    }
+
+    #[test]
+    fn tokens_are_sent_checked_by_their_program() {
+        for prize in [token(spl_token::id()), token(spl_token_2022::id())] {
+            let Prize::Token { program, mint, .. } = prize else {
+                unreachable!()
+            };
+            let (sender, receiver) = (Pubkey::new_unique(), Pubkey::new_unique());
+            let instruction = prize
+                .transfer_instruction(&sender, &receiver, 5000)
+                .unwrap();
+
+            assert_eq!(instruction.program_id, program);
+            assert_eq!(instruction.accounts[1].pubkey, mint);
+            assert_eq!(
+                instruction.accounts[2].pubkey,
+                get_associated_token_address_with_program_id(&receiver, &mint, &program)
+            );
+            assert!(matches!(
+                TokenInstruction::unpack(&instruction.data),
+                Ok(TokenInstruction::TransferChecked {
+                    amount: 5000,
+                    decimals: 6
+                })
+            ));
+            assert_eq!(
+                prize
+                    .create_account_instruction(&sender, &receiver)
+                    .unwrap()
+                    .program_id,
+                spl_associated_token_account::id()
+            );
+        }
+    }
+
+    #[test]
+    fn token_accounts_depend_on_the_program() {
+        let mint = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let spl = Prize::Token {
+            program: spl_token::id(),
+            mint,
+            decimals: 6,
+        };
+        let token_2022 = Prize::Token {
+            program: spl_token_2022::id(),
+            mint,
+            decimals: 6,
+        };
+
+        assert_ne!(
+            spl.holding_account(&wallet),
+            token_2022.holding_account(&wallet)
+        );
+    }
+
+    #[test]
+    fn deposits_of_every_kind_are_accepted() {
+        for prize in prizes() {
+            let sender = Keypair::new();
+            let receiver = Pubkey::new_unique();
+            let instruction = prize
+                .transfer_instruction(&sender.pubkey(), &receiver, 5000)
+                .unwrap();
+            let transaction = Transaction::new_signed_with_payer(
+                &[instruction],
+                Some(&sender.pubkey()),
+                &[&sender],
+                Hash::default(),
+            );
+
+            assert!(
+                check_transfer(&transaction, &prize.expected_transfer(&receiver, 5000)).is_ok()
+            );
+            assert!(
+                check_transfer(&transaction, &prize.expected_transfer(&receiver, 4000)).is_err()
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    secrets::Secrets,
+    solana::{
+        error::SolanaError,
+        sponsor_key::{MasterKeys, SponsorKey},
+    },
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    signature::{Keypair, Signature},
+    transaction::TransactionError,
+};
+use std::{str::FromStr, sync::Arc};
+
+/// How the transactions are sent and when they count as landed.
+#[derive(Debug, Clone, Copy)]
+pub struct SolanaConfig {
+    pub commitment: CommitmentConfig,
+    /// The compute unit limit of a transaction with a single transfer
+    pub compute_unit_limit: u32,
+    /// The priority fee in micro-lamports per compute unit
+    pub compute_unit_price: u64,
+}
+
+impl SolanaConfig {
+    pub fn from_secrets(secrets: &Secrets) -> Result<Self, SolanaError> {
+        let commitment = CommitmentLevel::from_str(&secrets.solana_commitment).map_err(|_| {
+            SolanaError::Invalid(format!("Invalid commitment {}", secrets.solana_commitment))
+        })?;
+
+        Ok(Self {
+            commitment: CommitmentConfig { commitment },
+            compute_unit_limit: secrets.solana_compute_unit_limit,
+            compute_unit_price: secrets.solana_compute_unit_price,
+        })
+    }
+}
+
+/// What the chain knows about a transaction.
+pub(in crate::solana) enum SignatureStatus {
+    /// The transaction reached the configured commitment, with its result
+    Landed(Result<(), TransactionError>),
+    /// The transaction was processed, but did not reach the commitment yet
+    Processing,
+    /// The chain does not know the transaction
+    Unknown,
+}
+
+/// The connection to the chain that is shared by the handlers and the workers, together
+/// with the keys that sign for the treasury and the sponsors.
+#[derive(Clone)]
+pub struct SolanaService {
+    rpc_client: Arc<RpcClient>,
+    treasury: Arc<Keypair>,
+    master_keys: Arc<MasterKeys>,
+    config: SolanaConfig,
+}
+
+impl SolanaService {
+    pub fn new(
+        rpc_url: String,
+        config: SolanaConfig,
+        treasury: Keypair,
+        master_keys: MasterKeys,
+    ) -> Self {
+        Self {
+            rpc_client: Arc::new(RpcClient::new_with_commitment(rpc_url, config.commitment)),
+            treasury: Arc::new(treasury),
+            master_keys: Arc::new(master_keys),
+            config,
+        }
+    }
+
+    pub fn from_secrets(secrets: &Secrets) -> Result<Self, SolanaError> {
+        let treasury_bytes = bs58::decode(&secrets.treasury_private_key)
+            .into_vec()
+            .map_err(|_| SolanaError::Invalid("Invalid treasury private key".to_owned()))?;
+        let treasury = Keypair::from_bytes(&treasury_bytes)
+            .map_err(|_| SolanaError::Invalid("Invalid treasury private key".to_owned()))?;
+        let master_keys = MasterKeys::from_secrets(secrets).map_err(SolanaError::Key)?;
+
+        Ok(Self::new(
+            secrets.rpc_url.clone(),
+            SolanaConfig::from_secrets(secrets)?,
+            treasury,
+            master_keys,
+        ))
+    }
+
+    pub(in crate::solana) fn rpc_client(&self) -> &RpcClient {
+        &self.rpc_client
+    }
+
+    pub(in crate::solana) fn treasury(&self) -> &Keypair {
+        &self.treasury
+    }
+
+    pub(in crate::solana) fn commitment(&self) -> CommitmentConfig {
+        self.config.commitment
+    }
+
+    /// Encrypts the wallet key of a new sponsor with the current master key.
+    pub fn seal_key(&self, keypair: &Keypair) -> Result<SponsorKey, SolanaError> {
+        SponsorKey::seal(keypair, &self.master_keys).map_err(SolanaError::Key)
+    }
+
+    /// Decrypts the wallet key of a sponsor.
+    pub(in crate::solana) fn open_key(&self, key: &SponsorKey) -> Result<Keypair, SolanaError> {
+        key.open(&self.master_keys).map_err(SolanaError::Key)
+    }
+
+    /// The instructions that set the compute unit limit and the priority fee. Transactions
+    /// that do more than a single transfer ask for `extra_units` on top of the limit.
+    pub(in crate::solana) fn compute_budget(&self, extra_units: u32) -> [Instruction; 2] {
+        [
+            ComputeBudgetInstruction::set_compute_unit_limit(
+                self.config.compute_unit_limit + extra_units,
+            ),
+            ComputeBudgetInstruction::set_compute_unit_price(self.config.compute_unit_price),
+        ]
+    }
+
+    /// The latest blockhash and the last block height at which a transaction using it can land.
+    pub(in crate::solana) async fn latest_blockhash(&self) -> Result<(Hash, u64), SolanaError> {
+        Ok(self
+            .rpc_client
+            .get_latest_blockhash_with_commitment(self.config.commitment)
+            .await?)
+    }
+
+    /// Looks the transaction up in the whole transaction history. The recent status cache
+    /// only covers the last few minutes, a transaction that is missing from it may have
+    /// landed long ago.
+    pub(in crate::solana) async fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<SignatureStatus, SolanaError> {
+        let status = self
+            .rpc_client
+            .get_signature_statuses_with_history(&[*signature])
+            .await?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+
+        Ok(match status {
+            Some(status) if status.satisfies_commitment(self.config.commitment) => {
+                SignatureStatus::Landed(status.status)
+            }
+            Some(_) => SignatureStatus::Processing,
+            None => SignatureStatus::Unknown,
+        })
+    }
+}
@@ -0,0 +1,347 @@
+//! Envelope encryption of the sponsor wallet keys.
+//!
+//! Every sponsor key is encrypted with its own random data key, which is in turn encrypted
+//! with a master key from the secrets. Rotating the master key only re-encrypts the data keys.
+//! A sealed key is stored as `v1.<master key id>.<wrapped data key>.<encrypted keypair>`.
+
+use crate::{database::Database, secrets::Secrets};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_sdk::signature::Keypair;
+use std::fmt;
+
+const ENVELOPE_VERSION: &str = "v1";
+const NONCE_LENGTH: usize = 12;
+
+/// The encrypted wallet key of a sponsor. It is never logged or serialized, and only
+/// `solana::transfer` can decrypt it.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SponsorKey(String);
+
+impl SponsorKey {
+    /// Encrypts the keypair with a new data key under the current master key.
+    pub fn seal(keypair: &Keypair, keys: &MasterKeys) -> Result<Self> {
+        seal_bytes(&keypair.to_bytes(), keys)
+    }
+
+    /// Decrypts the keypair, only the transfers need it.
+    pub(in crate::solana) fn open(&self, keys: &MasterKeys) -> Result<Keypair> {
+        let bytes = self.open_bytes(keys)?;
+        Keypair::from_bytes(&bytes).context("Parsing sponsor keypair")
+    }
+
+    /// The sealed key as it is stored in the database.
+    pub fn as_sealed(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the key is encrypted, keys from before the encryption are plain base58.
+    pub fn is_sealed(&self) -> bool {
+        self.0.starts_with(&format!("{ENVELOPE_VERSION}."))
+    }
+
+    /// Re-encrypts the data key under the current master key.
+    /// Returns `None` if the key already uses the current master key.
+    pub fn rewrap(&self, keys: &MasterKeys) -> Result<Option<Self>> {
+        let envelope = Envelope::parse(&self.0)?;
+        if envelope.key_id == keys.current.id {
+            return Ok(None);
+        }
+
+        let data_key = keys.get(envelope.key_id)?.unwrap(&envelope)?;
+        let wrapped_key = keys.current.wrap(&data_key)?;
+
+        Ok(Some(Self(format!(
+            "{ENVELOPE_VERSION}.{}.{wrapped_key}.{}",
+            keys.current.id, envelope.data
+        ))))
+    }
+
+    fn open_bytes(&self, keys: &MasterKeys) -> Result<Vec<u8>> {
+        if self.0.is_empty() {
+            bail!("The sponsor key is not available");
+        }
+
+        let envelope = Envelope::parse(&self.0)?;
+        let data_key = keys.get(envelope.key_id)?.unwrap(&envelope)?;
+        let cipher = Aes256Gcm::new(&data_key);
+
+        decrypt(&cipher, envelope.data, b"").context("Decrypting sponsor key")
+    }
+}
+
+impl From<String> for SponsorKey {
+    fn from(sealed: String) -> Self {
+        Self(sealed)
+    }
+}
+
+impl fmt::Debug for SponsorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SponsorKey(<redacted>)")
+    }
+}
+
+impl Serialize for SponsorKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<redacted>")
+    }
+}
+
+/// Serialized keys are redacted, so deserialized keys can not be opened.
+impl<'de> Deserialize<'de> for SponsorKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde::de::IgnoredAny::deserialize(deserializer)?;
+        Ok(Self::default())
+    }
+}
+
+/// The master keys from the secrets, new keys are always sealed with the current one.
+pub struct MasterKeys {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+impl MasterKeys {
+    /// Parses the current and previous master keys, every key is written as `<id>:<base64 key>`.
+    pub fn from_secrets(secrets: &Secrets) -> Result<Self> {
+        let previous = secrets
+            .sponsor_previous_master_keys
+            .iter()
+            .flat_map(|keys| keys.split(','))
+            .filter(|key| !key.trim().is_empty())
+            .map(MasterKey::parse)
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            current: MasterKey::parse(&secrets.sponsor_master_key)?,
+            previous,
+        })
+    }
+
+    fn get(&self, id: &str) -> Result<&MasterKey> {
+        std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == id)
+            .ok_or_else(|| anyhow!("Unknown master key {id}"))
+    }
+}
+
+struct MasterKey {
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    fn parse(key: &str) -> Result<Self> {
+        let (id, key) = key
+            .trim()
+            .split_once(':')
+            .context("Master keys must be written as <id>:<base64 key>")?;
+
+        if id.is_empty() || id.contains('.') {
+            bail!("Invalid master key id {id}");
+        }
+
+        let key = general_purpose::STANDARD
+            .decode(key)
+            .context("Decoding master key")?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow!("Master key {id} must be 32 bytes long"))?;
+
+        Ok(Self {
+            id: id.to_owned(),
+            cipher,
+        })
+    }
+
+    /// Encrypts a data key, the master key id is authenticated along with it.
+    fn wrap(&self, data_key: &Key<Aes256Gcm>) -> Result<String> {
+        encrypt(&self.cipher, data_key, self.id.as_bytes()).context("Wrapping data key")
+    }
+
+    fn unwrap(&self, envelope: &Envelope) -> Result<Key<Aes256Gcm>> {
+        let data_key = decrypt(&self.cipher, envelope.wrapped_key, self.id.as_bytes())
+            .context("Unwrapping data key")?;
+
+        if data_key.len() != 32 {
+            bail!("Invalid data key length");
+        }
+
+        Ok(*Key::<Aes256Gcm>::from_slice(&data_key))
+    }
+}
+
+struct Envelope<'a> {
+    key_id: &'a str,
+    wrapped_key: &'a str,
+    data: &'a str,
+}
+
+impl<'a> Envelope<'a> {
+    fn parse(sealed: &'a str) -> Result<Self> {
+        match sealed.split('.').collect::<Vec<_>>()[..] {
+            [ENVELOPE_VERSION, key_id, wrapped_key, data] => Ok(Self {
+                key_id,
+                wrapped_key,
+                data,
+            }),
+            _ => bail!("The sponsor key is not sealed"),
+        }
+    }
+}
+
+fn seal_bytes(bytes: &[u8], keys: &MasterKeys) -> Result<SponsorKey> {
+    let data_key = Aes256Gcm::generate_key(OsRng);
+    let data = encrypt(&Aes256Gcm::new(&data_key), bytes, b"").context("Encrypting sponsor key")?;
+    let wrapped_key = keys.current.wrap(&data_key)?;
+
+    Ok(SponsorKey(format!(
+        "{ENVELOPE_VERSION}.{}.{wrapped_key}.{data}",
+        keys.current.id
+    )))
+}
+
+/// Encrypts with a random nonce and returns the base64 encoded nonce and ciphertext.
+fn encrypt(cipher: &Aes256Gcm, msg: &[u8], aad: &[u8]) -> Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg, aad })
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    Ok(general_purpose::STANDARD.encode([nonce.as_slice(), ciphertext.as_slice()].concat()))
+}
+
+fn decrypt(cipher: &Aes256Gcm, encoded: &str, aad: &[u8]) -> Result<Vec<u8>> {
+    let bytes = general_purpose::STANDARD
+        .decode(encoded)
+        .context("Decoding ciphertext")?;
+
+    if bytes.len() < NONCE_LENGTH {
+        bail!("Ciphertext is too short");
+    }
+
+    let (nonce, msg) = bytes.split_at(NONCE_LENGTH);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+        .map_err(|_| anyhow!("Decryption failed"))
+}
+
+/// Encrypts the keys of sponsors that were created before the keys were encrypted. Run once
+/// when the encryption is deployed, keys that are already sealed are left alone.
+pub async fn seal_plaintext_sponsor_keys(database: &Database, secrets: &Secrets) -> Result<()> {
+    let keys = MasterKeys::from_secrets(secrets)?;
+    let mut sealed_keys = 0;
+
+    for (id, key) in database.get_sponsor_keys().await.context("Getting sponsor keys")? {
+        if key.is_sealed() {
+            continue;
+        }
+
+        let keypair = Keypair::from_base58_string(key.as_sealed());
+        let sealed = SponsorKey::seal(&keypair, &keys)?;
+
+        database
+            .update_sponsor_key(id, &sealed)
+            .await
+            .context("Updating sponsor key")?;
+        sealed_keys += 1;
+    }
+
+    log::info!("Sealed the keys of {sealed_keys} sponsors");
+
+    Ok(())
+}
+
+/// Re-encrypts the data keys of all sponsors under the current master key. Run with
+/// the new key as the current and the old key as a previous master key, afterwards
+/// the old key can be removed.
+pub async fn rotate_sponsor_keys(database: &Database, secrets: &Secrets) -> Result<()> {
+    let keys = MasterKeys::from_secrets(secrets)?;
+    let mut rotated = 0;
+
+    for (id, key) in database.get_sponsor_keys().await.context("Getting sponsor keys")? {
+        let Some(rewrapped) = key
+            .rewrap(&keys)
+            .with_context(|| format!("Rotating the key of sponsor {id}"))?
+        else {
+            continue;
+        };
+
+        database
+            .update_sponsor_key(id, &rewrapped)
+            .await
+            .context("Updating sponsor key")?;
+        rotated += 1;
+    }
+
+    log::info!("Rotated the keys of {rotated} sponsors to master key {}", keys.current.id);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    const CURRENT: &str = "current:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const PREVIOUS: &str = "previous:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    fn keys(current: &str, previous: Option<&str>) -> MasterKeys {
+        MasterKeys::from_secrets(&Secrets {
+            sponsor_master_key: current.to_owned(),
+            sponsor_previous_master_keys: previous.map(str::to_owned),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn sealed_key_opens() {
+        let keys = keys(CURRENT, None);
+        let keypair = Keypair::new();
+
+        let sealed = SponsorKey::seal(&keypair, &keys).unwrap();
+        assert!(sealed.is_sealed());
+        assert!(!sealed.as_sealed().contains(&keypair.to_base58_string()));
+        assert_eq!(sealed.open(&keys).unwrap().pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn key_is_redacted() {
+        let sealed = SponsorKey::seal(&Keypair::new(), &keys(CURRENT, None)).unwrap();
+
+        assert_eq!(format!("{sealed:?}"), "SponsorKey(<redacted>)");
+        assert_eq!(serde_json::to_string(&sealed).unwrap(), r#""<redacted>""#);
+
+        let deserialized: SponsorKey = serde_json::from_str(r#""<redacted>""#).unwrap();
+        assert!(deserialized.open(&keys(CURRENT, None)).is_err());
+    }
+
+    #[test]
+    fn rotated_key_opens_with_new_master_key_only() {
+        let keypair = Keypair::new();
+        let sealed = SponsorKey::seal(&keypair, &keys(PREVIOUS, None)).unwrap();
+
+        let rotation = keys(CURRENT, Some(PREVIOUS));
+        let rotated = sealed.rewrap(&rotation).unwrap().unwrap();
+        assert!(rotated.rewrap(&rotation).unwrap().is_none());
+
+        assert_eq!(rotated.open(&keys(CURRENT, None)).unwrap().pubkey(), keypair.pubkey());
+        assert!(sealed.open(&keys(CURRENT, None)).is_err());
+    }
+
+    #[test]
+    fn plaintext_key_is_not_sealed() {
+        let plaintext = SponsorKey::from(Keypair::new().to_base58_string());
+
+        assert!(!plaintext.is_sealed());
+        assert!(plaintext.open(&keys(CURRENT, None)).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+//! Tests of the Solana flows against a local `solana-test-validator`.
+//!
+//! They are ignored by default, as they need a running validator:
+//!
+//! ```sh
+//! solana-test-validator --reset --quiet &
+//! cargo test solana::tests -- --ignored
+//! ```
+//!
+//! Set `SOLANA_TEST_RPC_URL` to use a validator that is not on the default port.
+
+use crate::{
+    database::Sponsor,
+    secrets::Secrets,
+    solana::{
+        generate_deposit::generate_deposit,
+        generate_payment::generate_payment,
+        keys::get_or_create_ata,
+        nft::{nft_prize, sign_nft_mint},
+        prize::{Prize, PrizeKind},
+        service::SolanaService,
+        sponsor_key::{MasterKeys, SponsorKey},
+        transfer::{
+            prize_balance, send_prize_transfer, sign_prize_transfer, transfer_status,
+            TransferStatus,
+        },
+        verify::{
+            check_transfer, expected_deposit, land_transaction, ExpectedTransfer,
+            LAUNCH_FEE_LAMPORTS,
+        },
+    },
+};
+use solana_sdk::{
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+
+const DEFAULT_TEST_RPC_URL: &str = "http://127.0.0.1:8899";
+const TEST_MASTER_KEY: &str = "test:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+const DECIMALS: u8 = 6;
+
+fn test_secrets(treasury: &Keypair) -> Secrets {
+    Secrets {
+        rpc_url: std::env::var("SOLANA_TEST_RPC_URL")
+            .unwrap_or_else(|_| DEFAULT_TEST_RPC_URL.to_owned()),
+        treasury_private_key: treasury.to_base58_string(),
+        sponsor_master_key: TEST_MASTER_KEY.to_owned(),
+        solana_commitment: "confirmed".to_owned(),
+        solana_compute_unit_limit: 40000,
+        solana_compute_unit_price: 1000,
+        ..Default::default()
+    }
+}
+
+/// A service with a funded treasury.
+async fn test_service() -> SolanaService {
+    let treasury = Keypair::new();
+    let solana = SolanaService::from_secrets(&test_secrets(&treasury)).unwrap();
+    airdrop(&solana, &treasury.pubkey(), 10 * LAMPORTS_PER_SOL).await;
+
+    solana
+}
+
+async fn airdrop(solana: &SolanaService, receiver: &Pubkey, lamports: u64) {
+    let signature = solana
+        .rpc_client()
+        .request_airdrop(receiver, lamports)
+        .await
+        .unwrap();
+    solana
+        .rpc_client()
+        .poll_for_signature(&signature)
+        .await
+        .unwrap();
+}
+
+async fn send(solana: &SolanaService, instructions: &[Instruction], signers: &[&Keypair]) {
+    let (blockhash, _) = solana.latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&signers[0].pubkey()),
+        signers,
+        blockhash,
+    );
+
+    solana
+        .rpc_client()
+        .send_and_confirm_transaction(&transaction)
+        .await
+        .unwrap();
+}
+
+/// Creates a new mint of the token program, the authority can mint the tokens.
+async fn create_mint(solana: &SolanaService, authority: &Keypair, program: &Pubkey) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = solana
+        .rpc_client()
+        .get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)
+        .await
+        .unwrap();
+
+    send(
+        solana,
+        &[
+            system_instruction::create_account(
+                &authority.pubkey(),
+                &mint.pubkey(),
+                rent,
+                spl_token::state::Mint::LEN as u64,
+                program,
+            ),
+            spl_token_2022::instruction::initialize_mint2(
+                program,
+                &mint.pubkey(),
+                &authority.pubkey(),
+                None,
+                DECIMALS,
+            )
+            .unwrap(),
+        ],
+        &[authority, &mint],
+    )
+    .await;
+
+    mint.pubkey()
+}
+
+async fn mint_tokens(
+    solana: &SolanaService,
+    authority: &Keypair,
+    program: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+) {
+    let token_account = get_associated_token_address_with_program_id(owner, mint, program);
+
+    send(
+        solana,
+        &[
+            create_associated_token_account_idempotent(&authority.pubkey(), owner, mint, program),
+            spl_token_2022::instruction::mint_to(
+                program,
+                mint,
+                &token_account,
+                &authority.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap(),
+        ],
+        &[authority],
+    )
+    .await;
+}
+
+/// A funded wallet that holds `amount` of a new prize of the kind.
+async fn prize_holder(solana: &SolanaService, kind: PrizeKind, amount: u64) -> (Keypair, Prize) {
+    let holder = Keypair::new();
+    airdrop(solana, &holder.pubkey(), 2 * LAMPORTS_PER_SOL).await;
+
+    let Some(program) = kind.token_program() else {
+        return (holder, Prize::Sol);
+    };
+    let mint = create_mint(solana, &holder, &program).await;
+    mint_tokens(solana, &holder, &program, &mint, &holder.pubkey(), amount).await;
+
+    let prize = Prize::resolve(solana, kind, &mint.to_string())
+        .await
+        .unwrap();
+    assert_eq!(prize.decimals(), DECIMALS);
+
+    (holder, prize)
+}
+
+/// The token mint of the prize as stored on a sponsor.
+fn token_mint(prize: &Prize) -> String {
+    match prize {
+        Prize::Sol | Prize::Nft => String::new(),
+        Prize::Token { mint, .. } => mint.to_string(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a local solana-test-validator"]
+async fn transfer_of_every_kind_reaches_receiver() {
+    let solana = test_service().await;
+    let master_keys = MasterKeys::from_secrets(&test_secrets(&Keypair::new())).unwrap();
+
+    for kind in [PrizeKind::Sol, PrizeKind::Spl, PrizeKind::Token2022] {
+        let (sponsor, prize) = prize_holder(&solana, kind, 1_000_000_000).await;
+        let sponsor_key = SponsorKey::seal(&sponsor, &master_keys).unwrap();
+        let receiver = Pubkey::new_unique();
+        let balance = prize_balance(&solana, &sponsor.pubkey(), &prize)
+            .await
+            .unwrap();
+
+        let transfer = sign_prize_transfer(&solana, &sponsor_key, &receiver, &prize, 250_000_000)
+            .await
+            .unwrap();
+        send_prize_transfer(&solana, &transfer).await.unwrap();
+        solana
+            .rpc_client()
+            .poll_for_signature(&transfer.signature)
+            .await
+            .unwrap();
+
+        let status = transfer_status(
+            &solana,
+            &transfer.signature.to_string(),
+            transfer.last_valid_block_height,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, TransferStatus::Confirmed), "{kind:?}");
+
+        // The treasury pays the fees of SOL transfers, so the sender loses exactly the prize
+        assert_eq!(
+            prize_balance(&solana, &receiver, &prize).await.unwrap(),
+            250_000_000,
+            "{kind:?}"
+        );
+        assert_eq!(
+            prize_balance(&solana, &sponsor.pubkey(), &prize)
+                .await
+                .unwrap(),
+            balance - 250_000_000,
+            "{kind:?}"
+        );
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a local solana-test-validator"]
+async fn nft_prize_is_minted_once_to_receiver() {
+    let solana = test_service().await;
+    let master_keys = MasterKeys::from_secrets(&test_secrets(&Keypair::new())).unwrap();
+    let (sponsor, _) = prize_holder(&solana, PrizeKind::Sol, 0).await;
+    let sponsor_key = SponsorKey::seal(&sponsor, &master_keys).unwrap();
+    let receiver = Pubkey::new_unique();
+    let nft = nft_prize(
+        &Sponsor {
+            name: "Test Sponsor".to_owned(),
+            challenge_text: "Sell me this pen".to_owned(),
+            ..Default::default()
+        },
+        "CA123",
+        "Alice",
+        Some(8),
+        "https://cdn.why.fun/CA123.mp4",
+        "http://localhost:3000",
+    );
+
+    let mint = sign_nft_mint(&solana, &sponsor_key, &receiver, &nft)
+        .await
+        .unwrap();
+    send_prize_transfer(&solana, &mint.transfer).await.unwrap();
+    solana
+        .rpc_client()
+        .poll_for_signature(&mint.transfer.signature)
+        .await
+        .unwrap();
+
+    let prize = Prize::resolve(&solana, PrizeKind::Token2022, &mint.mint.to_string())
+        .await
+        .unwrap();
+    assert_eq!(prize.decimals(), 0);
+    assert_eq!(prize_balance(&solana, &receiver, &prize).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a local solana-test-validator"]
+async fn token_account_is_created_once() {
+    let solana = test_service().await;
+    let (holder, prize) = prize_holder(&solana, PrizeKind::Token2022, 1).await;
+    let Prize::Token { program, mint, .. } = prize else {
+        unreachable!()
+    };
+    let wallet = Pubkey::new_unique();
+
+    let created = get_or_create_ata(&solana, &holder, &wallet, &mint, &program)
+        .await
+        .unwrap();
+    let existing = get_or_create_ata(&solana, &holder, &wallet, &mint, &program)
+        .await
+        .unwrap();
+
+    assert_eq!(created, existing);
+    assert_eq!(created, prize.holding_account(&wallet));
+    assert_eq!(prize_balance(&solana, &wallet, &prize).await.unwrap(), 0);
+}
+
+#[tokio::test]
+#[ignore = "needs a local solana-test-validator"]
+async fn mint_has_to_match_the_kind() {
+    let solana = test_service().await;
+    let (_, prize) = prize_holder(&solana, PrizeKind::Token2022, 1).await;
+
+    let spl = Prize::resolve(&solana, PrizeKind::Spl, &token_mint(&prize)).await;
+    assert!(spl.is_err_and(|e| e.is_rejection()));
+}
+
+#[tokio::test]
+#[ignore = "needs a local solana-test-validator"]
+async fn launch_payment_lands_in_treasury() {
+    let solana = test_service().await;
+    let sender = Keypair::new();
+    airdrop(&solana, &sender.pubkey(), 2 * LAMPORTS_PER_SOL).await;
+    let treasury = solana.treasury().pubkey();
+    let treasury_balance = solana.rpc_client().get_balance(&treasury).await.unwrap();
+
+    let mut transaction =
+        generate_payment(&solana, sender.pubkey().to_string(), LAUNCH_FEE_LAMPORTS)
+            .await
+            .unwrap();
+    transaction.partial_sign(&[&sender], transaction.message.recent_blockhash);
+
+    check_transfer(
+        &transaction,
+        &ExpectedTransfer::Lamports {
+            receiver: treasury,
+            amount: LAUNCH_FEE_LAMPORTS,
+        },
+    )
+    .unwrap();
+    land_transaction(&solana, &transaction, &transaction.signatures[0])
+        .await
+        .unwrap();
+
+    // The treasury pays the transaction fee
+    let paid = solana.rpc_client().get_balance(&treasury).await.unwrap() - treasury_balance;
+    assert!(paid > LAUNCH_FEE_LAMPORTS - LAMPORTS_PER_SOL / 1000);
+}
+
+#[tokio::test]
+#[ignore = "needs a local solana-test-validator"]
+async fn deposit_of_every_kind_lands_in_sponsor_wallet() {
+    let solana = test_service().await;
+
+    for kind in [PrizeKind::Sol, PrizeKind::Spl, PrizeKind::Token2022] {
+        let (sender, prize) = prize_holder(&solana, kind, 500_000_000).await;
+        let sponsor = Sponsor {
+            id: 1,
+            public_key: Keypair::new().pubkey().to_string(),
+            token_mint: token_mint(&prize),
+            prize_kind: kind.as_str().to_owned(),
+            original_tokens: 500_000_000,
+            ..Default::default()
+        };
+
+        let mut transaction =
+            generate_deposit(&solana, sender.pubkey().to_string(), &sponsor, 500_000_000)
+                .await
+                .unwrap();
+        transaction.partial_sign(&[&sender], transaction.message.recent_blockhash);
+
+        let expected = expected_deposit(&solana, &sponsor, 500_000_000)
+            .await
+            .unwrap();
+        check_transfer(&transaction, &expected).unwrap();
+        land_transaction(&solana, &transaction, &transaction.signatures[0])
+            .await
+            .unwrap();
+
+        let sponsor_pubkey = sponsor.public_key.parse().unwrap();
+        assert_eq!(
+            prize_balance(&solana, &sponsor_pubkey, &prize)
+                .await
+                .unwrap(),
+            500_000_000,
+            "{kind:?}"
+        );
+    }
+}
@@ -3,84 +3,282 @@ use solana_sdk::{
     transaction::Transaction,
     pubkey::Pubkey
 };
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::commitment_config::CommitmentConfig;
-use spl_token::instruction::transfer;
-use std::str::FromStr;
-use crate::solana::keys::get_or_create_ata;
-use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::system_instruction;
+use spl_token_2022::instruction::{close_account, transfer_checked};
 use solana_sdk::signature::Signature;
-use crate::Secrets;
+use crate::solana::error::SolanaError;
+use crate::solana::prize::{Prize, NFT_MINT_LAMPORTS};
+use crate::solana::service::{SignatureStatus, SolanaService};
+use crate::solana::sponsor_key::SponsorKey;
 
 
-pub async fn transfer_solana_token(
-    secrets: &Secrets,
-    sender_private_key: String, 
-    receiver_pubkey: Pubkey, 
-    token_mint: String,
-    amount: u64
-) -> Result<Signature, Box<dyn std::error::Error>> {
-    log::debug!("Transfer Solana token");
+/// The compute units that creating an associated token account needs on top of a transfer.
+pub const CREATE_ACCOUNT_COMPUTE_UNITS: u32 = 20000;
+
+/// A prize transfer that is signed, but not necessarily sent yet.
+pub struct SignedTransfer {
+    pub transaction: Transaction,
+    pub signature: Signature,
+    /// The transfer can not land anymore once the chain is past this block height.
+    pub last_valid_block_height: u64,
+}
 
-    // Initialize the RPC client
-    let commitment_config = CommitmentConfig::confirmed();
-    let rpc_client = RpcClient::new_with_commitment(&secrets.rpc_url, commitment_config);
+/// Whether a sent transfer landed.
+#[derive(Debug)]
+pub enum TransferStatus {
+    Confirmed,
+    /// The transfer can still land.
+    Pending,
+    /// The transfer landed, but failed.
+    Failed(String),
+    /// The transfer did not land before its blockhash expired and never will.
+    Expired,
+}
+
+/// Signs a transfer of `amount` of the prize from the sender's wallet to the receiver.
+/// The receiver's token account is created in the same transaction if it does not exist yet.
+/// The treasury pays the fees of SOL transfers, so the sender's balance stays equal to the pool.
+/// NFT prizes are minted with [`sign_nft_mint`](crate::solana::nft::sign_nft_mint), for them
+/// this moves the SOL that funds `amount` NFTs.
+pub async fn sign_prize_transfer(
+    solana: &SolanaService,
+    sender_key: &SponsorKey,
+    receiver_pubkey: &Pubkey,
+    prize: &Prize,
+    amount: u64
+) -> Result<SignedTransfer, SolanaError> {
+    log::debug!("Sign Solana {} transfer", prize.kind().as_str());
 
     // Initialize accounts needed for the transfer
-    let sender_keypair: Keypair = Keypair::from_base58_string(&sender_private_key);
+    let sender_keypair: Keypair = solana.open_key(sender_key)?;
 
-    let token_mint: Pubkey = Pubkey::from_str(&token_mint).expect("Invalid token mint address");
+    let mut instructions = Vec::new();
+    let mut extra_units = 0;
 
-    let account_info = rpc_client.get_account(&token_mint).expect("Failed to fetch account info for token mint");
-    let token_program_id = account_info.owner;
+    if let Some(create_receiver_ix) = prize.create_account_instruction(&sender_keypair.pubkey(), receiver_pubkey) {
+        instructions.push(create_receiver_ix);
+        extra_units = CREATE_ACCOUNT_COMPUTE_UNITS;
+    }
 
-    let sender_token_account = get_or_create_ata(
-        &sender_keypair,
-        &sender_keypair.pubkey(), 
-        &token_mint,
-        &token_program_id,
-        &secrets
-    ).await.expect("Failed to get or create sender token account");
+    // Create the transfer instruction
+    instructions.push(prize.transfer_instruction(&sender_keypair.pubkey(), receiver_pubkey, amount)?);
+    instructions.extend(solana.compute_budget(extra_units));
 
-    let receiver_token_account = get_or_create_ata(
-        &sender_keypair,
-        &receiver_pubkey, 
-        &token_mint,
-        &token_program_id,
-        &secrets
-    ).await.expect("Failed to get or create receiver token account");
+    let (latest_blockhash, last_valid_block_height) = solana.latest_blockhash().await?;
 
+    let transaction = match prize {
+        Prize::Sol | Prize::Nft => Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&solana.treasury().pubkey()),
+            &[solana.treasury(), &sender_keypair],
+            latest_blockhash
+        ),
+        Prize::Token { .. } => Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&sender_keypair.pubkey()),
+            &[&sender_keypair],
+            latest_blockhash
+        ),
+    };
 
-    // let amount_to_transfer: u64 = amount * 1000000000;
+    Ok(SignedTransfer {
+        signature: transaction.signatures[0],
+        transaction,
+        last_valid_block_height,
+    })
+}
 
-    // Create the transfer instruction
-    let transfer_ix = transfer(
+/// Sends a signed transfer without waiting for it to land, use [`transfer_status`] to follow it.
+pub async fn send_prize_transfer(solana: &SolanaService, transfer: &SignedTransfer) -> Result<(), SolanaError> {
+    solana
+        .rpc_client()
+        .send_transaction(&transfer.transaction)
+        .await?;
+
+    Ok(())
+}
+
+/// Checks whether the transfer with the given signature landed.
+pub async fn transfer_status(
+    solana: &SolanaService,
+    signature: &str,
+    last_valid_block_height: u64,
+) -> Result<TransferStatus, SolanaError> {
+    let signature: Signature = signature
+        .parse()
+        .map_err(|_| SolanaError::Invalid(format!("Invalid transfer signature {signature}")))?;
+
+    // The block height is fetched first, a transfer that is unknown after the chain passed
+    // its last valid block height can not land anymore
+    let block_height = solana.rpc_client().get_block_height().await?;
+
+    Ok(match solana.signature_status(&signature).await? {
+        SignatureStatus::Landed(Ok(())) => TransferStatus::Confirmed,
+        SignatureStatus::Landed(Err(e)) => TransferStatus::Failed(e.to_string()),
+        SignatureStatus::Processing => TransferStatus::Pending,
+        SignatureStatus::Unknown if block_height > last_valid_block_height => {
+            TransferStatus::Expired
+        }
+        SignatureStatus::Unknown => TransferStatus::Pending,
+    })
+}
+
+/// A signed transfer returning an unclaimed prize to the sponsor, send it with
+/// [`send_prize_transfer`].
+pub struct SignedSweep {
+    pub transfer: SignedTransfer,
+    /// The amount that is returned, the winner may have claimed part of it
+    pub amount: u64,
+}
+
+/// Signs the transfer of the whole prize from the prize keypair back to the sponsor. A token
+/// account is closed afterwards, so the rent the sponsor paid for it is returned as well. The
+/// sponsor pays the fees of tokens and the treasury those of SOL, the prize keypair never held
+/// any SOL of its own.
+/// Returns `None` if the prize keypair holds nothing anymore, which is always the case for NFT
+/// prizes, they are only minted to claimed wallets.
+pub async fn sign_prize_sweep(
+    solana: &SolanaService,
+    sponsor_key: &SponsorKey,
+    prize_keypair: &Keypair,
+    prize: &Prize,
+) -> Result<Option<SignedSweep>, SolanaError> {
+    log::debug!("Sign Solana sweep of prize account {}", prize_keypair.pubkey());
+
+    if *prize == Prize::Nft {
+        return Ok(None);
+    }
+
+    let sponsor_keypair: Keypair = solana.open_key(sponsor_key)?;
+
+    let Prize::Token { program: token_program_id, mint: token_mint, decimals } = *prize else {
+        let amount = prize_balance(solana, &prize_keypair.pubkey(), prize).await?;
+        if amount == 0 {
+            return Ok(None);
+        }
+
+        let mut instructions = vec![system_instruction::transfer(
+            &prize_keypair.pubkey(),
+            &sponsor_keypair.pubkey(),
+            amount,
+        )];
+        instructions.extend(solana.compute_budget(0));
+
+        let (latest_blockhash, last_valid_block_height) = solana.latest_blockhash().await?;
+
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&solana.treasury().pubkey()),
+            &[solana.treasury(), prize_keypair],
+            latest_blockhash
+        );
+
+        return Ok(Some(SignedSweep {
+            transfer: SignedTransfer {
+                signature: transaction.signatures[0],
+                transaction,
+                last_valid_block_height,
+            },
+            amount,
+        }));
+    };
+
+    let prize_token_account = prize.holding_account(&prize_keypair.pubkey());
+
+    if solana
+        .rpc_client()
+        .get_account_with_commitment(&prize_token_account, solana.commitment())
+        .await?
+        .value
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    let amount = prize_balance(solana, &prize_keypair.pubkey(), prize).await?;
+
+    let sponsor_token_account = prize.holding_account(&sponsor_keypair.pubkey());
+
+    let mut instructions = Vec::new();
+
+    if amount > 0 {
+        instructions.push(transfer_checked(
+            &token_program_id,
+            &prize_token_account,
+            &token_mint,
+            &sponsor_token_account,
+            &prize_keypair.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?);
+    }
+
+    instructions.push(close_account(
         &token_program_id,
-        &sender_token_account,
-        &receiver_token_account,
-        &sender_keypair.pubkey(),
-        &[&sender_keypair.pubkey()],
-        amount
-        // amount_to_transfer,
-    )
-    .expect("Failed to create transfer instruction");
-    
-
-
-    let modify_compute_units = ComputeBudgetInstruction::set_compute_unit_limit(40000);
-    let set_priority_fee = ComputeBudgetInstruction::set_compute_unit_price(1000);
-
-    let latest_blockhash = rpc_client.get_latest_blockhash()?;
-    
+        &prize_token_account,
+        &sponsor_keypair.pubkey(),
+        &prize_keypair.pubkey(),
+        &[],
+    )?);
+
+    instructions.extend(solana.compute_budget(0));
+
+    let (latest_blockhash, last_valid_block_height) = solana.latest_blockhash().await?;
+
     let transaction = Transaction::new_signed_with_payer(
-        &[transfer_ix, modify_compute_units, set_priority_fee], 
-        Some(&sender_keypair.pubkey()),
-        &[sender_keypair],
+        &instructions,
+        Some(&sponsor_keypair.pubkey()),
+        &[&sponsor_keypair, prize_keypair],
         latest_blockhash
     );
-    
-    let signature = rpc_client.send_and_confirm_transaction(&transaction).expect("Failed to send and confirm transaction");
 
+    Ok(Some(SignedSweep {
+        transfer: SignedTransfer {
+            signature: transaction.signatures[0],
+            transaction,
+            last_valid_block_height,
+        },
+        amount,
+    }))
+}
+
+/// Gets the amount of the prize that the owner holds, in lamports for SOL, in the number of NFTs
+/// its SOL can fund for NFTs and in tokens of the mint otherwise.
+/// Returns 0 if the owner has no token account.
+pub async fn prize_balance(solana: &SolanaService, owner: &Pubkey, prize: &Prize) -> Result<u64, SolanaError> {
+    if let Prize::Sol | Prize::Nft = prize {
+        let lamports = solana
+            .rpc_client()
+            .get_balance_with_commitment(owner, solana.commitment())
+            .await?
+            .value;
+
+        return Ok(match prize {
+            Prize::Nft => lamports / NFT_MINT_LAMPORTS,
+            _ => lamports,
+        });
+    }
+
+    let token_account = prize.holding_account(owner);
+
+    if solana
+        .rpc_client()
+        .get_account_with_commitment(&token_account, solana.commitment())
+        .await?
+        .value
+        .is_none()
+    {
+        return Ok(0);
+    }
+
+    let balance = solana
+        .rpc_client()
+        .get_token_account_balance(&token_account)
+        .await?;
 
-    Ok(signature)
+    balance
+        .amount
+        .parse()
+        .map_err(|_| SolanaError::Invalid(format!("Invalid token balance {}", balance.amount)))
 }
@@ -0,0 +1,443 @@
+use crate::{
+    database::{Database, Sponsor},
+    solana::{
+        error::{parse_pubkey, SolanaError},
+        prize::Prize,
+        service::{SignatureStatus, SolanaService},
+    },
+};
+use solana_sdk::{
+    compute_budget, instruction::CompiledInstruction, pubkey::Pubkey, signature::Signature,
+    signer::Signer, system_instruction::SystemInstruction, system_program,
+    transaction::Transaction,
+};
+use spl_token::instruction::TokenInstruction;
+
+/// The price of launching a sponsor, in lamports.
+pub const LAUNCH_FEE_LAMPORTS: u64 = 1_000_000_000;
+
+/// The kind of the processed signature of a launch payment.
+pub const PAYMENT_KIND: &str = "payment";
+
+/// The kind of the processed signature of the initial deposit of a sponsor.
+pub const DEPOSIT_KIND: &str = "deposit";
+
+/// The kind of the processed signature of a deposit that tops up an active sponsor.
+pub const TOP_UP_KIND: &str = "top_up";
+
+/// The transfer a payment or deposit transaction has to make.
+#[derive(Debug)]
+pub enum ExpectedTransfer {
+    /// A system transfer of exactly `amount` lamports to the receiver.
+    Lamports { receiver: Pubkey, amount: u64 },
+    /// A transfer of exactly `amount` tokens of the mint to the receiver's token account.
+    /// Unchecked transfers are only accepted from the token program.
+    Tokens {
+        token_program: Pubkey,
+        mint: Pubkey,
+        decimals: u8,
+        receiver_token_account: Pubkey,
+        amount: u64,
+    },
+}
+
+/// Verifies that the transaction pays the launch fee to the treasury, sends it and waits
+/// until it is confirmed.
+pub async fn verify_payment(
+    solana: &SolanaService,
+    database: &Database,
+    transaction: Transaction,
+) -> Result<Signature, SolanaError> {
+    check_transfer(
+        &transaction,
+        &ExpectedTransfer::Lamports {
+            receiver: solana.treasury().pubkey(),
+            amount: LAUNCH_FEE_LAMPORTS,
+        },
+    )?;
+
+    submit_transfer(
+        solana,
+        database,
+        &transaction,
+        PAYMENT_KIND,
+        None,
+        LAUNCH_FEE_LAMPORTS,
+    )
+    .await
+}
+
+/// Verifies that the transaction deposits `amount` of the sponsor's prize to the sponsor's
+/// wallet, sends it and waits until it is confirmed.
+pub async fn verify_deposit(
+    solana: &SolanaService,
+    database: &Database,
+    sponsor: &Sponsor,
+    kind: &str,
+    amount: u64,
+    transaction: Transaction,
+) -> Result<Signature, SolanaError> {
+    check_transfer(
+        &transaction,
+        &expected_deposit(solana, sponsor, amount).await?,
+    )?;
+
+    submit_transfer(
+        solana,
+        database,
+        &transaction,
+        kind,
+        Some(sponsor.id),
+        amount,
+    )
+    .await
+}
+
+/// The transfer of `amount` of the sponsor's prize to the sponsor's wallet.
+pub async fn expected_deposit(
+    solana: &SolanaService,
+    sponsor: &Sponsor,
+    amount: u64,
+) -> Result<ExpectedTransfer, SolanaError> {
+    let sponsor_pubkey = parse_pubkey(&sponsor.public_key, "sponsor")?;
+    let prize = Prize::of_sponsor(solana, sponsor).await?;
+
+    Ok(prize.expected_transfer(&sponsor_pubkey, amount))
+}
+
+/// Checks that the transaction is fully signed and makes exactly the expected transfer.
+/// Besides the transfer, only compute budget instructions are allowed.
+pub fn check_transfer(
+    transaction: &Transaction,
+    expected: &ExpectedTransfer,
+) -> Result<(), SolanaError> {
+    let reject = |reason: String| Err(SolanaError::Rejected(reason));
+
+    if transaction.signatures.is_empty()
+        || transaction.signatures.len()
+            != usize::from(transaction.message.header.num_required_signatures)
+        || !transaction.is_signed()
+    {
+        return reject("The transaction is not fully signed".to_owned());
+    }
+
+    if let Err(e) = transaction.verify() {
+        return reject(format!("The transaction signatures are invalid: {e}"));
+    }
+
+    let mut transfers = 0;
+    for instruction in &transaction.message.instructions {
+        let Some(program_id) = transaction
+            .message
+            .account_keys
+            .get(usize::from(instruction.program_id_index))
+        else {
+            return reject("The instruction has no program".to_owned());
+        };
+
+        if *program_id == compute_budget::id() {
+            continue;
+        }
+
+        if !is_expected_transfer(transaction, instruction, program_id, expected) {
+            return reject(format!(
+                "The transaction contains an unexpected instruction of program {program_id}"
+            ));
+        }
+
+        transfers += 1;
+    }
+
+    if transfers != 1 {
+        return reject(format!(
+            "The transaction has to make exactly one transfer, it makes {transfers}"
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_expected_transfer(
+    transaction: &Transaction,
+    instruction: &CompiledInstruction,
+    program_id: &Pubkey,
+    expected: &ExpectedTransfer,
+) -> bool {
+    let account = |position: usize| {
+        instruction
+            .accounts
+            .get(position)
+            .and_then(|index| transaction.message.account_keys.get(usize::from(*index)))
+    };
+
+    match expected {
+        ExpectedTransfer::Lamports { receiver, amount } => {
+            *program_id == system_program::id()
+                && matches!(
+                    bincode::deserialize::<SystemInstruction>(&instruction.data),
+                    Ok(SystemInstruction::Transfer { lamports }) if lamports == *amount
+                )
+                && account(1) == Some(receiver)
+        }
+        ExpectedTransfer::Tokens {
+            token_program,
+            mint,
+            decimals,
+            receiver_token_account,
+            amount,
+        } => {
+            if program_id != token_program {
+                return false;
+            }
+
+            match TokenInstruction::unpack(&instruction.data) {
+                Ok(TokenInstruction::Transfer { amount: sent }) => {
+                    *token_program == spl_token::id()
+                        && sent == *amount
+                        && account(1) == Some(receiver_token_account)
+                }
+                Ok(TokenInstruction::TransferChecked {
+                    amount: sent,
+                    decimals: sent_decimals,
+                }) => {
+                    sent == *amount
+                        && sent_decimals == *decimals
+                        && account(1) == Some(mint)
+                        && account(2) == Some(receiver_token_account)
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Sends the verified transaction and waits until it is confirmed. The signature is
+/// reserved first, so the same transaction is never accepted twice.
+async fn submit_transfer(
+    solana: &SolanaService,
+    database: &Database,
+    transaction: &Transaction,
+    kind: &str,
+    sponsor_id: Option<i32>,
+    amount: u64,
+) -> Result<Signature, SolanaError> {
+    let signature = transaction.signatures[0];
+    let amount = i64::try_from(amount)
+        .map_err(|_| SolanaError::Invalid(format!("Invalid transfer amount {amount}")))?;
+
+    let reserved = database
+        .reserve_signature(&signature.to_string(), kind, sponsor_id, amount)
+        .await
+        .map_err(SolanaError::Database)?;
+    if !reserved {
+        return Err(SolanaError::Replayed(signature));
+    }
+
+    if let Err(e) = land_transaction(solana, transaction, &signature).await {
+        database
+            .release_signature(&signature.to_string())
+            .await
+            .map_err(SolanaError::Database)?;
+        return Err(e);
+    }
+
+    Ok(signature)
+}
+
+/// Sends the transaction and waits until it is confirmed.
+pub(in crate::solana) async fn land_transaction(
+    solana: &SolanaService,
+    transaction: &Transaction,
+    signature: &Signature,
+) -> Result<(), SolanaError> {
+    // The transaction may have landed after an earlier request timed out waiting for it,
+    // sending it again would fail
+    match solana.signature_status(signature).await? {
+        SignatureStatus::Landed(Ok(())) => return Ok(()),
+        SignatureStatus::Landed(Err(e)) => return Err(SolanaError::Failed(e)),
+        SignatureStatus::Processing | SignatureStatus::Unknown => {}
+    }
+
+    solana
+        .rpc_client()
+        .send_and_confirm_transaction(transaction)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        hash::Hash,
+        instruction::Instruction,
+        signature::{Keypair, Signer},
+        system_instruction,
+    };
+    use spl_associated_token_account::get_associated_token_address_with_program_id;
+
+    fn signed(instructions: &[Instruction], payer: &Keypair) -> Transaction {
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            Hash::new_unique(),
+        )
+    }
+
+    fn launch_fee(treasury: Pubkey) -> ExpectedTransfer {
+        ExpectedTransfer::Lamports {
+            receiver: treasury,
+            amount: LAUNCH_FEE_LAMPORTS,
+        }
+    }
+
+    #[test]
+    fn accepts_launch_fee_payment() {
+        let sender = Keypair::new();
+        let treasury = Pubkey::new_unique();
+        let transaction = signed(
+            &[
+                system_instruction::transfer(&sender.pubkey(), &treasury, LAUNCH_FEE_LAMPORTS),
+                compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(40000),
+            ],
+            &sender,
+        );
+
+        assert!(check_transfer(&transaction, &launch_fee(treasury)).is_ok());
+    }
+
+    #[test]
+    fn rejects_payment_of_other_amount_or_receiver() {
+        let sender = Keypair::new();
+        let treasury = Pubkey::new_unique();
+
+        let too_little = signed(
+            &[system_instruction::transfer(&sender.pubkey(), &treasury, 1)],
+            &sender,
+        );
+        assert!(check_transfer(&too_little, &launch_fee(treasury)).is_err());
+
+        let other_receiver = signed(
+            &[system_instruction::transfer(
+                &sender.pubkey(),
+                &Pubkey::new_unique(),
+                LAUNCH_FEE_LAMPORTS,
+            )],
+            &sender,
+        );
+        assert!(check_transfer(&other_receiver, &launch_fee(treasury)).is_err());
+    }
+
+    #[test]
+    fn rejects_additional_instructions() {
+        let sender = Keypair::new();
+        let treasury = Pubkey::new_unique();
+        let transaction = signed(
+            &[
+                system_instruction::transfer(&sender.pubkey(), &treasury, LAUNCH_FEE_LAMPORTS),
+                system_instruction::transfer(&sender.pubkey(), &treasury, LAUNCH_FEE_LAMPORTS),
+            ],
+            &sender,
+        );
+
+        assert!(check_transfer(&transaction, &launch_fee(treasury)).is_err());
+    }
+
+    #[test]
+    fn rejects_partially_signed_transaction() {
+        let sender = Keypair::new();
+        let fee_payer = Keypair::new();
+        let treasury = Pubkey::new_unique();
+        let mut transaction = Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                &sender.pubkey(),
+                &treasury,
+                LAUNCH_FEE_LAMPORTS,
+            )],
+            Some(&fee_payer.pubkey()),
+        );
+        transaction.partial_sign(&[&fee_payer], Hash::new_unique());
+
+        assert!(check_transfer(&transaction, &launch_fee(treasury)).is_err());
+    }
+
+    #[test]
+    fn accepts_deposit_only_to_sponsor_token_account() {
+        let sender = Keypair::new();
+        let sponsor = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let sponsor_token_account =
+            get_associated_token_address_with_program_id(&sponsor, &mint, &spl_token::id());
+        let expected = ExpectedTransfer::Tokens {
+            token_program: spl_token::id(),
+            mint,
+            decimals: 6,
+            receiver_token_account: sponsor_token_account,
+            amount: 500,
+        };
+
+        let deposit = |receiver: &Pubkey| {
+            let transfer = spl_token::instruction::transfer(
+                &spl_token::id(),
+                &Pubkey::new_unique(),
+                receiver,
+                &sender.pubkey(),
+                &[],
+                500,
+            )
+            .unwrap();
+            signed(&[transfer], &sender)
+        };
+
+        assert!(check_transfer(&deposit(&sponsor_token_account), &expected).is_ok());
+        assert!(check_transfer(&deposit(&Pubkey::new_unique()), &expected).is_err());
+    }
+
+    #[test]
+    fn rejects_unchecked_token_2022_deposit_and_other_decimals() {
+        let sender = Keypair::new();
+        let sponsor = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let program = spl_token_2022::id();
+        let sponsor_token_account =
+            get_associated_token_address_with_program_id(&sponsor, &mint, &program);
+        let expected = ExpectedTransfer::Tokens {
+            token_program: program,
+            mint,
+            decimals: 6,
+            receiver_token_account: sponsor_token_account,
+            amount: 500,
+        };
+
+        let mut unchecked = spl_token::instruction::transfer(
+            &spl_token::id(),
+            &Pubkey::new_unique(),
+            &sponsor_token_account,
+            &sender.pubkey(),
+            &[],
+            500,
+        )
+        .unwrap();
+        unchecked.program_id = program;
+        assert!(check_transfer(&signed(&[unchecked], &sender), &expected).is_err());
+
+        let checked = |decimals| {
+            let transfer = spl_token_2022::instruction::transfer_checked(
+                &program,
+                &Pubkey::new_unique(),
+                &mint,
+                &sponsor_token_account,
+                &sender.pubkey(),
+                &[],
+                500,
+                decimals,
+            )
+            .unwrap();
+            signed(&[transfer], &sender)
+        };
+        assert!(check_transfer(&checked(6), &expected).is_ok());
+        assert!(check_transfer(&checked(9), &expected).is_err());
+    }
+}
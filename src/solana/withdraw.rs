@@ -0,0 +1,75 @@
+use crate::{
+    database::Sponsor,
+    solana::{
+        error::{parse_pubkey, SolanaError},
+        prize::Prize,
+        service::SolanaService,
+        transfer::{prize_balance, sign_prize_transfer},
+        verify::land_transaction,
+    },
+};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+/// The result of returning the pool of a sponsor to its owner.
+pub struct Withdrawal {
+    /// The signature of the transfer, `None` if there was nothing to withdraw
+    pub signature: Option<Signature>,
+    /// The number of tokens, or lamports for SOL, that were sent to the owner
+    pub amount: u64,
+    /// The number of tokens that are left in the sponsor's wallet for unpaid prizes
+    pub remaining: u64,
+}
+
+/// Sends every token of the sponsor's wallet to the owner, except the `reserved` tokens
+/// that are still owed to winners, and waits until the transfer is confirmed.
+pub async fn withdraw_sponsor_tokens(
+    solana: &SolanaService,
+    sponsor: &Sponsor,
+    owner: &Pubkey,
+    reserved: u64,
+) -> Result<Withdrawal, SolanaError> {
+    let sponsor_pubkey = parse_pubkey(&sponsor.public_key, "sponsor")?;
+    let prize = Prize::of_sponsor(solana, sponsor).await?;
+    let balance = prize_balance(solana, &sponsor_pubkey, &prize).await?;
+
+    let amount = withdrawable_tokens(balance, reserved);
+    if amount == 0 {
+        return Ok(Withdrawal {
+            signature: None,
+            amount,
+            remaining: balance,
+        });
+    }
+
+    let transfer = sign_prize_transfer(solana, &sponsor.private_key, owner, &prize, amount).await?;
+
+    land_transaction(solana, &transfer.transaction, &transfer.signature).await?;
+
+    Ok(Withdrawal {
+        signature: Some(transfer.signature),
+        amount,
+        remaining: balance - amount,
+    })
+}
+
+/// The tokens of the balance that are not owed to winners.
+fn withdrawable_tokens(balance: u64, reserved: u64) -> u64 {
+    balance.saturating_sub(reserved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_reserved_tokens() {
+        assert_eq!(withdrawable_tokens(1000, 300), 700);
+        assert_eq!(withdrawable_tokens(1000, 0), 1000);
+    }
+
+    #[test]
+    fn withdraws_nothing_when_balance_is_owed() {
+        assert_eq!(withdrawable_tokens(300, 300), 0);
+        assert_eq!(withdrawable_tokens(200, 300), 0);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{database::Sponsor, game::reward::jackpot_text};
+use std::fmt;
+
+/// A value a text can use, written as `{name}` in the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    Name,
+    Sponsor,
+    Duration,
+    Reward,
+    Tier,
+    Link,
+    VideoUrl,
+    Attempts,
+    Jackpot,
+}
+
+impl Variable {
+    pub const ALL: [Variable; 9] = [
+        Variable::Name,
+        Variable::Sponsor,
+        Variable::Duration,
+        Variable::Reward,
+        Variable::Tier,
+        Variable::Link,
+        Variable::VideoUrl,
+        Variable::Attempts,
+        Variable::Jackpot,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Variable::Name => "name",
+            Variable::Sponsor => "sponsor",
+            Variable::Duration => "duration",
+            Variable::Reward => "reward",
+            Variable::Tier => "tier",
+            Variable::Link => "link",
+            Variable::VideoUrl => "video_url",
+            Variable::Attempts => "attempts",
+            Variable::Jackpot => "jackpot",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Variable> {
+        Variable::ALL
+            .into_iter()
+            .find(|variable| variable.as_str() == name)
+    }
+}
+
+/// The values a text is rendered with. Variables without a value are left in the text as
+/// they are written.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    /// The name the caller gave
+    pub name: Option<String>,
+    /// The name of the sponsor
+    pub sponsor: Option<String>,
+    /// The seconds the caller has for the challenge
+    pub duration: Option<i32>,
+    /// Everything the winner is paid, in whole tokens
+    pub reward: Option<String>,
+    /// The name of the reward tier the winner reached
+    pub tier: Option<String>,
+    /// The link the winner claims the prize with
+    pub link: Option<String>,
+    pub video_url: Option<String>,
+    /// The attempts a caller has per day
+    pub attempts: Option<i64>,
+    /// The jackpot in whole tokens
+    pub jackpot: Option<String>,
+}
+
+impl TemplateContext {
+    /// The values every text of a call of the sponsor can use.
+    pub fn for_sponsor(sponsor: &Sponsor) -> Self {
+        TemplateContext {
+            sponsor: Some(sponsor.name.clone()),
+            duration: Some(sponsor.challenge_time),
+            jackpot: Some(jackpot_text(sponsor)),
+            ..Default::default()
+        }
+    }
+
+    fn value(&self, variable: Variable) -> Option<String> {
+        match variable {
+            Variable::Name => self.name.clone(),
+            Variable::Sponsor => self.sponsor.clone(),
+            Variable::Duration => self.duration.map(|duration| duration.to_string()),
+            Variable::Reward => self.reward.clone(),
+            Variable::Tier => self.tier.clone(),
+            Variable::Link => self.link.clone(),
+            Variable::VideoUrl => self.video_url.clone(),
+            Variable::Attempts => self.attempts.map(|attempts| attempts.to_string()),
+            Variable::Jackpot => self.jackpot.clone(),
+        }
+    }
+}
+
+/// A placeholder a text can not use.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    /// The placeholder is no variable, e.g. a typo like `{nmae}`.
+    Unknown(String),
+    /// The variable has no value where the text is used, e.g. `{link}` in the greeting.
+    Unavailable(Variable),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Unknown(placeholder) => write!(f, "unknown placeholder {placeholder}"),
+            TemplateError::Unavailable(variable) => {
+                write!(f, "placeholder {{{}}} is not available", variable.as_str())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    /// A placeholder as it is written in the text, with its variable if it is one
+    Placeholder {
+        written: String,
+        variable: Option<Variable>,
+    },
+}
+
+/// A text with placeholders, parsed once and rendered with the values of a call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses a text with placeholders like `{name}`. Braces around anything but a word are
+    /// kept as they are.
+    pub fn parse(text: &str) -> Self {
+        Self::parse_with(text, false)
+    }
+
+    /// Parses a text of the config, which can also write the variables like `$attempts`.
+    /// Anything else after a `$`, like an amount, is kept as it is.
+    pub fn parse_config(text: &str) -> Self {
+        Self::parse_with(text, true)
+    }
+
+    fn parse_with(text: &str, dollar_variables: bool) -> Self {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find(|c| c == '{' || (dollar_variables && c == '$')) {
+            literal.push_str(&rest[..start]);
+            let marker = &rest[start..start + 1];
+            let after = &rest[start + 1..];
+            let word_length = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            let word = &after[..word_length];
+
+            let placeholder = match marker {
+                "{" if !word.is_empty() && after[word_length..].starts_with('}') => Some((
+                    format!("{{{word}}}"),
+                    Variable::from_name(word),
+                    word_length + 2,
+                )),
+                "$" => Variable::from_name(word)
+                    .map(|variable| (format!("${word}"), Some(variable), word_length + 1)),
+                _ => None,
+            };
+
+            match placeholder {
+                Some((written, variable, length)) => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder { written, variable });
+                    rest = &rest[start + length..];
+                }
+                None => {
+                    literal.push_str(marker);
+                    rest = after;
+                }
+            }
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Text(literal));
+        }
+
+        Template { segments }
+    }
+
+    /// Checks that the text only uses the variables that are available where it is used, so
+    /// a text with a typo or a missing value is caught when it is set and not when a caller
+    /// hears it.
+    pub fn check(&self, available: &[Variable]) -> Result<(), TemplateError> {
+        for segment in &self.segments {
+            match segment {
+                Segment::Placeholder {
+                    written,
+                    variable: None,
+                } => return Err(TemplateError::Unknown(written.clone())),
+                Segment::Placeholder {
+                    variable: Some(variable),
+                    ..
+                } if !available.contains(variable) => {
+                    return Err(TemplateError::Unavailable(*variable))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The text with the values of the context, as it is sent in a message.
+    pub fn render(&self, context: &TemplateContext) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Placeholder { written, variable } => variable
+                    .and_then(|variable| context.value(variable))
+                    .unwrap_or_else(|| written.clone()),
+            })
+            .collect()
+    }
+
+    /// The text with the values of the context, escaped to be spoken in TwiML.
+    pub fn render_speech(&self, context: &TemplateContext) -> String {
+        escape_speech(&self.render(context))
+    }
+}
+
+/// Escapes a text to be spoken by a `Say`, whose text is XML and can be SSML. Caller names
+/// and generated responses can contain anything, so everything spoken has to go through this.
+pub fn escape_speech(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            name: Some("Alice".to_owned()),
+            duration: Some(30),
+            attempts: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_the_values_of_the_context() {
+        let template = Template::parse("Hi {name}, you have {duration} seconds.");
+
+        assert_eq!(
+            template.render(&context()),
+            "Hi Alice, you have 30 seconds."
+        );
+    }
+
+    #[test]
+    fn keeps_placeholders_without_a_value() {
+        let template = Template::parse("{name} won {reward} and {nmae}");
+
+        assert_eq!(template.render(&context()), "Alice won {reward} and {nmae}");
+    }
+
+    #[test]
+    fn keeps_braces_that_are_no_placeholders() {
+        let template = Template::parse("{ } {see you} {name");
+
+        assert_eq!(template.render(&context()), "{ } {see you} {name");
+        assert_eq!(template.check(&[]), Ok(()));
+    }
+
+    #[test]
+    fn config_texts_can_use_dollar_variables() {
+        let template = Template::parse_config("Only $attempts calls a day, $5 and {name}.");
+
+        assert_eq!(
+            template.render(&context()),
+            "Only 3 calls a day, $5 and Alice."
+        );
+        assert_eq!(
+            Template::parse("Only $attempts calls").render(&context()),
+            "Only $attempts calls"
+        );
+    }
+
+    #[test]
+    fn detects_unknown_and_unavailable_placeholders() {
+        let available = [Variable::Name, Variable::Duration];
+
+        assert_eq!(Template::parse("Hi {name}").check(&available), Ok(()));
+        assert_eq!(
+            Template::parse("Hi {nmae}").check(&available),
+            Err(TemplateError::Unknown("{nmae}".to_owned()))
+        );
+        assert_eq!(
+            Template::parse("Claim it: {link}").check(&available),
+            Err(TemplateError::Unavailable(Variable::Link))
+        );
+    }
+
+    #[test]
+    fn escapes_speech() {
+        let context = TemplateContext {
+            name: Some("<Tom & Jerry>".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Template::parse("Hi {name}, it's on").render_speech(&context),
+            "Hi &lt;Tom &amp; Jerry&gt;, it&apos;s on"
+        );
+    }
+}
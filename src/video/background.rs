@@ -85,7 +85,7 @@ pub async fn generate_background_video(
         )));
 
         // Write the background file to the CONCAT file
-        let duration = (cached_message.timespan.end - cached_message.timespan.start).as_secs_f32();
+        let duration = (cached_message.timespan.end - cached_message.timespan.start).num_milliseconds() as f32 / 1000.0;
         let content = format!("file 'background_{index}.jpeg'\nduration {duration}\n");
         concat_content.push_str(&content);
     }
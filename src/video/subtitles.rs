@@ -13,11 +13,11 @@ pub fn generate_subtitles_srt(
     let mut subtitles = Subtitles::new();
     for (index, cached_message) in cached_messages.iter().enumerate() {
         let start = Timestamp::from_milliseconds(
-            (cached_message.timespan.start - cached_call.start).as_millis() as _,
+            (cached_message.timespan.start - cached_call.start).num_milliseconds() as _,
         );
 
         let end = Timestamp::from_milliseconds(
-            (cached_message.timespan.end - cached_call.start).as_millis() as _,
+            (cached_message.timespan.end - cached_call.start).num_milliseconds() as _,
         );
 
         subtitles.push(Subtitle::new(
@@ -0,0 +1,57 @@
+const form = document.getElementById("claim");
+const walletInput = document.getElementById("wallet");
+const connectButton = document.getElementById("connect");
+const submitButton = document.getElementById("submit");
+const status = document.getElementById("status");
+
+// Any wallet that injects the standard Solana provider, like Phantom or Solflare
+function getProvider() {
+	return window.phantom?.solana ?? window.solana;
+}
+
+connectButton.addEventListener("click", async () => {
+	const provider = getProvider();
+	if (!provider) {
+		status.textContent = "Please install a Solana wallet to claim your prize";
+		return;
+	}
+
+	try {
+		const { publicKey } = await provider.connect();
+		walletInput.value = publicKey.toString();
+		submitButton.disabled = false;
+	} catch (error) {
+		status.textContent = "Could not connect to your wallet";
+	}
+});
+
+form.addEventListener("submit", async (event) => {
+	event.preventDefault();
+	submitButton.disabled = true;
+
+	try {
+		// Signing the claim message proves that the wallet belongs to the winner
+		const message = new TextEncoder().encode(form.dataset.message);
+		const { signature } = await getProvider().signMessage(message, "utf8");
+
+		const response = await fetch(window.location.href, {
+			method: "POST",
+			headers: { "Content-Type": "application/json" },
+			body: JSON.stringify({
+				wallet: walletInput.value,
+				signature: btoa(String.fromCharCode(...signature)),
+			}),
+		});
+
+		if (!response.ok) {
+			status.textContent = await response.text();
+			return;
+		}
+
+		form.remove();
+		status.textContent = "Your prize is on its way to " + walletInput.value;
+	} catch (error) {
+		status.textContent = "Could not claim your prize, please try again";
+		submitButton.disabled = false;
+	}
+});
@@ -15,7 +15,7 @@ impl<'a> OutboundCall<'a> {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum CallStatus {
     Queued,
@@ -28,6 +28,29 @@ pub enum CallStatus {
     NoAnswer,
 }
 
+impl CallStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CallStatus::Queued => "queued",
+            CallStatus::Ringing => "ringing",
+            CallStatus::InProgress => "in-progress",
+            CallStatus::Canceled => "canceled",
+            CallStatus::Completed => "completed",
+            CallStatus::Failed => "failed",
+            CallStatus::Busy => "busy",
+            CallStatus::NoAnswer => "no-answer",
+        }
+    }
+
+    /// Whether the call has ended, no further status changes will follow.
+    pub fn is_final(&self) -> bool {
+        !matches!(
+            self,
+            CallStatus::Queued | CallStatus::Ringing | CallStatus::InProgress
+        )
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Call {
     pub from: String,
@@ -36,6 +59,12 @@ pub struct Call {
     pub status: CallStatus,
     pub speech_confidence: Option<f64>,
     pub speech_result: Option<String>,
+    /// Only sent to status callbacks of completed calls, in seconds.
+    #[serde(default)]
+    pub call_duration: Option<u32>,
+    /// Only sent to status callbacks of calls that ended with a SIP response.
+    #[serde(default)]
+    pub sip_response_code: Option<u16>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -54,12 +83,27 @@ impl Client {
         self.send_request(Method::POST, "Calls", &opts).await
     }
 
+    pub async fn get_call(&self, sid: &str) -> Result<Call, TwilioError> {
+        self.send_request(Method::GET, &format!("Calls/{sid}"), &[])
+            .await
+    }
+
     pub async fn update_call_url(&self, sid: &str, url: &str) -> Result<Call, TwilioError> {
         let opts = [("Url", url)];
         self.send_request(Method::POST, &format!("Calls/{sid}"), &opts)
             .await
     }
 
+    pub async fn update_call_status_callback(
+        &self,
+        sid: &str,
+        callback: &str,
+    ) -> Result<Call, TwilioError> {
+        let opts = [("StatusCallback", callback), ("StatusCallbackMethod", "POST")];
+        self.send_request(Method::POST, &format!("Calls/{sid}"), &opts)
+            .await
+    }
+
     pub async fn record_call(&self, sid: &str, callback: &str) -> Result<Recording, TwilioError> {
         let opts = [("RecordingStatusCallback", callback)];
         self.send_request(Method::POST, &format!("Calls/{sid}/Recordings.json"), &opts)
@@ -112,6 +156,8 @@ impl FromMap for Call {
 
         let speech_confidence = m.remove("Confidence").and_then(|c| c.parse().ok());
         let speech_result = m.remove("SpeechResult");
+        let call_duration = m.remove("CallDuration").and_then(|d| d.parse().ok());
+        let sip_response_code = m.remove("SipResponseCode").and_then(|c| c.parse().ok());
 
         Ok(Box::new(Call {
             from,
@@ -120,6 +166,8 @@ impl FromMap for Call {
             status: stat,
             speech_confidence,
             speech_result,
+            call_duration,
+            sip_response_code,
         }))
     }
 }
@@ -3,7 +3,7 @@ mod message;
 pub mod twiml;
 mod webhook;
 
-pub use call::{Call, OutboundCall, Recording};
+pub use call::{Call, CallStatus, OutboundCall, Recording};
 use headers::HeaderMapExt;
 use hyper::body::{Body, Bytes};
 use hyper::Response;
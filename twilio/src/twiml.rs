@@ -1,4 +1,5 @@
 mod gather;
+mod hangup;
 mod message;
 mod play;
 mod record;
@@ -8,6 +9,7 @@ mod say;
 mod sms;
 
 pub use self::gather::{Gather, GatherInput, Prompt, SpeechTimeout};
+pub use self::hangup::Hangup;
 pub use self::message::Message;
 pub use self::play::{Digits, Play, Playable};
 pub use self::record::{Record, Transcribe};